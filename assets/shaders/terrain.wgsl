@@ -0,0 +1,17 @@
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    pbr_types::pbr_input_new,
+    pbr_functions as fns,
+}
+
+@group(2) @binding(0) var albedo_texture: texture_2d<f32>;
+@group(2) @binding(1) var albedo_sampler: sampler;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(albedo_texture, albedo_sampler, mesh.uv);
+
+	var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = color;
+    return fns::apply_pbr_lighting(pbr_input);
+}
@@ -0,0 +1,622 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use bevy::{
+    app::AppExit,
+    color::palettes::css::RED,
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+
+use crate::{
+    message::lan::LanServers,
+    shared::{
+        self,
+        console::{cvar::CVars, Console},
+        objects::player::name::{sanitize_name, MAX_NAME_LENGTH},
+    },
+};
+
+use super::{connect, leave_game, Connection, ConnectionError, LastSession, LoadState};
+
+const TITLE_SIZE: f32 = 60.0;
+const FONT_SIZE: f32 = 24.0;
+const BUTTON_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON_COLOR: Color = Color::srgb(0.35, 0.75, 0.35);
+const FOCUSED_FIELD_COLOR: Color = Color::srgb(0.3, 0.3, 0.45);
+
+/// Cvars that can be changed from the settings page, with the label shown
+/// for them, how much each click changes them, and their limits.
+const SETTINGS: [(&str, &str, f32, f32, f32); 2] = [
+    ("sensitivity", "Mouse sensitivity", 0.1, 0.1, 5.0),
+    ("fov", "Field of view", 5.0, 40.0, 110.0),
+];
+
+/// The main menu, and the screens shown while connecting and after the
+/// connection failed.
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConnectForm>()
+            .init_resource::<MenuPage>()
+            .init_resource::<MenuStatus>()
+            .add_systems(OnEnter(LoadState::Menu), show_main_page.after(leave_game))
+            .add_systems(OnEnter(LoadState::Connecting), spawn_loading_screen)
+            .add_systems(OnEnter(LoadState::Done), despawn_loading_screen)
+            .add_systems(
+                OnEnter(LoadState::Failed),
+                spawn_failed_screen.after(leave_game),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        type_in_field,
+                        draw_menu_page,
+                        draw_fields,
+                        draw_lan_servers,
+                        draw_settings,
+                        draw_status,
+                    )
+                        .chain()
+                        .run_if(in_state(LoadState::Menu)),
+                    color_buttons,
+                    press_buttons,
+                ),
+            );
+    }
+}
+
+/// What's typed into the main menu.
+#[derive(Resource)]
+struct ConnectForm {
+    address: String,
+    port: String,
+    name: String,
+    focused: Option<Field>,
+}
+
+impl Default for ConnectForm {
+    fn default() -> Self {
+        let (address, port) = shared::SERVER_ADDR.split_once(':').unwrap();
+        let name = std::env::var("PLAYER_NAME")
+            .ok()
+            .and_then(|name| sanitize_name(&name))
+            .unwrap_or_else(|| format!("player{}", rand::random::<u16>() % 10000));
+        Self {
+            address: address.to_string(),
+            port: port.to_string(),
+            name,
+            focused: None,
+        }
+    }
+}
+
+impl ConnectForm {
+    fn value_mut(&mut self, field: Field) -> &mut String {
+        match field {
+            Field::Address => &mut self.address,
+            Field::Port => &mut self.port,
+            Field::Name => &mut self.name,
+        }
+    }
+
+    fn value(&self, field: Field) -> &str {
+        match field {
+            Field::Address => &self.address,
+            Field::Port => &self.port,
+            Field::Name => &self.name,
+        }
+    }
+
+    fn server_addr(&self) -> Result<SocketAddr, String> {
+        let port: u16 = self
+            .port
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} isn't a port", self.port))?;
+        (self.address.trim(), port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.find(SocketAddr::is_ipv4))
+            .ok_or_else(|| format!("couldn't find {}", self.address))
+    }
+
+    fn player_name(&self) -> Result<String, String> {
+        sanitize_name(&self.name).ok_or_else(|| "enter a name".to_string())
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+enum Field {
+    Address,
+    Port,
+    Name,
+}
+
+impl Field {
+    const ALL: [Self; 3] = [Self::Address, Self::Port, Self::Name];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Address => "Address",
+            Self::Port => "Port",
+            Self::Name => "Name",
+        }
+    }
+
+    fn max_length(&self) -> usize {
+        match self {
+            Self::Address => 64,
+            Self::Port => 5,
+            Self::Name => MAX_NAME_LENGTH,
+        }
+    }
+}
+
+#[derive(Resource, Default, PartialEq, Eq, Clone, Copy)]
+enum MenuPage {
+    #[default]
+    Main,
+    Settings,
+}
+
+/// Shown under the main menu's buttons, e.g. when the address is invalid.
+#[derive(Resource, Default)]
+struct MenuStatus(String);
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Connect,
+    JoinLan(SocketAddr),
+    Settings,
+    Back,
+    Quit,
+    /// Changes a setting by a number of steps.
+    Adjust(usize, f32),
+    /// Stops connecting, or leaves the failed screen.
+    BackToMenu,
+    /// Connects to the last server again, taking back the player left there.
+    Reconnect,
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component)]
+struct FieldText;
+
+#[derive(Component)]
+struct LanList;
+
+#[derive(Component)]
+struct SettingText(usize);
+
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct LoadingScreen;
+
+fn text(value: impl Into<String>, font_size: f32) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size,
+            color: Color::srgb(0.9, 0.9, 0.9),
+            ..default()
+        },
+    )
+}
+
+fn spawn_button(parent: &mut ChildBuilder, label: &str, button: MenuButton, width: f32) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(40.0),
+                    margin: UiRect::all(Val::Px(4.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                border_radius: BorderRadius::all(Val::Px(6.0)),
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(text(label, FONT_SIZE));
+        });
+}
+
+/// A full screen column that's despawned when leaving `state`.
+fn spawn_screen(commands: &mut Commands, state: LoadState) -> Entity {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::srgb(0.05, 0.05, 0.08).into(),
+                ..default()
+            },
+            StateScoped(state),
+        ))
+        .id()
+}
+
+fn show_main_page(mut page: ResMut<MenuPage>, mut form: ResMut<ConnectForm>) {
+    *page = MenuPage::Main;
+    form.focused = None;
+    // Makes sure the page is drawn, even if it was already the main page.
+    page.set_changed();
+}
+
+/// Rebuilds the menu when switching between its pages.
+fn draw_menu_page(
+    page: Res<MenuPage>,
+    roots: Query<Entity, With<MenuRoot>>,
+    cvars: Res<CVars>,
+    mut commands: Commands,
+) {
+    if !page.is_changed() {
+        return;
+    }
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+    let root = spawn_screen(&mut commands, LoadState::Menu);
+    commands.entity(root).insert(MenuRoot);
+    commands.entity(root).with_children(|parent| match *page {
+        MenuPage::Main => {
+            parent.spawn(text("Main Menu", TITLE_SIZE));
+            for field in Field::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Px(4.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(text(field.label(), FONT_SIZE).with_style(Style {
+                            width: Val::Px(100.0),
+                            ..default()
+                        }));
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(300.0),
+                                        height: Val::Px(36.0),
+                                        padding: UiRect::horizontal(Val::Px(8.0)),
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    background_color: BUTTON_COLOR.into(),
+                                    ..default()
+                                },
+                                field,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((text("", FONT_SIZE), FieldText));
+                            });
+                    });
+            }
+            spawn_button(parent, "Connect", MenuButton::Connect, 300.0);
+            spawn_button(parent, "Settings", MenuButton::Settings, 300.0);
+            spawn_button(parent, "Quit", MenuButton::Quit, 300.0);
+            parent.spawn((text("", FONT_SIZE), StatusText));
+            parent.spawn(text("Local servers", FONT_SIZE).with_style(Style {
+                margin: UiRect::top(Val::Px(20.0)),
+                ..default()
+            }));
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+                LanList,
+            ));
+        }
+        MenuPage::Settings => {
+            parent.spawn(text("Settings", TITLE_SIZE));
+            for (i, (name, label, ..)) in SETTINGS.iter().enumerate() {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(text(*label, FONT_SIZE).with_style(Style {
+                            width: Val::Px(220.0),
+                            ..default()
+                        }));
+                        spawn_button(parent, "-", MenuButton::Adjust(i, -1.0), 40.0);
+                        let value = cvars.get::<f32>(name).unwrap_or_default();
+                        parent.spawn((
+                            text(format!("{value:.1}"), FONT_SIZE).with_style(Style {
+                                width: Val::Px(80.0),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            }),
+                            SettingText(i),
+                        ));
+                        spawn_button(parent, "+", MenuButton::Adjust(i, 1.0), 40.0);
+                    });
+            }
+            spawn_button(parent, "Back", MenuButton::Back, 300.0);
+        }
+    });
+}
+
+/// Types into the focused field. Tab moves to the next field and enter
+/// connects.
+fn type_in_field(
+    mut keys: EventReader<KeyboardInput>,
+    mut form: ResMut<ConnectForm>,
+    console: Res<Console>,
+    mut buttons: Query<(&mut Interaction, &MenuButton)>,
+) {
+    for key in keys.read() {
+        if key.state != ButtonState::Pressed || console.is_open() {
+            continue;
+        }
+        match &key.logical_key {
+            Key::Tab => {
+                let next = match form.focused {
+                    Some(field) => Field::ALL[(field as usize + 1) % Field::ALL.len()],
+                    None => Field::Address,
+                };
+                form.focused = Some(next);
+            }
+            Key::Enter => {
+                // Pressing the button keeps connecting in one place.
+                for (mut interaction, button) in buttons.iter_mut() {
+                    if matches!(button, MenuButton::Connect) {
+                        *interaction = Interaction::Pressed;
+                    }
+                }
+            }
+            key => {
+                let Some(field) = form.focused else {
+                    continue;
+                };
+                let value = form.value_mut(field);
+                match key {
+                    Key::Backspace => {
+                        value.pop();
+                    }
+                    Key::Character(text) if value.chars().count() < field.max_length() => {
+                        value.push_str(text);
+                    }
+                    Key::Space if field == Field::Name => value.push(' '),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn draw_fields(
+    form: Res<ConnectForm>,
+    mut texts: Query<&mut Text, With<FieldText>>,
+    fields: Query<(&Field, &Children)>,
+    mut backgrounds: Query<(&Field, &mut BackgroundColor)>,
+    added: Query<(), Added<FieldText>>,
+) {
+    if !form.is_changed() && added.is_empty() {
+        return;
+    }
+    for (field, children) in fields.iter() {
+        let cursor = if form.focused == Some(*field) {
+            "_"
+        } else {
+            ""
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = format!("{}{cursor}", form.value(*field));
+            }
+        }
+    }
+    for (field, mut background) in backgrounds.iter_mut() {
+        *background = if form.focused == Some(*field) {
+            FOCUSED_FIELD_COLOR
+        } else {
+            BUTTON_COLOR
+        }
+        .into();
+    }
+}
+
+fn draw_lan_servers(
+    lan: Res<LanServers>,
+    list: Query<Entity, With<LanList>>,
+    added: Query<(), Added<LanList>>,
+    mut commands: Commands,
+) {
+    if !lan.is_changed() && added.is_empty() {
+        return;
+    }
+    let Ok(list) = list.get_single() else {
+        return;
+    };
+    commands
+        .entity(list)
+        .despawn_descendants()
+        .with_children(|parent| {
+            let servers = lan.servers();
+            if !lan.is_listening() {
+                parent.spawn(text("Can't look for local servers", FONT_SIZE));
+            } else if servers.is_empty() {
+                parent.spawn(text("Looking for local servers...", FONT_SIZE));
+            }
+            for server in servers {
+                let label = format!(
+                    "{} - {} - {}/{}",
+                    server.addr, server.game_mode, server.players, server.max_players
+                );
+                spawn_button(parent, &label, MenuButton::JoinLan(server.addr), 500.0);
+            }
+        });
+}
+
+fn draw_settings(cvars: Res<CVars>, mut texts: Query<(&mut Text, &SettingText)>) {
+    if !cvars.is_changed() {
+        return;
+    }
+    for (mut text, setting) in texts.iter_mut() {
+        let value = cvars.get::<f32>(SETTINGS[setting.0].0).unwrap_or_default();
+        text.sections[0].value = format!("{value:.1}");
+    }
+}
+
+fn draw_status(
+    status: Res<MenuStatus>,
+    mut texts: Query<&mut Text, With<StatusText>>,
+    added: Query<(), Added<StatusText>>,
+) {
+    if !status.is_changed() && added.is_empty() {
+        return;
+    }
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = status.0.clone();
+        text.sections[0].style.color = RED.into();
+    }
+}
+
+fn color_buttons(
+    mut buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuButton>),
+    >,
+) {
+    for (interaction, mut background) in buttons.iter_mut() {
+        *background = match interaction {
+            Interaction::Pressed => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn press_buttons(
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    fields: Query<(&Interaction, &Field), Changed<Interaction>>,
+    mut form: ResMut<ConnectForm>,
+    mut page: ResMut<MenuPage>,
+    mut status: ResMut<MenuStatus>,
+    mut cvars: ResMut<CVars>,
+    last_session: Option<Res<LastSession>>,
+    mut load_state: ResMut<NextState<LoadState>>,
+    mut exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    for (interaction, field) in fields.iter() {
+        if *interaction == Interaction::Pressed {
+            form.focused = Some(*field);
+        }
+    }
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            MenuButton::Connect | MenuButton::JoinLan(_) => {
+                let addr = match button {
+                    MenuButton::JoinLan(addr) => Ok(*addr),
+                    _ => form.server_addr(),
+                };
+                match addr.and_then(|addr| Ok((addr, form.player_name()?))) {
+                    Ok((addr, name)) => {
+                        status.0.clear();
+                        let session = last_session.as_ref().and_then(|last| last.token_for(addr));
+                        connect(&mut commands, addr, &name, session);
+                        load_state.set(LoadState::Connecting);
+                    }
+                    Err(e) => status.0 = e,
+                }
+            }
+            MenuButton::Settings => *page = MenuPage::Settings,
+            MenuButton::Back => *page = MenuPage::Main,
+            MenuButton::Quit => {
+                exit.send(AppExit::Success);
+            }
+            MenuButton::Adjust(i, steps) => {
+                let (name, _, step, min, max) = SETTINGS[*i];
+                let value = cvars.get::<f32>(name).unwrap_or_default();
+                let value = (value + step * steps).clamp(min, max);
+                let _ = cvars.set(name, &value.to_string());
+            }
+            MenuButton::BackToMenu => load_state.set(LoadState::Menu),
+            MenuButton::Reconnect => {
+                if let Some(last) = &last_session {
+                    connect(&mut commands, last.addr, &last.name, Some(last.token));
+                    load_state.set(LoadState::Connecting);
+                }
+            }
+        }
+    }
+}
+
+fn spawn_loading_screen(connection: Res<Connection>, mut commands: Commands) {
+    let screen = spawn_screen(&mut commands, LoadState::Connecting);
+    // Loading goes through a few states, so this screen is despawned by hand.
+    commands
+        .entity(screen)
+        .remove::<StateScoped<LoadState>>()
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent.spawn(text(
+                format!("Connecting to {}...", connection.addr),
+                FONT_SIZE,
+            ));
+            spawn_button(parent, "Cancel", MenuButton::BackToMenu, 300.0);
+        });
+}
+
+fn despawn_loading_screen(screens: Query<Entity, With<LoadingScreen>>, mut commands: Commands) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn spawn_failed_screen(
+    error: Option<Res<ConnectionError>>,
+    last_session: Option<Res<LastSession>>,
+    mut commands: Commands,
+) {
+    let message = error.map_or("Disconnected".to_string(), |error| error.0.clone());
+    let screen = spawn_screen(&mut commands, LoadState::Failed);
+    commands.entity(screen).with_children(|parent| {
+        parent.spawn(text(message, FONT_SIZE));
+        if last_session.is_some() {
+            spawn_button(parent, "Reconnect", MenuButton::Reconnect, 300.0);
+        }
+        spawn_button(parent, "Back to menu", MenuButton::BackToMenu, 300.0);
+    });
+}
@@ -0,0 +1,369 @@
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy_renet::renet::transport::{ClientAuthentication, NetcodeClientTransport};
+use bevy_renet::renet::{ConnectionConfig, DefaultChannel, RenetClient};
+use bevy_renet::transport::NetcodeClientPlugin;
+use bevy_renet::RenetClientPlugin;
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime};
+
+use crate::message::client::{ConnectData, MessageReaderOnClient, ReliableMessageFromClient};
+use crate::message::lan::LanBrowserPlugin;
+use crate::message::server::{ReliableMessageFromServer, SessionToken};
+use crate::message::MessagesAvailable;
+use crate::shared::game_mode::GameModeState;
+use crate::shared::objects::player::client::InputBuffer;
+use crate::shared::objects::player::movement::MovementProfile;
+use crate::shared::objects::player::spawn::PlayerSpawnRequest;
+use crate::shared::objects::player::LocalPlayer;
+use crate::shared::proc::edits::ReceivedTerrainEdits;
+use crate::shared::proc::terrain::Terrain;
+use crate::shared::proc::tree::FelledTrees;
+use crate::shared::proc::{ChunkGeneration, WorldSeed};
+use crate::shared::tick::{get_client_tick, Tick};
+use crate::shared::{AppState, Persistent};
+use crate::ui::UIPlugin;
+use crate::utils::toggle_cursor_grab_with_esc;
+use crate::{message, shared};
+
+mod menu;
+
+/// Giving up on a server that doesn't answer takes this long.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
+enum LoadState {
+    Menu,
+    Connecting,
+    LocalLoaded,
+    RemoteLoading,
+    Done,
+    /// The connection failed or was lost. `ConnectionError` says why.
+    Failed,
+}
+
+pub fn run() {
+    let is_server = false;
+    App::new()
+        .add_plugins((DefaultPlugins, Client, UIPlugin { is_server }))
+        .insert_state(LoadState::Menu)
+        .enable_state_scoped_entities::<LoadState>()
+        .add_plugins((menu::MenuPlugin, LanBrowserPlugin))
+        .init_resource::<ServerInfoReceived>()
+        .add_systems(Startup, spawn_ui_camera)
+        .add_systems(OnEnter(LoadState::Connecting), load_local)
+        .add_systems(OnEnter(LoadState::RemoteLoading), reset_server_info)
+        .add_systems(
+            OnEnter(LoadState::Menu),
+            leave_game.run_if(resource_exists::<RenetClient>),
+        )
+        .add_systems(
+            OnEnter(LoadState::Failed),
+            leave_game.run_if(resource_exists::<RenetClient>),
+        )
+        .add_systems(OnExit(AppState::InGame), clear_game)
+        .add_systems(
+            FixedUpdate,
+            (
+                check_connection.run_if(resource_exists::<Connection>),
+                send_ready.run_if(in_state(LoadState::LocalLoaded)),
+                set_local_player.run_if(in_state(LoadState::RemoteLoading)),
+            )
+                .in_set(MessagesAvailable),
+        )
+        .add_systems(
+            Update,
+            toggle_cursor_grab_with_esc.run_if(in_state(LoadState::Done)),
+        )
+        .insert_state(shared::AppState::MainMenu)
+        .add_plugins((
+            shared::Game { is_server },
+            shared::tick::TickPlugin { is_server },
+            shared::game_mode::GameModePlugin { is_server },
+        ))
+        .add_plugins(message::client::ClientMessagePlugin {
+            latency: Some(0.2),
+            message_loss: None,
+        })
+        .run();
+}
+
+struct Client;
+
+impl Plugin for Client {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RenetClientPlugin);
+        app.add_plugins(NetcodeClientPlugin);
+    }
+}
+
+/// Renders the menus while there's no player camera.
+#[derive(Component)]
+struct UICamera;
+
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn((UICamera, Camera3dBundle::default()));
+}
+
+/// The server being connected to or played on.
+#[derive(Resource)]
+struct Connection {
+    addr: SocketAddr,
+    name: String,
+    /// Real time when connecting started.
+    started: Duration,
+}
+
+/// The last server joined, kept after disconnecting so that the player left
+/// behind on it can be taken back.
+#[derive(Resource)]
+struct LastSession {
+    addr: SocketAddr,
+    name: String,
+    token: SessionToken,
+}
+
+impl LastSession {
+    /// The token to connect to `addr` with, if it's the same server.
+    fn token_for(&self, addr: SocketAddr) -> Option<SessionToken> {
+        (self.addr == addr).then_some(self.token)
+    }
+}
+
+/// Why the connection failed, shown on the failed screen.
+#[derive(Resource)]
+struct ConnectionError(String);
+
+/// Starts connecting to a server. The state should be set to
+/// `LoadState::Connecting` after calling this.
+fn connect(commands: &mut Commands, addr: SocketAddr, name: &str, session: Option<SessionToken>) {
+    let client = RenetClient::new(ConnectionConfig::default());
+    commands.insert_resource(client);
+    let client_id: u64 = rand::random();
+    println!("client id: {client_id}");
+    let authentication = ClientAuthentication::Unsecure {
+        server_addr: addr,
+        client_id,
+        user_data: Some(
+            ConnectData {
+                name: name.to_string(),
+                session,
+            }
+            .to_user_data(),
+        ),
+        protocol_id: 0,
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+    commands.insert_resource(transport);
+    let name = name.to_string();
+    commands.add(move |world: &mut World| {
+        let started = world.resource::<Time<Real>>().elapsed();
+        println!("connecting to {addr} as {name}");
+        world.insert_resource(Connection {
+            addr,
+            name,
+            started,
+        });
+        world.remove_resource::<ConnectionError>();
+    });
+}
+
+/// Fails the connection if the server kicked us, the connection was lost, or
+/// the server didn't answer in time.
+fn check_connection(
+    client: Option<Res<RenetClient>>,
+    transport: Option<Res<NetcodeClientTransport>>,
+    reader: Res<MessageReaderOnClient>,
+    connection: Res<Connection>,
+    time: Res<Time<Real>>,
+    load_state: Res<State<LoadState>>,
+    mut next_load_state: ResMut<NextState<LoadState>>,
+    mut commands: Commands,
+) {
+    if *load_state.get() == LoadState::Failed {
+        return;
+    }
+    let kicked = reader.reliable_messages().find_map(|msg| match msg {
+        ReliableMessageFromServer::Kicked(reason) => Some(format!("Kicked: {reason}")),
+        _ => None,
+    });
+    let error = kicked.or_else(|| {
+        let client = client.as_ref()?;
+        if client.is_disconnected() {
+            let reason = transport
+                .as_ref()
+                .and_then(|transport| transport.disconnect_reason())
+                .map(|reason| format!("{reason:?}"))
+                .or_else(|| {
+                    client
+                        .disconnect_reason()
+                        .map(|reason| format!("{reason:?}"))
+                })
+                .unwrap_or_else(|| "unknown reason".to_string());
+            Some(format!("Disconnected: {reason}"))
+        } else if !client.is_connected() && time.elapsed() - connection.started > CONNECT_TIMEOUT {
+            Some(format!("{} didn't answer", connection.addr))
+        } else {
+            None
+        }
+    });
+    if let Some(error) = error {
+        println!("{error}");
+        commands.insert_resource(ConnectionError(error));
+        next_load_state.set(LoadState::Failed);
+    }
+}
+
+/// Disconnects, going back to the menu. The world is thrown away once the
+/// game systems stop running, as they expect the client to be there.
+fn leave_game(world: &mut World) {
+    if let Some(mut transport) = world.get_resource_mut::<NetcodeClientTransport>() {
+        transport.disconnect();
+    }
+    if *world.resource::<State<AppState>>() == AppState::InGame {
+        world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::MainMenu);
+    } else {
+        clear_game(world);
+    }
+}
+
+/// Removes the client and everything it loaded. Screens of the current
+/// `LoadState` are kept.
+fn clear_game(world: &mut World) {
+    world.remove_resource::<NetcodeClientTransport>();
+    world.remove_resource::<RenetClient>();
+    world.remove_resource::<Connection>();
+    world.remove_resource::<Terrain>();
+    world.remove_resource::<WorldSeed>();
+    world.remove_resource::<MovementProfile>();
+    world.remove_resource::<LocalPlayer>();
+    world.remove_resource::<Tick>();
+    world.remove_resource::<GameModeState>();
+    world.insert_resource(ChunkGeneration::default());
+    world.insert_resource(FelledTrees::default());
+    world.insert_resource(ReceivedTerrainEdits::default());
+    world.insert_resource(InputBuffer::default());
+    world.resource_mut::<MessageReaderOnClient>().clear();
+
+    let roots: Vec<Entity> = world
+        .query_filtered::<Entity, (
+            Without<Parent>,
+            Without<Window>,
+            Without<Persistent>,
+            Without<StateScoped<LoadState>>,
+        )>()
+        .iter(world)
+        .collect();
+    for root in roots {
+        despawn_with_children_recursive(world, root);
+    }
+    let mut windows = world.query_filtered::<&mut Window, With<PrimaryWindow>>();
+    if let Ok(mut window) = windows.get_single_mut(world) {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+    world.spawn((UICamera, Camera3dBundle::default()));
+    println!("left the game");
+}
+
+fn load_local(world: &mut World) {
+    world.resource_scope(
+        |world: &mut World, mut load_state: Mut<NextState<LoadState>>| {
+            shared::scenes::setup_scene_1(world);
+            load_state.set(LoadState::LocalLoaded);
+            println!("loaded local");
+        },
+    )
+}
+
+fn send_ready(mut load_state: ResMut<NextState<LoadState>>, client: Option<ResMut<RenetClient>>) {
+    let Some(mut client) = client else {
+        return;
+    };
+    if client.is_connected() {
+        let message = ReliableMessageFromClient::Connected;
+        println!("connected");
+        let bytes = bincode::serialize(&message).unwrap();
+        client.send_message(DefaultChannel::ReliableUnordered, bytes);
+        load_state.set(LoadState::RemoteLoading);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ServerInfoReceived {
+    set_player_obj: bool,
+    tick: bool,
+}
+
+impl ServerInfoReceived {
+    fn all(&self) -> bool {
+        self.set_player_obj && self.tick
+    }
+}
+
+fn reset_server_info(mut server_info: ResMut<ServerInfoReceived>) {
+    *server_info = ServerInfoReceived::default();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn set_local_player(
+    mut commands: Commands,
+    reader: Res<MessageReaderOnClient>,
+    client: Option<ResMut<RenetClient>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut load_state: ResMut<NextState<LoadState>>,
+    mut server_info: ResMut<ServerInfoReceived>,
+    connection: Res<Connection>,
+    ui_camera: Query<Entity, With<UICamera>>,
+    mut player_spawn_reqs: EventWriter<PlayerSpawnRequest>,
+) {
+    let Some(mut client) = client else {
+        return;
+    };
+    for msg in reader.reliable_messages() {
+        match msg {
+            ReliableMessageFromServer::InitPlayer(player_info) => {
+                server_info.set_player_obj = true;
+                commands.insert_resource(LastSession {
+                    addr: connection.addr,
+                    name: connection.name.clone(),
+                    token: player_info.session,
+                });
+                commands.insert_resource(WorldSeed(player_info.seed));
+                commands.insert_resource(player_info.movement.clone());
+                commands.insert_resource(Terrain::new(player_info.seed));
+                commands.insert_resource(LocalPlayer(player_info.net_obj.clone()));
+                player_spawn_reqs.send(PlayerSpawnRequest::Local(
+                    player_info.transform,
+                    player_info.net_obj.clone(),
+                    player_info.tick.clone(),
+                    player_info.team,
+                ));
+            }
+            ReliableMessageFromServer::TickSync(sync) => {
+                let tick = get_client_tick(sync.tick, sync.unix_millis);
+                commands.insert_resource(tick);
+                server_info.tick = true;
+            }
+            _ => {}
+        }
+    }
+
+    if server_info.all() {
+        let message = ReliableMessageFromClient::ReadyForUpdates;
+        let bytes = bincode::serialize(&message).unwrap();
+        client.send_message(DefaultChannel::ReliableUnordered, bytes);
+        app_state.set(AppState::InGame);
+        load_state.set(LoadState::Done);
+        for camera in ui_camera.iter() {
+            commands.entity(camera).despawn_recursive();
+        }
+    }
+}
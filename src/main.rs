@@ -16,7 +16,7 @@ fn main() {
     let mut args = env::args();
     args.next();
     match args.next().as_ref().map(|v| v.as_str()) {
-        Some("server") => server::run(),
+        Some("server") => server::run(args.next()),
         Some("client") => client::run(),
         Some("proc") => proc::run(),
         Some("test") => test::run(),
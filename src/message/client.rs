@@ -1,11 +1,20 @@
-use crate::shared::{objects::player, GameLogic};
+use crate::shared::{
+    chat::ChatMessage,
+    console::{
+        command::{CommandOutput, RemoteCommand, RunCommand},
+        cvar::{CVarValue, CVars},
+        ConsoleAppExt, ConsoleCommand,
+    },
+    objects::player,
+    GameLogic,
+};
 
 use super::{
-    server::{ReliableMessageFromServer, UnreliableMessageFromServer},
+    server::{ReliableMessageFromServer, SessionToken, UnreliableMessageFromServer},
     MessageSet, MessagesAvailable,
 };
 use bevy::prelude::*;
-use bevy_renet::renet::{DefaultChannel, RenetClient};
+use bevy_renet::renet::{transport::NETCODE_USER_DATA_BYTES, DefaultChannel, RenetClient};
 use serde::{Deserialize, Serialize};
 
 fn should_drop(message_loss: Option<f64>) -> bool {
@@ -38,6 +47,24 @@ impl MessageReaderOnClient {
         }
     }
 
+    /// Simulated latency in seconds, `None` to deliver messages right away.
+    pub fn set_latency(&mut self, latency: Option<f64>) {
+        self.latency = latency;
+    }
+
+    /// Fraction of unreliable messages to drop.
+    pub fn set_message_loss(&mut self, message_loss: Option<f64>) {
+        self.message_loss = message_loss;
+    }
+
+    /// Drops every message, e.g. after disconnecting.
+    pub fn clear(&mut self) {
+        self.pending_reliable_messages.clear();
+        self.pending_unreliable_messages.clear();
+        self.reliable_messages.clear();
+        self.unreliable_messages.clear();
+    }
+
     pub fn reliable_messages(&self) -> &[ReliableMessageFromServer] {
         self.reliable_messages.as_slice()
     }
@@ -45,6 +72,11 @@ impl MessageReaderOnClient {
     pub fn unreliable_messages(&self) -> &[UnreliableMessageFromServer] {
         self.unreliable_messages.as_slice()
     }
+
+    #[cfg(test)]
+    pub fn push_reliable(&mut self, message: ReliableMessageFromServer) {
+        self.reliable_messages.push(message);
+    }
 }
 
 pub struct ClientMessagePlugin {
@@ -59,7 +91,25 @@ impl Plugin for ClientMessagePlugin {
                 FixedUpdate,
                 read_messages_from_server.in_set(MessageSet::Read),
             )
-            .add_systems(FixedUpdate, clear_messages.in_set(MessageSet::Clear));
+            .add_systems(FixedUpdate, clear_messages.in_set(MessageSet::Clear))
+            .add_systems(Update, (run_net_sim_command, apply_net_sim).chain());
+        app.add_cvar(
+            "net_sim_latency",
+            CVarValue::Float(self.latency.unwrap_or(0.0) as f32 * 1000.0),
+            "Simulated latency of messages from the server, in milliseconds.",
+        )
+        .add_cvar(
+            "net_sim_loss",
+            CVarValue::Float(self.message_loss.unwrap_or(0.0) as f32),
+            "Fraction of unreliable messages from the server to drop.",
+        )
+        .add_console_command(
+            ConsoleCommand::new(
+                "net_sim",
+                "Shows or changes the simulated network conditions.",
+            )
+            .usage("[latency <ms> | loss <fraction>]"),
+        );
         app.configure_sets(
             FixedUpdate,
             (
@@ -83,14 +133,18 @@ fn read_messages_from_server(
     };
     let current_time = time.elapsed_seconds_f64();
 
-    for i in (0..message_reader.pending_reliable_messages.len()).rev() {
-        if message_reader.pending_reliable_messages[i].delivery_time <= current_time {
-            let pending_message = message_reader.pending_reliable_messages.swap_remove(i);
-            message_reader
-                .reliable_messages
-                .push(pending_message.message);
-        }
-    }
+    // Kept in the order they arrived in, since some reliable messages are
+    // sent on an ordered channel.
+    let (ready, pending): (Vec<_>, Vec<_>) =
+        std::mem::take(&mut message_reader.pending_reliable_messages)
+            .into_iter()
+            .partition(|pending_message| pending_message.delivery_time <= current_time);
+    message_reader.pending_reliable_messages = pending;
+    message_reader.reliable_messages.extend(
+        ready
+            .into_iter()
+            .map(|pending_message| pending_message.message),
+    );
 
     // Process unreliable messages
     for i in (0..message_reader.pending_unreliable_messages.len()).rev() {
@@ -102,24 +156,30 @@ fn read_messages_from_server(
         }
     }
 
-    while let Some(message) = client.receive_message(DefaultChannel::ReliableUnordered) {
-        if let Ok(parsed_message) = bincode::deserialize::<ReliableMessageFromServer>(&message) {
-            match message_reader.latency {
-                Some(latency) => {
-                    let delivery_time = current_time + latency;
-                    message_reader
-                        .pending_reliable_messages
-                        .push(PendingMessage {
-                            delivery_time,
-                            message: parsed_message,
-                        });
-                }
-                None => {
-                    message_reader.reliable_messages.push(parsed_message);
-                }
-            };
-        } else {
-            error!("Failed to deserialize message from server");
+    for channel in [
+        DefaultChannel::ReliableOrdered,
+        DefaultChannel::ReliableUnordered,
+    ] {
+        while let Some(message) = client.receive_message(channel) {
+            if let Ok(parsed_message) = bincode::deserialize::<ReliableMessageFromServer>(&message)
+            {
+                match message_reader.latency {
+                    Some(latency) => {
+                        let delivery_time = current_time + latency;
+                        message_reader
+                            .pending_reliable_messages
+                            .push(PendingMessage {
+                                delivery_time,
+                                message: parsed_message,
+                            });
+                    }
+                    None => {
+                        message_reader.reliable_messages.push(parsed_message);
+                    }
+                };
+            } else {
+                error!("Failed to deserialize message from server");
+            }
         }
     }
 
@@ -148,15 +208,81 @@ fn read_messages_from_server(
     }
 }
 
+fn run_net_sim_command(
+    mut run: EventReader<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+    mut cvars: ResMut<CVars>,
+) {
+    for command in run.read().filter(|command| command.is("net_sim")) {
+        let reply = match command.args.as_slice() {
+            [] => Ok(()),
+            [setting, value] if setting == "latency" => {
+                cvars.set("net_sim_latency", value).map(|_| ())
+            }
+            [setting, value] if setting == "loss" => cvars.set("net_sim_loss", value).map(|_| ()),
+            _ => Err("usage: net_sim [latency <ms> | loss <fraction>]".to_string()),
+        };
+        output.send(match reply {
+            Ok(()) => command.reply(format!(
+                "latency {}ms, loss {}",
+                cvars.get::<f32>("net_sim_latency").unwrap_or_default(),
+                cvars.get::<f32>("net_sim_loss").unwrap_or_default(),
+            )),
+            Err(text) => command.error(text),
+        });
+    }
+}
+
+/// Keeps the simulated network conditions in sync with their cvars.
+fn apply_net_sim(cvars: Res<CVars>, mut message_reader: ResMut<MessageReaderOnClient>) {
+    if !cvars.is_changed() {
+        return;
+    }
+    let latency = cvars.get::<f32>("net_sim_latency").unwrap_or_default();
+    let loss = cvars.get::<f32>("net_sim_loss").unwrap_or_default();
+    message_reader.set_latency((latency > 0.0).then_some(latency as f64 / 1000.0));
+    message_reader.set_message_loss((loss > 0.0).then_some(loss as f64));
+}
+
 fn clear_messages(mut message_reader: ResMut<MessageReaderOnClient>) {
     message_reader.reliable_messages.clear();
     message_reader.unreliable_messages.clear();
 }
 
+/// Sent as the user data when connecting, so the server knows who's joining
+/// before any messages arrive.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConnectData {
+    pub name: String,
+    /// The session from a previous connection to this server, to take back
+    /// the player that was left behind.
+    pub session: Option<SessionToken>,
+}
+
+impl ConnectData {
+    pub fn to_user_data(&self) -> [u8; NETCODE_USER_DATA_BYTES] {
+        let mut data = [0; NETCODE_USER_DATA_BYTES];
+        let bytes = bincode::serialize(self).unwrap();
+        // Names are short, so this always fits.
+        data[..bytes.len()].copy_from_slice(&bytes);
+        data
+    }
+
+    pub fn from_user_data(data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<Self> {
+        bincode::deserialize(data).ok()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum ReliableMessageFromClient {
     Connected,
     ReadyForUpdates,
+    /// Asks which trees of a chunk were cut down. Sent when a chunk is first
+    /// loaded.
+    RequestChunkDiff(IVec2),
+    /// A console command that needs the server to run it.
+    ConsoleCommand(RemoteCommand),
+    Chat(ChatMessage),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
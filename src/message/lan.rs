@@ -0,0 +1,169 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::game_mode::ActiveGameMode;
+
+/// Servers announce themselves to this port on the local network.
+pub const LAN_DISCOVERY_PORT: u16 = 5001;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Servers that haven't announced themselves for this long are forgotten.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Broadcast by servers over UDP so that clients on the same network can list
+/// them without knowing their address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LanAnnouncement {
+    /// The address clients connect to. Clients replace its IP with the one
+    /// the announcement came from, since the server may only know itself by
+    /// an unspecified or loopback address.
+    pub addr: SocketAddr,
+    pub game_mode: String,
+    pub players: usize,
+    pub max_players: usize,
+}
+
+/// Announces the server on the local network.
+pub struct LanAnnouncePlugin {
+    pub addr: SocketAddr,
+    pub max_players: usize,
+}
+
+impl Plugin for LanAnnouncePlugin {
+    fn build(&self, app: &mut App) {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .and_then(|socket| socket.set_broadcast(true).map(|_| socket));
+        match socket {
+            Ok(socket) => {
+                app.insert_resource(LanAnnouncer {
+                    socket,
+                    addr: self.addr,
+                    max_players: self.max_players,
+                    timer: Timer::new(ANNOUNCE_INTERVAL, TimerMode::Repeating),
+                });
+                app.add_systems(Update, announce);
+            }
+            Err(e) => warn!("not announcing the server on the local network: {e}"),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct LanAnnouncer {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    max_players: usize,
+    timer: Timer,
+}
+
+fn announce(
+    mut announcer: ResMut<LanAnnouncer>,
+    server: Res<RenetServer>,
+    game_mode: Option<Res<ActiveGameMode>>,
+    time: Res<Time>,
+) {
+    if !announcer.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let announcement = LanAnnouncement {
+        addr: announcer.addr,
+        game_mode: game_mode
+            .map_or("", |mode| mode.state().kind.name())
+            .to_string(),
+        players: server.connected_clients(),
+        max_players: announcer.max_players,
+    };
+    let bytes = bincode::serialize(&announcement).unwrap();
+    let broadcast = (Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT);
+    if let Err(e) = announcer.socket.send_to(&bytes, broadcast) {
+        warn!("failed to announce the server: {e}");
+    }
+}
+
+/// Listens for servers announcing themselves on the local network.
+pub struct LanBrowserPlugin;
+
+impl Plugin for LanBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LanServers::bind());
+        app.add_systems(Update, listen_for_servers);
+    }
+}
+
+/// Servers found on the local network, by address.
+#[derive(Resource)]
+pub struct LanServers {
+    /// `None` if the discovery port couldn't be bound, e.g. because another
+    /// client on this machine already has it.
+    socket: Option<UdpSocket>,
+    servers: HashMap<SocketAddr, (LanAnnouncement, Duration)>,
+}
+
+impl LanServers {
+    fn bind() -> Self {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT))
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+        if let Err(e) = &socket {
+            warn!("can't look for servers on the local network: {e}");
+        }
+        Self {
+            socket: socket.ok(),
+            servers: HashMap::new(),
+        }
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Sorted by address.
+    pub fn servers(&self) -> Vec<&LanAnnouncement> {
+        let mut servers: Vec<_> = self.servers.values().map(|(server, _)| server).collect();
+        servers.sort_by_key(|server| server.addr);
+        servers
+    }
+}
+
+fn listen_for_servers(mut lan: ResMut<LanServers>, time: Res<Time>) {
+    let now = time.elapsed();
+    let mut buf = [0; 512];
+    let mut found = Vec::new();
+    if let Some(socket) = &lan.socket {
+        while let Ok((len, source)) = socket.recv_from(&mut buf) {
+            if let Ok(mut announcement) = bincode::deserialize::<LanAnnouncement>(&buf[..len]) {
+                announcement.addr.set_ip(source.ip());
+                found.push(announcement);
+            }
+        }
+    }
+    for announcement in found {
+        let changed = lan
+            .servers
+            .get(&announcement.addr)
+            .map_or(true, |(known, _)| *known != announcement);
+        // Only updating servers that changed keeps the menu from being
+        // rebuilt every time one announces itself.
+        if changed {
+            lan.servers.insert(announcement.addr, (announcement, now));
+        } else if let Some((_, last_seen)) = lan
+            .bypass_change_detection()
+            .servers
+            .get_mut(&announcement.addr)
+        {
+            *last_seen = now;
+        }
+    }
+    let expired = lan
+        .servers
+        .values()
+        .any(|(_, last_seen)| now - *last_seen > SERVER_TIMEOUT);
+    if expired {
+        lan.servers
+            .retain(|_, (_, last_seen)| now - *last_seen <= SERVER_TIMEOUT);
+    }
+}
@@ -10,5 +10,6 @@ pub enum MessageSet {
 pub struct MessagesAvailable;
 
 pub mod client;
+pub mod lan;
 pub mod server;
 pub mod spawn;
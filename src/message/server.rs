@@ -5,10 +5,17 @@ use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer};
 use serde::{Deserialize, Serialize};
 
 use crate::shared::{
+    chat::ChatBroadcast,
+    console::command::RemoteOutput,
+    game_mode::{GameModeState, Team},
     objects::{
-        player::{PlayerKinematics, Shot},
+        player::{movement::MovementProfile, PlayerKinematics, Shot},
         NetworkObject,
     },
+    proc::{
+        edits::TerrainEdit,
+        tree::{ChunkDiff, TreeFelled},
+    },
     tick::Tick,
     GameLogic,
 };
@@ -37,6 +44,25 @@ pub struct PlayerInit {
     pub net_obj: NetworkObject,
     pub transform: Transform,
     pub tick: Tick,
+    pub team: Option<Team>,
+    /// Seed to generate the world from.
+    pub seed: u64,
+    /// How players move on this server, needed to predict movement.
+    pub movement: MovementProfile,
+    /// Lets the client take this player back if it reconnects soon after
+    /// losing its connection.
+    pub session: SessionToken,
+}
+
+/// Issued to each client when it joins. A client that comes back with it
+/// within the grace period gets its old player back.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken(pub u64);
+
+impl SessionToken {
+    pub fn new_rand() -> Self {
+        Self(rand::random())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,6 +79,17 @@ pub enum ReliableMessageFromServer {
     InitPlayer(PlayerInit),
     TickSync(TickSync),
     HealthSync(HealthSync),
+    GameModeSync(GameModeState),
+    TreeFelled(TreeFelled),
+    /// Answers `ReliableMessageFromClient::RequestChunkDiff`.
+    ChunkDiff(ChunkDiff),
+    /// Edits numbered from the index of the first one in the server's log.
+    TerrainEdits(usize, Vec<TerrainEdit>),
+    /// Answers `ReliableMessageFromClient::ConsoleCommand`.
+    ConsoleOutput(RemoteOutput),
+    Chat(ChatBroadcast),
+    /// Sent right before the server disconnects a client, with the reason.
+    Kicked(String),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -63,6 +100,7 @@ pub struct PlayerPositionSync {
     pub body_rotation: f32,
     /// Euler radian rotation of player head about Vec3::X
     pub head_rotation: f32,
+    pub crouching: bool,
     pub tick: Tick,
 }
 
@@ -76,6 +114,17 @@ pub struct OwnedPlayerSync {
     pub jump_cooldown_elapsed: Duration,
 }
 
+/// The state of a physics prop, simulated on the server.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhysicsPropSync {
+    pub net_obj: NetworkObject,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+    pub tick: Tick,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum UnreliableMessageFromServer {
     TransformSync(NetworkObject, Transform, Tick),
@@ -84,6 +133,7 @@ pub enum UnreliableMessageFromServer {
     OwnedPlayerSync(OwnedPlayerSync),
     /// PlayerShot has structure of (Shooter, Shot)
     PlayerShot(NetworkObject, Shot),
+    PhysicsPropSync(PhysicsPropSync),
 }
 
 #[derive(Resource)]
@@ -1,8 +1,64 @@
-use bevy::prelude::*;
+use bevy::{ecs::world::EntityRef, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use crate::shared::{
+    game_mode::Team,
+    objects::{physics_prop::PhysicsPropKind, player::name::PlayerName},
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlayerSpawn {
+    pub transform: Transform,
+    pub team: Option<Team>,
+    pub name: PlayerName,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WormSpawn {
+    pub transform: Transform,
+    pub max_health: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhysicsPropSpawn {
+    pub kind: PhysicsPropKind,
+    pub transform: Transform,
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum NetworkSpawn {
-    Player(Transform),
-    Ball(Transform),
+    Player(PlayerSpawn),
+    PhysicsProp(PhysicsPropSpawn),
+    Worm(WormSpawn),
+}
+
+/// Builds the `NetworkSpawn` that recreates an entity on clients from its
+/// current components. Returns `None` for entities it doesn't handle.
+pub type SpawnSerializer = fn(EntityRef) -> Option<NetworkSpawn>;
+
+/// How each kind of replicated object is sent to clients, registered by the
+/// plugin that owns it.
+#[derive(Resource, Default)]
+pub struct SpawnSerializers(Vec<SpawnSerializer>);
+
+impl SpawnSerializers {
+    pub fn serialize(&self, entity: EntityRef) -> Option<NetworkSpawn> {
+        self.0.iter().find_map(|serializer| serializer(entity))
+    }
+}
+
+pub trait SpawnAppExt {
+    fn add_spawn_serializer(&mut self, serializer: SpawnSerializer) -> &mut Self;
+}
+
+impl SpawnAppExt for App {
+    fn add_spawn_serializer(&mut self, serializer: SpawnSerializer) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(SpawnSerializers::default)
+            .0
+            .push(serializer);
+        self
+    }
 }
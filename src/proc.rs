@@ -1,5 +1,6 @@
 use std::f32::consts::PI;
 
+use bevy::core_pipeline::prepass::DepthPrepass;
 use bevy::dev_tools::fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin};
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
@@ -7,7 +8,8 @@ use bevy::window::{CursorGrabMode, PrimaryWindow};
 use bevy_rapier3d::prelude::*;
 
 use crate::shared::proc::chunk::Chunk;
-use crate::shared::proc::grassy_desert::{GrassyDesertBiomeData, GrassyDesertTerrain};
+use crate::shared::proc::navmesh::{NavMesh, NavMeshPlugin};
+use crate::shared::proc::terrain::{Terrain, TerrainChunkData};
 use crate::shared::proc::{LoadsChunks, TerrainPlugin};
 use crate::utils::toggle_cursor_grab_with_esc;
 
@@ -16,6 +18,7 @@ pub fn run() {
         .add_plugins((
             DefaultPlugins,
             TerrainPlugin,
+            NavMeshPlugin,
             FpsOverlayPlugin {
                 config: FpsOverlayConfig {
                     text_config: TextStyle {
@@ -30,6 +33,7 @@ pub fn run() {
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_schedule(FixedPostUpdate))
         .add_plugins(RapierDebugRenderPlugin::default())
         .init_resource::<DebugGizmos>()
+        .init_resource::<NavDebug>()
         .add_systems(Startup, setup)
         .add_systems(
             Update,
@@ -39,6 +43,8 @@ pub fn run() {
                 toggle_cursor_grab_with_esc,
                 draw_gizmos,
                 toggle_debug_ui,
+                set_nav_debug_points,
+                draw_nav_gizmos,
             ),
         )
         .run();
@@ -68,7 +74,7 @@ fn toggle_debug_ui(
 }
 
 fn setup(mut commands: Commands) {
-    let terrain = GrassyDesertTerrain::new();
+    let terrain = Terrain::new(rand::random());
     commands.insert_resource(terrain);
 
     commands.spawn(DirectionalLightBundle {
@@ -102,6 +108,7 @@ fn setup(mut commands: Commands) {
                 walk_speed: 10.0,
             },
             LoadsChunks,
+            DepthPrepass,
         ))
         .insert(RigidBody::KinematicPositionBased)
         .insert(Collider::ball(0.5))
@@ -113,8 +120,8 @@ fn setup(mut commands: Commands) {
 
 fn draw_gizmos(
     mut gizmos: Gizmos,
-    query: Query<&Chunk<GrassyDesertBiomeData>>,
-    terrain: Res<GrassyDesertTerrain>,
+    query: Query<&Chunk<TerrainChunkData>>,
+    terrain: Res<Terrain>,
     debug: Res<DebugGizmos>,
 ) {
     if !debug.0 {
@@ -126,6 +133,50 @@ fn draw_gizmos(
     }
 }
 
+/// Ends of a test path, placed with N (start) and M (goal) at the camera.
+#[derive(Resource, Default)]
+struct NavDebug {
+    start: Option<Vec3>,
+    goal: Option<Vec3>,
+}
+
+fn set_nav_debug_points(
+    keys: Res<ButtonInput<KeyCode>>,
+    camera: Query<&Transform, With<FreeCamera>>,
+    mut nav_debug: ResMut<NavDebug>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::KeyN) {
+        nav_debug.start = Some(camera.translation);
+    }
+    if keys.just_pressed(KeyCode::KeyM) {
+        nav_debug.goal = Some(camera.translation);
+    }
+}
+
+fn draw_nav_gizmos(
+    mut gizmos: Gizmos,
+    navmesh: Res<NavMesh>,
+    nav_debug: Res<NavDebug>,
+    camera: Query<&Transform, With<FreeCamera>>,
+    debug: Res<DebugGizmos>,
+) {
+    if !debug.0 {
+        return;
+    }
+    if let Ok(camera) = camera.get_single() {
+        navmesh.draw_gizmos(&mut gizmos, camera.translation, 20.0);
+    }
+    let (Some(start), Some(goal)) = (nav_debug.start, nav_debug.goal) else {
+        return;
+    };
+    if let Some(path) = navmesh.find_path(start, goal) {
+        gizmos.linestrip(path.into_iter().map(|p| p + Vec3::Y * 0.5), Color::WHITE);
+    }
+}
+
 #[derive(Default)]
 struct PhysicsEnabled(bool);
 
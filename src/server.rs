@@ -1,4 +1,7 @@
-use std::{net::UdpSocket, time::SystemTime};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, SystemTime},
+};
 
 use bevy::{prelude::*, utils::HashMap};
 use bevy_renet::{
@@ -13,12 +16,25 @@ use bevy_renet::{
 use crate::{
     message::{
         self,
-        client::ReliableMessageFromClient,
-        server::{MessageReaderOnServer, ReliableMessageFromServer, TickSync},
+        client::{ConnectData, ReliableMessageFromClient},
+        lan::LanAnnouncePlugin,
+        server::{MessageReaderOnServer, ReliableMessageFromServer, SessionToken, TickSync},
     },
     shared::{
-        self, despawn_recursive_and_broadcast,
-        objects::{player::Player, NetworkObject},
+        self,
+        console::command::ConsoleAdmins,
+        despawn_recursive_and_broadcast,
+        game_mode::{ActiveGameMode, GameModeKind, GameModePlugin},
+        objects::{
+            player::{
+                movement::MovementProfile,
+                name::{sanitize_name, PlayerName},
+                server::ClientInputs,
+                Player,
+            },
+            NetworkObject,
+        },
+        proc::{navmesh::NavMeshPlugin, WorldSeed},
         scenes::setup_scene_1,
         tick::{get_unix_millis, Tick},
         GameLogic,
@@ -26,20 +42,36 @@ use crate::{
     ui::UIPlugin,
 };
 
-pub fn run() {
+const MAX_CLIENTS: usize = 64;
+/// How long the player of a client that lost its connection is kept around,
+/// waiting for it to come back.
+const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+
+pub fn run(game_mode: Option<String>) {
     let is_server = true;
+    let game_mode = match game_mode.as_deref() {
+        Some(v) => {
+            GameModeKind::from_arg(v).unwrap_or_else(|| panic!("{v} is not a valid game mode"))
+        }
+        None => GameModeKind::Deathmatch,
+    };
     App::new()
         .add_plugins((DefaultPlugins, Server, UIPlugin { is_server }))
         .add_systems(Startup, (setup, setup_scene_1))
         .add_systems(
             FixedUpdate,
-            (handle_server_events, handle_ready_game).in_set(GameLogic::Sync),
+            (handle_server_events, handle_ready_game, expire_sessions).in_set(GameLogic::Sync),
         )
         .add_plugins((
             shared::Game { is_server },
             shared::tick::TickPlugin { is_server },
             message::server::ServerMessagePlugin,
+            GameModePlugin { is_server },
+            NavMeshPlugin,
         ))
+        .insert_resource(ActiveGameMode::new(game_mode))
+        .insert_resource(WorldSeed::from_env_or_random())
+        .insert_resource(MovementProfile::from_env_or_default())
         .insert_state(shared::AppState::InGame)
         .add_event::<PlayerWantsUpdates>()
         .add_event::<PlayerNeedsInit>()
@@ -52,24 +84,35 @@ impl Plugin for Server {
     fn build(&self, app: &mut App) {
         app.add_plugins(RenetServerPlugin);
         app.insert_resource(ClientNetworkObjectMap::default());
+        app.init_resource::<PlayerNames>();
+        app.init_resource::<Sessions>();
 
         let server = RenetServer::new(ConnectionConfig::default());
         app.insert_resource(server);
 
         app.add_plugins(NetcodeServerPlugin);
-        let server_addr = shared::SERVER_ADDR.parse().unwrap();
+        // Servers only listen on the loopback address by default. Set
+        // `SERVER_ADDR` to the machine's address to play over the network.
+        let server_addr: SocketAddr = std::env::var("SERVER_ADDR")
+            .unwrap_or_else(|_| shared::SERVER_ADDR.to_string())
+            .parse()
+            .expect("SERVER_ADDR should be an address and port");
         let socket = UdpSocket::bind(server_addr).unwrap();
         let server_config = ServerConfig {
             current_time: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap(),
-            max_clients: 64,
+            max_clients: MAX_CLIENTS,
             protocol_id: 0,
             public_addresses: vec![server_addr],
             authentication: ServerAuthentication::Unsecure,
         };
         let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
         app.insert_resource(transport);
+        app.add_plugins(LanAnnouncePlugin {
+            addr: server_addr,
+            max_players: MAX_CLIENTS,
+        });
     }
 }
 
@@ -85,39 +128,167 @@ pub struct ClientNetworkObjectMap {
     pub net_obj_to_client: HashMap<NetworkObject, ClientId>,
 }
 
+/// The names clients connected with. Names are unique, so players can be told
+/// apart in chat.
+#[derive(Resource, Default)]
+pub struct PlayerNames(HashMap<ClientId, String>);
+
+impl PlayerNames {
+    pub fn get(&self, client_id: ClientId) -> String {
+        self.0
+            .get(&client_id)
+            .cloned()
+            .unwrap_or_else(|| format!("player{client_id}"))
+    }
+
+    pub fn find(&self, name: &str) -> Option<ClientId> {
+        self.0
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|(client_id, _)| *client_id)
+    }
+
+    /// Adds a number to the end of names that are taken.
+    fn insert(&mut self, client_id: ClientId, name: String) -> String {
+        let mut unique = name.clone();
+        let mut count = 1;
+        while self.find(&unique).is_some() {
+            count += 1;
+            unique = format!("{name}{count}");
+        }
+        self.0.insert(client_id, unique.clone());
+        unique
+    }
+}
+
+/// A player whose client lost its connection.
+struct HeldPlayer {
+    net_obj: NetworkObject,
+    /// When the connection was lost.
+    since: Duration,
+}
+
+/// Tracks the session of each client, and the players of clients that
+/// disconnected until they come back or their grace period runs out.
+#[derive(Resource, Default)]
+pub struct Sessions {
+    tokens: HashMap<ClientId, SessionToken>,
+    held: HashMap<SessionToken, HeldPlayer>,
+    /// Players waiting to be given back to their reconnected clients.
+    resumed: HashMap<ClientId, NetworkObject>,
+}
+
+impl Sessions {
+    /// Ends the session of a client, so its player is removed as soon as it
+    /// disconnects instead of waiting for it to come back.
+    pub fn revoke(&mut self, client_id: ClientId) {
+        self.tokens.remove(&client_id);
+    }
+
+    pub fn token(&self, client_id: ClientId) -> Option<SessionToken> {
+        self.tokens.get(&client_id).copied()
+    }
+}
+
+fn despawn_player(
+    net_obj: &NetworkObject,
+    query: &Query<(Entity, &NetworkObject), With<Player>>,
+    server: &mut RenetServer,
+    commands: &mut Commands,
+) {
+    if let Some((entity, _)) = query.iter().find(|(_, obj)| *obj == net_obj) {
+        despawn_recursive_and_broadcast(server, commands, entity, net_obj.clone());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_server_events(
     mut server_events: EventReader<ServerEvent>,
+    transport: Res<NetcodeServerTransport>,
+    mut names: ResMut<PlayerNames>,
+    mut sessions: ResMut<Sessions>,
+    mut admins: ResMut<ConsoleAdmins>,
     mut client_map: ResMut<ClientNetworkObjectMap>,
+    mut inputs: ResMut<ClientInputs>,
     query: Query<(Entity, &NetworkObject), With<Player>>,
+    time: Res<Time>,
     mut server: ResMut<RenetServer>,
     mut commands: Commands,
 ) {
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                println!("Client {} connected", client_id);
+                let data = transport
+                    .user_data(*client_id)
+                    .and_then(|data| ConnectData::from_user_data(&data));
+                let name = data
+                    .as_ref()
+                    .and_then(|data| sanitize_name(&data.name))
+                    .unwrap_or_else(|| format!("player{client_id}"));
+                let name = names.insert(*client_id, name);
+                let held = data
+                    .and_then(|data| data.session)
+                    .and_then(|token| Some((token, sessions.held.remove(&token)?)));
+                match held {
+                    Some((token, held)) => {
+                        println!("Client {} reconnected as {}", client_id, name);
+                        sessions.tokens.insert(*client_id, token);
+                        sessions.resumed.insert(*client_id, held.net_obj);
+                    }
+                    None => {
+                        println!("Client {} connected as {}", client_id, name);
+                        sessions.tokens.insert(*client_id, SessionToken::new_rand());
+                    }
+                }
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 println!("Client {} disconnected: {:?}", client_id, reason);
-                if let Some(net_obj) = client_map.client_to_net_obj.remove(client_id) {
-                    client_map.net_obj_to_client.remove(&net_obj);
-                    for (entity, obj) in query.iter() {
-                        if *obj == net_obj {
-                            despawn_recursive_and_broadcast(
-                                &mut server,
-                                &mut commands,
-                                entity,
-                                net_obj.clone(),
-                            );
-                            break;
-                        }
+                names.0.remove(client_id);
+                let resumed = sessions.resumed.remove(client_id);
+                let token = sessions.tokens.remove(client_id);
+                if let Some(token) = token {
+                    admins.logout(token);
+                }
+                let net_obj = client_map.client_to_net_obj.remove(client_id);
+                if let Some(net_obj) = &net_obj {
+                    client_map.net_obj_to_client.remove(net_obj);
+                    inputs.forget(net_obj);
+                }
+                // A client that reconnected and dropped again before taking its
+                // player back still has to be held, or the player is left behind.
+                let Some(net_obj) = net_obj.or(resumed) else {
+                    continue;
+                };
+                match token {
+                    Some(token) => {
+                        let since = time.elapsed();
+                        sessions.held.insert(token, HeldPlayer { net_obj, since });
                     }
+                    None => despawn_player(&net_obj, &query, &mut server, &mut commands),
                 }
             }
         }
     }
 }
 
+/// Removes players whose clients didn't come back in time.
+fn expire_sessions(
+    mut sessions: ResMut<Sessions>,
+    query: Query<(Entity, &NetworkObject), With<Player>>,
+    time: Res<Time>,
+    mut server: ResMut<RenetServer>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed();
+    sessions.held.retain(|_, held| {
+        let expired = now - held.since > RECONNECT_GRACE;
+        if expired {
+            despawn_player(&held.net_obj, &query, &mut server, &mut commands);
+        }
+        !expired
+    });
+}
+
 #[derive(Event)]
 pub struct PlayerWantsUpdates {
     pub client_id: ClientId,
@@ -127,12 +298,19 @@ pub struct PlayerWantsUpdates {
 pub struct PlayerNeedsInit {
     pub client_id: ClientId,
     pub net_obj: NetworkObject,
+    pub name: PlayerName,
+    pub session: SessionToken,
+    /// The player already exists, left behind when the client lost its
+    /// connection.
+    pub resumed: bool,
 }
 
 fn handle_ready_game(
     mut server: ResMut<RenetServer>,
     reader: Res<MessageReaderOnServer>,
     mut client_map: ResMut<ClientNetworkObjectMap>,
+    mut sessions: ResMut<Sessions>,
+    names: Res<PlayerNames>,
     tick: Res<Tick>,
     mut player_updates: EventWriter<PlayerWantsUpdates>,
     mut player_inits: EventWriter<PlayerNeedsInit>,
@@ -143,8 +321,12 @@ fn handle_ready_game(
                 println!("connected called twice");
                 continue;
             }
+            let Some(session) = sessions.token(*client_id) else {
+                continue;
+            };
             println!("sending player network object");
-            let net_obj = NetworkObject::new_rand();
+            let resumed = sessions.resumed.remove(client_id);
+            let net_obj = resumed.clone().unwrap_or_else(NetworkObject::new_rand);
             client_map
                 .client_to_net_obj
                 .insert(*client_id, net_obj.clone());
@@ -154,6 +336,9 @@ fn handle_ready_game(
             player_inits.send(PlayerNeedsInit {
                 client_id: *client_id,
                 net_obj,
+                name: PlayerName(names.get(*client_id)),
+                session,
+                resumed: resumed.is_some(),
             });
 
             let message = ReliableMessageFromServer::TickSync(TickSync {
@@ -0,0 +1,428 @@
+use std::time::Duration;
+
+use bevy::{color::palettes::css::VIOLET, prelude::*, utils::HashMap};
+use bevy_renet::renet::{ClientId, DefaultChannel, RenetClient, RenetServer, ServerEvent};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{
+        client::{MessageReaderOnClient, ReliableMessageFromClient},
+        server::{MessageReaderOnServer, ReliableMessageFromServer},
+    },
+    server::{ClientNetworkObjectMap, PlayerNames},
+    shared::{
+        console::{
+            command::{CommandOutput, CommandSender},
+            Console, ConsoleMessage, InputMode, SubmittedLine,
+        },
+        game_mode::Team,
+        objects::{player::Player, NetworkObject},
+        GameLogic,
+    },
+};
+
+/// Longest chat message, in characters.
+const MAX_LENGTH: usize = 200;
+/// Messages a client can send at once before being rate limited.
+const BURST: u32 = 5;
+/// How often a rate limited client can send another message.
+const REFILL_INTERVAL: Duration = Duration::from_secs(1);
+const CHAT_KEY: KeyCode = KeyCode::KeyT;
+const TEAM_CHAT_KEY: KeyCode = KeyCode::KeyY;
+/// The name messages typed into the server's own console are sent with.
+const SERVER_NAME: &str = "server";
+
+/// Text chat between players. Clients send what they type to the server,
+/// which checks it and sends it on to everyone who should see it. Messages
+/// are shown in the console.
+pub struct ChatPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, open_chat);
+        if self.is_server {
+            app.insert_resource(ChatFilters::from_env());
+            app.init_resource::<ChatRateLimits>();
+            app.add_event::<ServerChat>();
+            app.add_systems(Update, (send_server_chat, forget_rate_limits));
+            app.add_systems(
+                FixedUpdate,
+                (
+                    recv_chat.in_set(GameLogic::ReadInput),
+                    broadcast_chat.in_set(GameLogic::Game),
+                ),
+            );
+        } else {
+            app.add_systems(Update, send_chat);
+            app.add_systems(FixedUpdate, recv_chat_broadcasts.in_set(GameLogic::Sync));
+        }
+    }
+}
+
+/// Who a chat message is for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ChatChannel {
+    All,
+    /// Only players on the sender's team.
+    Team,
+    /// Only the player with this name.
+    Whisper(String),
+}
+
+/// A chat message sent from a client to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    pub text: String,
+}
+
+/// A chat message the server sends on to the clients that should see it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatBroadcast {
+    pub sender: String,
+    pub team: Option<Team>,
+    pub channel: ChatChannel,
+    pub text: String,
+}
+
+impl ChatBroadcast {
+    fn to_console(&self) -> ConsoleMessage {
+        let (line, color) = match &self.channel {
+            ChatChannel::All => (
+                format!("{}: {}", self.sender, self.text),
+                self.team.map_or(Color::WHITE, |team| team.color()),
+            ),
+            ChatChannel::Team => (
+                format!("[team] {}: {}", self.sender, self.text),
+                self.team.map_or(Color::WHITE, |team| team.color()),
+            ),
+            ChatChannel::Whisper(to) => (
+                format!("{} -> {to}: {}", self.sender, self.text),
+                VIOLET.into(),
+            ),
+        };
+        ConsoleMessage::new(line).with_color(color)
+    }
+}
+
+/// A check run on every chat message on the server. It returns the text to
+/// send, which can be changed, or why the message was rejected.
+pub type ChatFilter = Box<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+/// Checks run on every chat message on the server, in order. Messages are
+/// limited to `MAX_LENGTH` characters, and words listed in
+/// `CHAT_BLOCKED_WORDS` (separated by commas) are starred out.
+#[derive(Resource)]
+pub struct ChatFilters(Vec<ChatFilter>);
+
+impl ChatFilters {
+    fn from_env() -> Self {
+        let mut filters = Self(Vec::new());
+        filters.add(|text| {
+            if text.chars().count() > MAX_LENGTH {
+                Err(format!(
+                    "messages can't be longer than {MAX_LENGTH} characters"
+                ))
+            } else {
+                Ok(text.to_string())
+            }
+        });
+        let blocked: Vec<String> = std::env::var("CHAT_BLOCKED_WORDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        if !blocked.is_empty() {
+            filters.add(move |text| Ok(star_out(text, &blocked)));
+        }
+        filters
+    }
+
+    pub fn add(&mut self, filter: impl Fn(&str) -> Result<String, String> + Send + Sync + 'static) {
+        self.0.push(Box::new(filter));
+    }
+
+    /// Runs every filter on a message, after removing control characters and
+    /// surrounding whitespace.
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        let mut text: String = text.trim().chars().filter(|c| !c.is_control()).collect();
+        if text.is_empty() {
+            return Err("messages can't be empty".to_string());
+        }
+        for filter in self.0.iter() {
+            text = filter(&text)?;
+        }
+        Ok(text)
+    }
+}
+
+/// Replaces blocked words, ignoring case, with stars.
+fn star_out(text: &str, blocked: &[String]) -> String {
+    text.split(' ')
+        .map(|word| {
+            let bare = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            if blocked.contains(&bare) {
+                "*".repeat(word.chars().count())
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Each client can send `BURST` messages at once, and then one every
+/// `REFILL_INTERVAL`.
+#[derive(Resource, Default)]
+struct ChatRateLimits(HashMap<ClientId, (u32, Duration)>);
+
+impl ChatRateLimits {
+    /// Uses up one message, if the client has any left.
+    fn try_send(&mut self, client_id: ClientId, now: Duration) -> bool {
+        let (available, last_refill) = self.0.entry(client_id).or_insert((BURST, now));
+        let refills = ((now - *last_refill).as_secs_f32() / REFILL_INTERVAL.as_secs_f32()) as u32;
+        if refills > 0 {
+            *available = (*available + refills).min(BURST);
+            *last_refill += REFILL_INTERVAL * refills;
+        }
+        if *available == 0 {
+            return false;
+        }
+        *available -= 1;
+        true
+    }
+}
+
+/// Client ids are random, so limits of clients that left would pile up.
+fn forget_rate_limits(
+    mut limits: ResMut<ChatRateLimits>,
+    mut server_events: EventReader<ServerEvent>,
+) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            limits.0.remove(client_id);
+        }
+    }
+}
+
+/// A chat message that passed the server's checks.
+#[derive(Event)]
+struct ServerChat {
+    sender: CommandSender,
+    message: ChatMessage,
+}
+
+/// Splits `/whisper <name> <message>` into a whisper. Other lines starting
+/// with a slash aren't chat.
+fn parse_chat(text: &str, mode: InputMode) -> Result<ChatMessage, String> {
+    let Some(command) = text.strip_prefix('/') else {
+        let channel = match mode {
+            InputMode::TeamChat => ChatChannel::Team,
+            _ => ChatChannel::All,
+        };
+        return Ok(ChatMessage {
+            channel,
+            text: text.to_string(),
+        });
+    };
+    let usage = || "usage: /whisper <name> <message>".to_string();
+    let (name, rest) = command.split_once(' ').ok_or_else(usage)?;
+    if name != "whisper" && name != "w" {
+        return Err(usage());
+    }
+    let (to, text) = rest.trim_start().split_once(' ').ok_or_else(usage)?;
+    Ok(ChatMessage {
+        channel: ChatChannel::Whisper(to.to_string()),
+        text: text.to_string(),
+    })
+}
+
+fn open_chat(keys: Res<ButtonInput<KeyCode>>, mut console: ResMut<Console>) {
+    if console.is_open() {
+        return;
+    }
+    if keys.just_pressed(CHAT_KEY) {
+        console.open_chat(InputMode::Chat);
+    } else if keys.just_pressed(TEAM_CHAT_KEY) {
+        console.open_chat(InputMode::TeamChat);
+    }
+}
+
+fn chat_lines(submitted: &mut EventReader<SubmittedLine>) -> Vec<Result<ChatMessage, String>> {
+    submitted
+        .read()
+        .filter(|line| line.mode != InputMode::Command)
+        .map(|line| parse_chat(&line.text, line.mode))
+        .collect()
+}
+
+fn send_chat(
+    mut submitted: EventReader<SubmittedLine>,
+    client: Option<ResMut<RenetClient>>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    let Some(mut client) = client else {
+        return;
+    };
+    for chat in chat_lines(&mut submitted) {
+        match chat {
+            Ok(chat) => {
+                let message = ReliableMessageFromClient::Chat(chat);
+                let bytes = bincode::serialize(&message).unwrap();
+                client.send_message(DefaultChannel::ReliableUnordered, bytes);
+            }
+            Err(e) => {
+                messages.send(ConsoleMessage::error(e));
+            }
+        }
+    }
+}
+
+/// Chat typed into the server's own console.
+fn send_server_chat(
+    mut submitted: EventReader<SubmittedLine>,
+    mut chat: EventWriter<ServerChat>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    for message in chat_lines(&mut submitted) {
+        match message {
+            Ok(message) => {
+                chat.send(ServerChat {
+                    sender: CommandSender::Local,
+                    message,
+                });
+            }
+            Err(e) => {
+                messages.send(ConsoleMessage::error(e));
+            }
+        }
+    }
+}
+
+fn recv_chat(
+    reader: Res<MessageReaderOnServer>,
+    filters: Res<ChatFilters>,
+    mut limits: ResMut<ChatRateLimits>,
+    time: Res<Time>,
+    mut chat: EventWriter<ServerChat>,
+    mut output: EventWriter<CommandOutput>,
+) {
+    for (client_id, msg) in reader.reliable_messages() {
+        let ReliableMessageFromClient::Chat(message) = msg else {
+            continue;
+        };
+        let sender = CommandSender::Client(*client_id);
+        let error = |text: String| CommandOutput {
+            to: sender,
+            text,
+            is_error: true,
+        };
+        if !limits.try_send(*client_id, time.elapsed()) {
+            output.send(error("you're sending messages too quickly".to_string()));
+            continue;
+        }
+        match filters.apply(&message.text) {
+            Ok(text) => {
+                chat.send(ServerChat {
+                    sender,
+                    message: ChatMessage {
+                        channel: message.channel.clone(),
+                        text,
+                    },
+                });
+            }
+            Err(e) => {
+                output.send(error(e));
+            }
+        }
+    }
+}
+
+/// Sends chat messages on to the clients on their channel, and shows them in
+/// the server's console.
+fn broadcast_chat(
+    mut chat: EventReader<ServerChat>,
+    client_map: Res<ClientNetworkObjectMap>,
+    names: Res<PlayerNames>,
+    players: Query<(&NetworkObject, Option<&Team>), With<Player>>,
+    mut server: ResMut<RenetServer>,
+    mut output: EventWriter<CommandOutput>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    let team_of = |client_id: &ClientId| {
+        let net_obj = client_map.client_to_net_obj.get(client_id)?;
+        players
+            .iter()
+            .find(|(player_obj, _)| *player_obj == net_obj)
+            .and_then(|(_, team)| team.copied())
+    };
+    for ServerChat { sender, message } in chat.read() {
+        let (name, team) = match sender {
+            CommandSender::Local => (SERVER_NAME.to_string(), None),
+            CommandSender::Client(client_id) => (names.get(*client_id), team_of(client_id)),
+        };
+        let recipients: Vec<ClientId> = match &message.channel {
+            ChatChannel::All => server.clients_id(),
+            ChatChannel::Team => {
+                let Some(team) = team else {
+                    output.send(CommandOutput {
+                        to: *sender,
+                        text: "you aren't on a team".to_string(),
+                        is_error: true,
+                    });
+                    continue;
+                };
+                server
+                    .clients_id()
+                    .into_iter()
+                    .filter(|client_id| team_of(client_id) == Some(team))
+                    .collect()
+            }
+            ChatChannel::Whisper(to) => {
+                let Some(target) = names.find(to) else {
+                    output.send(CommandOutput {
+                        to: *sender,
+                        text: format!("there's no player called {to}"),
+                        is_error: true,
+                    });
+                    continue;
+                };
+                // The sender gets their whisper back, to see that it was sent.
+                let mut recipients = vec![target];
+                if let CommandSender::Client(client_id) = sender {
+                    recipients.push(*client_id);
+                }
+                recipients
+            }
+        };
+
+        let broadcast = ChatBroadcast {
+            sender: name,
+            team,
+            channel: message.channel.clone(),
+            text: message.text.clone(),
+        };
+        info!("chat: {:?}", broadcast);
+        messages.send(broadcast.to_console());
+        let bytes = bincode::serialize(&ReliableMessageFromServer::Chat(broadcast)).unwrap();
+        for client_id in recipients {
+            server.send_message(client_id, DefaultChannel::ReliableUnordered, bytes.clone());
+        }
+    }
+}
+
+fn recv_chat_broadcasts(
+    reader: Res<MessageReaderOnClient>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    for msg in reader.reliable_messages() {
+        if let ReliableMessageFromServer::Chat(broadcast) = msg {
+            messages.send(broadcast.to_console());
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_renet::renet::{ClientId, DefaultChannel, RenetClient, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{
+        client::{MessageReaderOnClient, ReliableMessageFromClient},
+        server::{MessageReaderOnServer, ReliableMessageFromServer, SessionToken},
+    },
+    server::{ClientNetworkObjectMap, PlayerNames, Sessions},
+    shared::GameLogic,
+};
+
+use super::{
+    cvar::{CVarValue, CVars},
+    ConsoleMessage,
+};
+
+/// Who ran a command. Commands typed into the console of the server itself
+/// are `Local` and can do anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandSender {
+    Local,
+    Client(ClientId),
+}
+
+/// Describes a command that can be typed into the console. Commands are run
+/// by systems reading `RunCommand`s with their name.
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Arguments, shown by `help`.
+    pub usage: &'static str,
+    /// Whether the command needs the server's authority. Clients forward
+    /// these commands to the server instead of running them.
+    pub on_server: bool,
+    /// Whether only admins can run the command on the server.
+    pub admin: bool,
+}
+
+impl ConsoleCommand {
+    pub fn new(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            usage: "",
+            on_server: false,
+            admin: false,
+        }
+    }
+
+    pub fn usage(mut self, usage: &'static str) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn on_server(mut self) -> Self {
+        self.on_server = true;
+        self
+    }
+
+    pub fn admin(mut self) -> Self {
+        self.admin = true;
+        self
+    }
+}
+
+/// Every command that can be typed into the console, by name. Commands are
+/// registered on both the server and clients, so clients know which ones to
+/// forward.
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+impl ConsoleCommands {
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.0.get(name)
+    }
+
+    /// Sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.0.values()
+    }
+}
+
+/// Sent when a command should run, after permissions were checked.
+#[derive(Event, Clone, Debug)]
+pub struct RunCommand {
+    pub name: String,
+    pub args: Vec<String>,
+    pub sender: CommandSender,
+}
+
+impl RunCommand {
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    pub fn reply(&self, text: impl Into<String>) -> CommandOutput {
+        CommandOutput {
+            to: self.sender,
+            text: text.into(),
+            is_error: false,
+        }
+    }
+
+    pub fn error(&self, text: impl Into<String>) -> CommandOutput {
+        CommandOutput {
+            to: self.sender,
+            text: text.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// Text shown in the console of whoever ran a command.
+#[derive(Event, Clone, Debug)]
+pub struct CommandOutput {
+    pub to: CommandSender,
+    pub text: String,
+    pub is_error: bool,
+}
+
+/// A command forwarded from a client to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemoteCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// Output of a forwarded command, sent back to the client that ran it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemoteOutput {
+    pub text: String,
+    pub is_error: bool,
+}
+
+/// Lets plugins add commands and cvars to the console.
+pub trait ConsoleAppExt {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+
+    fn add_cvar(
+        &mut self,
+        name: &'static str,
+        default: CVarValue,
+        description: &'static str,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(command.name, command);
+        self
+    }
+
+    fn add_cvar(
+        &mut self,
+        name: &'static str,
+        default: CVarValue,
+        description: &'static str,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(CVars::default)
+            .register(name, default, description);
+        self
+    }
+}
+
+/// Sessions that logged in with the admin password. Logging in is disabled if
+/// `CONSOLE_ADMIN_PASSWORD` isn't set. Admin is tied to the session rather than
+/// the client id, and is dropped when the client disconnects, so a client that
+/// connects later with the same id doesn't inherit it.
+#[derive(Resource)]
+pub struct ConsoleAdmins {
+    password: Option<String>,
+    sessions: HashSet<SessionToken>,
+}
+
+impl ConsoleAdmins {
+    fn from_env() -> Self {
+        Self {
+            password: std::env::var("CONSOLE_ADMIN_PASSWORD").ok(),
+            sessions: HashSet::new(),
+        }
+    }
+
+    pub fn is_admin(&self, sender: CommandSender, sessions: &Sessions) -> bool {
+        match sender {
+            CommandSender::Local => true,
+            CommandSender::Client(client_id) => sessions
+                .token(client_id)
+                .is_some_and(|token| self.sessions.contains(&token)),
+        }
+    }
+
+    pub fn logout(&mut self, session: SessionToken) {
+        self.sessions.remove(&session);
+    }
+}
+
+/// Sets up running commands across the network, and the commands that are
+/// about connections rather than the game.
+pub(super) fn build(app: &mut App, is_server: bool) {
+    app.add_console_command(
+        ConsoleCommand::new("login", "Logs in as an admin.")
+            .usage("<password>")
+            .on_server(),
+    )
+    .add_console_command(
+        ConsoleCommand::new("status", "Lists the connected clients.")
+            .on_server()
+            .admin(),
+    )
+    .add_console_command(
+        ConsoleCommand::new("kick", "Disconnects a client.")
+            .usage("<client id> [reason]")
+            .on_server()
+            .admin(),
+    );
+
+    if is_server {
+        app.insert_resource(ConsoleAdmins::from_env());
+        app.add_systems(
+            FixedUpdate,
+            (
+                recv_remote_commands.in_set(GameLogic::ReadInput),
+                run_connection_commands.in_set(GameLogic::Game),
+            ),
+        );
+        app.add_systems(Update, send_remote_output);
+    } else {
+        app.add_systems(FixedUpdate, recv_remote_output.in_set(GameLogic::Sync));
+    }
+}
+
+/// Sends a command to the server to run.
+pub fn forward_command(client: &mut RenetClient, name: String, args: Vec<String>) {
+    let message = ReliableMessageFromClient::ConsoleCommand(RemoteCommand { name, args });
+    let bytes = bincode::serialize(&message).unwrap();
+    client.send_message(DefaultChannel::ReliableUnordered, bytes);
+}
+
+/// Runs commands forwarded by clients, if they're allowed to.
+fn recv_remote_commands(
+    reader: Res<MessageReaderOnServer>,
+    commands: Res<ConsoleCommands>,
+    admins: Res<ConsoleAdmins>,
+    sessions: Res<Sessions>,
+    mut run: EventWriter<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+) {
+    for (client_id, msg) in reader.reliable_messages() {
+        let ReliableMessageFromClient::ConsoleCommand(remote) = msg else {
+            continue;
+        };
+        let command = RunCommand {
+            name: remote.name.clone(),
+            args: remote.args.clone(),
+            sender: CommandSender::Client(*client_id),
+        };
+        match commands.get(&remote.name) {
+            Some(info) if !info.on_server => {
+                output.send(command.error(format!("{} can't run on the server", info.name)));
+            }
+            Some(info) if info.admin && !admins.is_admin(command.sender, &sessions) => {
+                output.send(command.error(format!("{} needs admin, try login", info.name)));
+            }
+            Some(_) => {
+                info!("client {client_id} ran {} {:?}", remote.name, remote.args);
+                run.send(command);
+            }
+            None => {
+                output.send(command.error(format!("unknown command {}", remote.name)));
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_connection_commands(
+    mut run: EventReader<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+    mut admins: ResMut<ConsoleAdmins>,
+    client_map: Res<ClientNetworkObjectMap>,
+    names: Res<PlayerNames>,
+    mut sessions: ResMut<Sessions>,
+    mut server: ResMut<RenetServer>,
+    mut kicked: Local<Vec<ClientId>>,
+) {
+    // Kicked clients are disconnected a tick later, so that they get told why.
+    for client_id in kicked.drain(..) {
+        server.disconnect(client_id);
+    }
+    for command in run.read() {
+        let reply = match command.name.as_str() {
+            "login" => login(command, &mut admins, &sessions),
+            "status" => {
+                let clients: Vec<String> = server
+                    .clients_id()
+                    .into_iter()
+                    .map(|client_id| {
+                        let player = client_map.client_to_net_obj.get(&client_id);
+                        let admin = admins.is_admin(CommandSender::Client(client_id), &sessions);
+                        let name = names.get(client_id);
+                        format!("{client_id} {name}: player {player:?}, admin {admin}")
+                    })
+                    .collect();
+                Ok(format!("{} clients\n{}", clients.len(), clients.join("\n")))
+            }
+            "kick" => match command
+                .args
+                .first()
+                .map(|arg| arg.parse().map(ClientId::from_raw))
+            {
+                Some(Ok(client_id)) if server.is_connected(client_id) => {
+                    let reason = match command.args[1..].join(" ") {
+                        reason if reason.is_empty() => "kicked by an admin".to_string(),
+                        reason => reason,
+                    };
+                    let message = ReliableMessageFromServer::Kicked(reason);
+                    let bytes = bincode::serialize(&message).unwrap();
+                    server.send_message(client_id, DefaultChannel::ReliableUnordered, bytes);
+                    kicked.push(client_id);
+                    // Kicked players can't come back to their old player.
+                    if let Some(session) = sessions.token(client_id) {
+                        admins.logout(session);
+                    }
+                    sessions.revoke(client_id);
+                    Ok(format!("kicked {client_id}"))
+                }
+                Some(Ok(client_id)) => Err(format!("{client_id} isn't connected")),
+                _ => Err("usage: kick <client id> [reason]".to_string()),
+            },
+            _ => continue,
+        };
+        output.send(match reply {
+            Ok(text) => command.reply(text),
+            Err(text) => command.error(text),
+        });
+    }
+}
+
+fn login(
+    command: &RunCommand,
+    admins: &mut ConsoleAdmins,
+    sessions: &Sessions,
+) -> Result<String, String> {
+    let CommandSender::Client(client_id) = command.sender else {
+        return Ok("the server console is always an admin".to_string());
+    };
+    let Some(password) = &admins.password else {
+        return Err("logging in is disabled on this server".to_string());
+    };
+    if command.args.first() != Some(password) {
+        warn!("client {client_id} failed to log in");
+        return Err("wrong password".to_string());
+    }
+    let Some(session) = sessions.token(client_id) else {
+        return Err("you don't have a session".to_string());
+    };
+    admins.sessions.insert(session);
+    Ok("logged in as admin".to_string())
+}
+
+/// Sends command output to the clients that ran the commands.
+fn send_remote_output(mut output: EventReader<CommandOutput>, mut server: ResMut<RenetServer>) {
+    for output in output.read() {
+        let CommandSender::Client(client_id) = output.to else {
+            continue;
+        };
+        let message = ReliableMessageFromServer::ConsoleOutput(RemoteOutput {
+            text: output.text.clone(),
+            is_error: output.is_error,
+        });
+        let bytes = bincode::serialize(&message).unwrap();
+        server.send_message(client_id, DefaultChannel::ReliableUnordered, bytes);
+    }
+}
+
+fn recv_remote_output(
+    reader: Res<MessageReaderOnClient>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    for msg in reader.reliable_messages() {
+        if let ReliableMessageFromServer::ConsoleOutput(output) = msg {
+            messages.send(ConsoleMessage::output(output.text.clone(), output.is_error));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::BTreeMap, fmt};
+
+use bevy::prelude::*;
+
+/// The value of a cvar. A cvar keeps the type of the value it was registered
+/// with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CVarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+}
+
+impl CVarValue {
+    /// Parses `input` as the same type as this value.
+    pub fn parse_same(&self, input: &str) -> Result<Self, String> {
+        let invalid = || format!("expected {}, got '{input}'", self.type_name());
+        match self {
+            Self::Bool(_) => match input {
+                "1" | "true" | "on" => Ok(Self::Bool(true)),
+                "0" | "false" | "off" => Ok(Self::Bool(false)),
+                _ => Err(invalid()),
+            },
+            Self::Int(_) => input.parse().map(Self::Int).map_err(|_| invalid()),
+            Self::Float(_) => input.parse().map(Self::Float).map_err(|_| invalid()),
+            Self::String(_) => Ok(Self::String(input.to_string())),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Bool(_) => "a bool",
+            Self::Int(_) => "an integer",
+            Self::Float(_) => "a number",
+            Self::String(_) => "a string",
+        }
+    }
+}
+
+impl fmt::Display for CVarValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "\"{value}\""),
+        }
+    }
+}
+
+/// Rust types that cvars can be read as.
+pub trait FromCVar: Sized {
+    fn from_cvar(value: &CVarValue) -> Option<Self>;
+}
+
+impl FromCVar for bool {
+    fn from_cvar(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromCVar for i64 {
+    fn from_cvar(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromCVar for f32 {
+    fn from_cvar(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromCVar for String {
+    fn from_cvar(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+pub struct CVar {
+    pub value: CVarValue,
+    pub default: CVarValue,
+    pub description: &'static str,
+}
+
+/// Settings that can be changed from the console while the game is running.
+/// Typing a cvar's name prints it, and typing its name and a value sets it.
+/// Systems that use a cvar can check if this resource changed.
+#[derive(Resource, Default)]
+pub struct CVars {
+    vars: BTreeMap<&'static str, CVar>,
+}
+
+impl CVars {
+    pub fn register(&mut self, name: &'static str, default: CVarValue, description: &'static str) {
+        self.vars.insert(
+            name,
+            CVar {
+                value: default.clone(),
+                default,
+                description,
+            },
+        );
+    }
+
+    /// Returns `None` if there's no such cvar, or it has a different type.
+    pub fn get<T: FromCVar>(&self, name: &str) -> Option<T> {
+        self.vars.get(name).and_then(|var| T::from_cvar(&var.value))
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&CVar> {
+        self.vars.get(name)
+    }
+
+    /// Parses and sets a cvar, keeping its type.
+    pub fn set(&mut self, name: &str, input: &str) -> Result<&CVarValue, String> {
+        let var = self
+            .vars
+            .get_mut(name)
+            .ok_or_else(|| format!("unknown cvar {name}"))?;
+        var.value = var.value.parse_same(input)?;
+        Ok(&var.value)
+    }
+
+    /// Sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &CVar)> {
+        self.vars.iter().map(|(name, var)| (*name, var))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cvars_keep_their_type() {
+        let mut cvars = CVars::default();
+        cvars.register("latency", CVarValue::Float(0.0), "");
+        cvars.register("enabled", CVarValue::Bool(false), "");
+
+        assert!(cvars.set("latency", "100").is_ok());
+        assert_eq!(cvars.get::<f32>("latency"), Some(100.0));
+        assert!(cvars.set("latency", "fast").is_err());
+        assert!(cvars.set("enabled", "on").is_ok());
+        assert_eq!(cvars.get::<bool>("enabled"), Some(true));
+        assert_eq!(cvars.get::<i64>("enabled"), None);
+        assert!(cvars.set("missing", "1").is_err());
+    }
+}
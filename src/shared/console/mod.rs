@@ -0,0 +1,471 @@
+use bevy::{
+    color::palettes::css::{GRAY, RED, WHITE},
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState, InputSystem,
+    },
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_renet::renet::RenetClient;
+
+use self::{
+    command::{forward_command, CommandOutput, CommandSender, ConsoleCommands, RunCommand},
+    cvar::CVars,
+};
+
+use super::{IsServer, Persistent};
+
+pub mod command;
+pub mod cvar;
+
+pub use command::{ConsoleAppExt, ConsoleCommand};
+
+/// Lines shown while the console is closed.
+const MESSAGES: usize = 5;
+/// Lines shown while the console is open.
+const OPEN_MESSAGES: usize = 20;
+/// Lines kept for scrolling back.
+const SCROLLBACK: usize = 500;
+const FONT_SIZE: f32 = 20.0;
+const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
+
+pub struct ConsolePlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConsoleMessage>()
+            .add_event::<SubmittedLine>()
+            .add_event::<RunCommand>()
+            .add_event::<CommandOutput>()
+            .init_resource::<Console>()
+            .init_resource::<ConsoleCommands>()
+            .init_resource::<CVars>()
+            .add_systems(Startup, setup)
+            .add_systems(PreUpdate, block_game_input.after(InputSystem))
+            .add_systems(
+                Update,
+                (
+                    type_in_console,
+                    run_lines,
+                    run_console_commands,
+                    print_local_output,
+                    collect_messages,
+                    render_console,
+                )
+                    .chain(),
+            );
+        app.add_console_command(ConsoleCommand::new("help", "Lists every command and cvar."))
+            .add_console_command(ConsoleCommand::new("clear", "Clears the console."));
+        command::build(app, self.is_server);
+    }
+}
+
+#[derive(Event, Clone)]
+pub struct ConsoleMessage {
+    content: String,
+    color: Color,
+}
+
+impl ConsoleMessage {
+    pub fn new(content: String) -> Self {
+        Self {
+            content: content + "\n",
+            color: WHITE.into(),
+        }
+    }
+
+    pub fn error(content: String) -> Self {
+        Self {
+            content: content + "\n",
+            color: RED.into(),
+        }
+    }
+
+    pub fn output(content: String, is_error: bool) -> Self {
+        if is_error {
+            Self::error(content)
+        } else {
+            Self::new(content)
+        }
+    }
+
+    /// A line typed into the console, shown before its output.
+    fn echo(line: &str) -> Self {
+        Self {
+            content: format!("> {line}\n"),
+            color: GRAY.into(),
+        }
+    }
+}
+
+/// What a line typed into the console is for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputMode {
+    #[default]
+    Command,
+    Chat,
+    TeamChat,
+}
+
+impl InputMode {
+    fn prompt(&self) -> &'static str {
+        match self {
+            Self::Command => ">",
+            Self::Chat => "say:",
+            Self::TeamChat => "say (team):",
+        }
+    }
+}
+
+/// The console's scrollback and what's being typed into it. While it's open,
+/// the game doesn't get any keyboard or mouse button input.
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    mode: InputMode,
+    input: String,
+    lines: Vec<ConsoleMessage>,
+    /// How many lines up from the bottom the console is scrolled.
+    scroll: usize,
+    /// Lines typed before, oldest first.
+    history: Vec<String>,
+    /// The line from `history` being shown, when going through it.
+    history_index: Option<usize>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens the console for typing a line of chat, which closes it again
+    /// once sent.
+    pub fn open_chat(&mut self, mode: InputMode) {
+        self.open = true;
+        self.mode = mode;
+    }
+}
+
+/// A line typed into the console.
+#[derive(Event)]
+pub struct SubmittedLine {
+    pub text: String,
+    pub mode: InputMode,
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+struct ConsoleTag;
+
+fn setup(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    padding: UiRect::all(Val::Px(5.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ConsoleRoot,
+            Persistent,
+        ))
+        .with_children(|parent| {
+            parent.spawn((TextBundle::default(), ConsoleTag));
+        });
+}
+
+/// Splits a line into words. Quotes group words with spaces into one.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn set_cursor_free(windows: &mut Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor.visible = true;
+        window.cursor.grab_mode = CursorGrabMode::None;
+    }
+}
+
+fn type_in_console(
+    mut keys: EventReader<KeyboardInput>,
+    mut console: ResMut<Console>,
+    mut submitted: EventWriter<SubmittedLine>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for key in keys.read() {
+        if key.state != ButtonState::Pressed {
+            continue;
+        }
+        if key.key_code == TOGGLE_KEY {
+            console.open = !console.open;
+            console.mode = InputMode::Command;
+            if console.open {
+                set_cursor_free(&mut windows);
+            }
+            continue;
+        }
+        if !console.open {
+            continue;
+        }
+        match &key.logical_key {
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.scroll = 0;
+                console.history_index = None;
+                if !line.trim().is_empty() {
+                    console.history.push(line.clone());
+                    submitted.send(SubmittedLine {
+                        text: line,
+                        mode: console.mode,
+                    });
+                }
+                if console.mode != InputMode::Command {
+                    console.open = false;
+                }
+            }
+            Key::Escape => console.open = false,
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::ArrowUp if !console.history.is_empty() => {
+                let index = console
+                    .history_index
+                    .map_or(console.history.len() - 1, |index| index.saturating_sub(1));
+                console.history_index = Some(index);
+                console.input = console.history[index].clone();
+            }
+            Key::ArrowDown => {
+                let next = console.history_index.map(|index| index + 1);
+                console.history_index = next.filter(|index| *index < console.history.len());
+                console.input = match console.history_index {
+                    Some(index) => console.history[index].clone(),
+                    None => String::new(),
+                };
+            }
+            Key::PageUp => {
+                let max_scroll = console.lines.len().saturating_sub(OPEN_MESSAGES);
+                console.scroll = (console.scroll + OPEN_MESSAGES / 2).min(max_scroll);
+            }
+            Key::PageDown => {
+                console.scroll = console.scroll.saturating_sub(OPEN_MESSAGES / 2);
+            }
+            Key::Space => console.input.push(' '),
+            Key::Character(text) => console.input.push_str(text),
+            _ => {}
+        }
+    }
+}
+
+/// Keeps typing in the console from moving the player or shooting.
+fn block_game_input(
+    console: Res<Console>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+) {
+    if console.open {
+        keys.reset_all();
+        mouse.reset_all();
+    }
+}
+
+/// Runs typed lines. A cvar's name prints it, and its name and a value sets
+/// it. Commands that need the server are forwarded to it when this is a
+/// client.
+fn run_lines(
+    mut submitted: EventReader<SubmittedLine>,
+    commands: Res<ConsoleCommands>,
+    mut cvars: ResMut<CVars>,
+    mut run: EventWriter<RunCommand>,
+    mut messages: EventWriter<ConsoleMessage>,
+    mut client: Option<ResMut<RenetClient>>,
+    is_server: Option<Res<IsServer>>,
+) {
+    for SubmittedLine { text: line, mode } in submitted.read() {
+        if *mode != InputMode::Command {
+            continue;
+        }
+        messages.send(ConsoleMessage::echo(line));
+        let mut args = tokenize(line);
+        if args.is_empty() {
+            continue;
+        }
+        let name = args.remove(0);
+
+        if let Some(var) = cvars.get_var(&name) {
+            let message = match args.first() {
+                None => ConsoleMessage::new(format!(
+                    "{name} = {} (default {}) - {}",
+                    var.value, var.default, var.description
+                )),
+                Some(value) => match cvars.set(&name, value) {
+                    Ok(value) => ConsoleMessage::new(format!("{name} = {value}")),
+                    Err(e) => ConsoleMessage::error(e),
+                },
+            };
+            messages.send(message);
+            continue;
+        }
+
+        let Some(command) = commands.get(&name) else {
+            messages.send(ConsoleMessage::error(format!(
+                "unknown command {name}, try help"
+            )));
+            continue;
+        };
+        if command.on_server && is_server.is_none() {
+            match client.as_deref_mut() {
+                Some(client) if client.is_connected() => forward_command(client, name, args),
+                _ => {
+                    messages.send(ConsoleMessage::error(format!(
+                        "{name} needs a server, but this isn't connected to one"
+                    )));
+                }
+            }
+        } else {
+            run.send(RunCommand {
+                name,
+                args,
+                sender: CommandSender::Local,
+            });
+        }
+    }
+}
+
+/// Runs the commands that are about the console itself.
+fn run_console_commands(
+    mut run: EventReader<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+    commands: Res<ConsoleCommands>,
+    cvars: Res<CVars>,
+    mut console: ResMut<Console>,
+) {
+    for command in run.read() {
+        if command.is("help") {
+            let mut help = String::from("commands:");
+            for info in commands.iter() {
+                let authority = match (info.on_server, info.admin) {
+                    (true, true) => " (server, admin)",
+                    (true, false) => " (server)",
+                    _ => "",
+                };
+                help += &format!(
+                    "\n  {} {} - {}{authority}",
+                    info.name, info.usage, info.description
+                );
+            }
+            help += "\ncvars:";
+            for (name, var) in cvars.iter() {
+                help += &format!("\n  {name} = {} - {}", var.value, var.description);
+            }
+            output.send(command.reply(help));
+        } else if command.is("clear") {
+            console.lines.clear();
+            console.scroll = 0;
+        }
+    }
+}
+
+fn print_local_output(
+    mut output: EventReader<CommandOutput>,
+    mut messages: EventWriter<ConsoleMessage>,
+) {
+    for output in output.read() {
+        if output.to == CommandSender::Local {
+            messages.send(ConsoleMessage::output(output.text.clone(), output.is_error));
+        }
+    }
+}
+
+/// Adds messages to the scrollback, a line at a time so that scrolling works
+/// with multi-line messages.
+fn collect_messages(mut reader: EventReader<ConsoleMessage>, mut console: ResMut<Console>) {
+    for msg in reader.read() {
+        for line in msg.content.lines() {
+            console
+                .lines
+                .push(ConsoleMessage::new(line.to_string()).with_color(msg.color));
+        }
+    }
+    let overflow = console.lines.len().saturating_sub(SCROLLBACK);
+    if overflow > 0 {
+        console.lines.drain(..overflow);
+    }
+}
+
+impl ConsoleMessage {
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn section(&self) -> TextSection {
+        TextSection::new(
+            self.content.clone(),
+            TextStyle {
+                color: self.color,
+                font_size: FONT_SIZE,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// Shows the last few lines while the console is closed. While it's open, it
+/// gets a background and shows more of the scrollback and the line being typed.
+fn render_console(
+    console: Res<Console>,
+    mut root: Query<&mut BackgroundColor, With<ConsoleRoot>>,
+    mut text: Query<&mut Text, With<ConsoleTag>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    let (Ok(mut background), Ok(mut text)) = (root.get_single_mut(), text.get_single_mut()) else {
+        return;
+    };
+    let is_panel = console.open && console.mode == InputMode::Command;
+    let shown = if is_panel { OPEN_MESSAGES } else { MESSAGES };
+    let end = console.lines.len() - console.scroll.min(console.lines.len());
+    let start = end.saturating_sub(shown);
+    text.sections = console.lines[start..end]
+        .iter()
+        .map(ConsoleMessage::section)
+        .collect();
+    if console.open {
+        let prompt = console.mode.prompt();
+        text.sections
+            .push(ConsoleMessage::new(format!("{prompt} {}_", console.input)).section());
+    }
+    if is_panel {
+        *background = Color::srgba(0.0, 0.0, 0.0, 0.7).into();
+    } else {
+        *background = Color::NONE.into();
+    }
+}
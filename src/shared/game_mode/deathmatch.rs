@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::shared::objects::NetworkObject;
+
+use super::{GameMode, GameModeKind, Scorer, Team};
+
+/// Free for all. Everyone can damage everyone and players score for themselves.
+pub struct Deathmatch {
+    pub score_limit: u32,
+    pub time_limit: Duration,
+}
+
+impl Default for Deathmatch {
+    fn default() -> Self {
+        Self {
+            score_limit: 20,
+            time_limit: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl GameMode for Deathmatch {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::Deathmatch
+    }
+
+    fn score_limit(&self) -> u32 {
+        self.score_limit
+    }
+
+    fn time_limit(&self) -> Duration {
+        self.time_limit
+    }
+
+    fn assign_team(&self, _teams: &[Team]) -> Option<Team> {
+        None
+    }
+
+    fn can_damage(&self, _attacker: Option<Team>, _victim: Option<Team>) -> bool {
+        true
+    }
+
+    fn scorer(&self, killer: &NetworkObject, _killer_team: Option<Team>) -> Scorer {
+        Scorer::Player(killer.clone())
+    }
+}
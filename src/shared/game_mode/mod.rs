@@ -0,0 +1,458 @@
+use std::time::Duration;
+
+use bevy::{
+    color::palettes::css::{BLUE, RED},
+    prelude::*,
+};
+use bevy_renet::renet::{DefaultChannel, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{client::MessageReaderOnClient, server::ReliableMessageFromServer},
+    server::PlayerWantsUpdates,
+    shared::{
+        objects::{
+            health::{Health, Killed},
+            player::{server::random_spawn_translation, Player, PlayerKinematics},
+            NetworkObject,
+        },
+        proc::{terrain::Terrain, utils::SnapToFloor},
+        GameLogic,
+    },
+};
+
+use self::{
+    deathmatch::Deathmatch,
+    team_deathmatch::TeamDeathmatch,
+    waves::{run_waves, WaveInfo, Waves},
+};
+
+pub mod deathmatch;
+pub mod team_deathmatch;
+pub mod waves;
+
+const WARMUP_DURATION: Duration = Duration::from_secs(15);
+const ROUND_END_DURATION: Duration = Duration::from_secs(10);
+/// The state is resent at this interval so that clients can correct their
+/// round timer, even when nothing else has changed.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct GameModePlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        if self.is_server {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    tick_game_mode.in_set(GameLogic::Game),
+                    handle_kills.in_set(GameLogic::Game),
+                    run_waves
+                        .in_set(GameLogic::Game)
+                        .run_if(is_game_mode(GameModeKind::Waves)),
+                    send_game_mode.in_set(GameLogic::Sync),
+                )
+                    .run_if(resource_exists::<ActiveGameMode>),
+            );
+        } else {
+            app.add_systems(FixedUpdate, recv_game_mode.in_set(GameLogic::Sync));
+        }
+    }
+}
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Red => RED.into(),
+            Self::Blue => BLUE.into(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Red => "Red",
+            Self::Blue => "Blue",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameModeKind {
+    Deathmatch,
+    TeamDeathmatch,
+    /// Players fight waves of worms together.
+    Waves,
+}
+
+impl GameModeKind {
+    /// Parses the game mode passed on the command line, e.g. `cargo run -- server tdm`.
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "dm" | "deathmatch" => Some(Self::Deathmatch),
+            "tdm" | "team_deathmatch" => Some(Self::TeamDeathmatch),
+            "pve" | "waves" => Some(Self::Waves),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Deathmatch => "Deathmatch",
+            Self::TeamDeathmatch => "Team Deathmatch",
+            Self::Waves => "Waves",
+        }
+    }
+}
+
+/// Order is:
+/// - Warmup
+/// - InProgress
+/// - RoundEnd
+///
+/// After the round ends the game goes back to warmup with the scores reset.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamePhase {
+    /// Kills don't count towards the score.
+    Warmup,
+    InProgress,
+    /// The winner is decided, waiting for the next warmup.
+    RoundEnd,
+}
+
+impl GamePhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Warmup => "Warmup",
+            Self::InProgress => "In progress",
+            Self::RoundEnd => "Round over",
+        }
+    }
+}
+
+/// Something that can earn points. Depends on the game mode, e.g. in team
+/// deathmatch the team gets the points, not the player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scorer {
+    Player(NetworkObject),
+    Team(Team),
+}
+
+/// Rules of a game mode. The phases and timers are handled by `ActiveGameMode`,
+/// this only decides who plays with who and who gets the points.
+pub trait GameMode: Send + Sync + 'static {
+    fn kind(&self) -> GameModeKind;
+
+    /// The round ends as soon as a scorer reaches this score.
+    fn score_limit(&self) -> u32;
+
+    /// The round ends after this long, even if no one reached the score limit.
+    fn time_limit(&self) -> Duration;
+
+    /// Picks the team for a player that just joined. `teams` are the teams of
+    /// the players already in the game.
+    fn assign_team(&self, teams: &[Team]) -> Option<Team>;
+
+    /// Returns if a player on team `attacker` can damage a player on team `victim`.
+    fn can_damage(&self, attacker: Option<Team>, victim: Option<Team>) -> bool;
+
+    /// Returns who should get the points when `killer` gets a kill.
+    fn scorer(&self, killer: &NetworkObject, killer_team: Option<Team>) -> Scorer;
+
+    /// Returns the winner when the time limit runs out before anyone reached
+    /// the score limit.
+    fn time_limit_winner(&self, state: &GameModeState) -> Option<Scorer> {
+        state.leader()
+    }
+}
+
+/// The state of the game mode. This is sent to clients, and is a resource on
+/// the client. On the server it is stored in `ActiveGameMode`.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+pub struct GameModeState {
+    pub kind: GameModeKind,
+    pub phase: GamePhase,
+    /// Time left in the current phase.
+    pub remaining: Duration,
+    pub score_limit: u32,
+    pub scores: Vec<(Scorer, u32)>,
+    /// Only set during `GamePhase::RoundEnd`. `None` if the round was a draw.
+    pub winner: Option<Scorer>,
+    /// Only set in `GameModeKind::Waves`.
+    pub waves: Option<WaveInfo>,
+}
+
+impl GameModeState {
+    pub fn score(&self, scorer: &Scorer) -> u32 {
+        self.scores
+            .iter()
+            .find(|(s, _)| s == scorer)
+            .map(|(_, score)| *score)
+            .unwrap_or(0)
+    }
+
+    fn add_score(&mut self, scorer: Scorer, points: u32) {
+        match self.scores.iter_mut().find(|(s, _)| *s == scorer) {
+            Some((_, score)) => *score += points,
+            None => self.scores.push((scorer, points)),
+        }
+        self.scores.sort_by(|(_, a), (_, b)| b.cmp(a));
+    }
+
+    /// Returns the scorer with the highest score, or `None` if it is a tie.
+    pub fn leader(&self) -> Option<Scorer> {
+        match self.scores.as_slice() {
+            [] => None,
+            [(first, _)] => Some(first.clone()),
+            [(first, a), (_, b), ..] => (a > b).then(|| first.clone()),
+        }
+    }
+}
+
+/// The game mode the server is running, along with the timers driving its
+/// phases.
+#[derive(Resource)]
+pub struct ActiveGameMode {
+    mode: Box<dyn GameMode>,
+    state: GameModeState,
+    phase_timer: Timer,
+    sync_timer: Timer,
+    /// Set when the state changed in a way clients should know about immediately.
+    dirty: bool,
+}
+
+impl ActiveGameMode {
+    pub fn new(kind: GameModeKind) -> Self {
+        let mode: Box<dyn GameMode> = match kind {
+            GameModeKind::Deathmatch => Box::new(Deathmatch::default()),
+            GameModeKind::TeamDeathmatch => Box::new(TeamDeathmatch::default()),
+            GameModeKind::Waves => Box::new(Waves::default()),
+        };
+        Self {
+            state: GameModeState {
+                kind,
+                phase: GamePhase::Warmup,
+                remaining: WARMUP_DURATION,
+                score_limit: mode.score_limit(),
+                scores: Vec::new(),
+                winner: None,
+                waves: None,
+            },
+            phase_timer: Timer::new(WARMUP_DURATION, TimerMode::Once),
+            sync_timer: Timer::new(SYNC_INTERVAL, TimerMode::Repeating),
+            dirty: true,
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> &dyn GameMode {
+        self.mode.as_ref()
+    }
+
+    pub fn state(&self) -> &GameModeState {
+        &self.state
+    }
+
+    fn set_phase(&mut self, phase: GamePhase, duration: Duration) {
+        self.state.phase = phase;
+        self.state.remaining = duration;
+        self.phase_timer = Timer::new(duration, TimerMode::Once);
+        self.dirty = true;
+    }
+
+    fn start_warmup(&mut self) {
+        self.state.scores.clear();
+        self.state.winner = None;
+        self.set_phase(GamePhase::Warmup, WARMUP_DURATION);
+    }
+
+    fn start_round(&mut self) {
+        self.state.scores.clear();
+        let time_limit = self.mode.time_limit();
+        self.set_phase(GamePhase::InProgress, time_limit);
+    }
+
+    fn end_round(&mut self, winner: Option<Scorer>) {
+        info!("round over, winner: {:?}", winner);
+        self.state.winner = winner;
+        self.set_phase(GamePhase::RoundEnd, ROUND_END_DURATION);
+    }
+
+    fn add_score(&mut self, scorer: Scorer, points: u32) {
+        self.state.add_score(scorer, points);
+        self.dirty = true;
+    }
+
+    /// Updates the wave info. Clients are only sent the update straight away
+    /// when something other than the timer changed.
+    fn set_waves(&mut self, waves: Option<WaveInfo>) {
+        let changed = match (&self.state.waves, &waves) {
+            (Some(old), Some(new)) => {
+                old.wave != new.wave
+                    || old.remaining_enemies != new.remaining_enemies
+                    || old.next_wave_in.is_some() != new.next_wave_in.is_some()
+            }
+            (None, None) => false,
+            _ => true,
+        };
+        self.dirty |= changed;
+        self.state.waves = waves;
+    }
+}
+
+/// Run condition for systems that only run in a specific game mode.
+pub fn is_game_mode(kind: GameModeKind) -> impl Fn(Option<Res<ActiveGameMode>>) -> bool {
+    move |game_mode| game_mode.is_some_and(|game_mode| game_mode.state.kind == kind)
+}
+
+/// Moves a player to a random spawn point with full health. Only to be used on
+/// the server, the owning client will get the new position through reconciliation.
+pub fn respawn_player(
+    entity: Entity,
+    transform: &mut Transform,
+    health: &mut Health,
+    player: &mut Player,
+    snap_to_floor: &mut EventWriter<SnapToFloor>,
+    terrain: &Terrain,
+) {
+    transform.translation = random_spawn_translation(terrain);
+    health.current = health.max;
+    player.kinematics = PlayerKinematics::new(&player.profile);
+    snap_to_floor.send(SnapToFloor::new(entity).with_offset(1.0));
+}
+
+/// Advances the phase timer and moves on to the next phase when needed.
+fn tick_game_mode(
+    mut game_mode: ResMut<ActiveGameMode>,
+    mut players: Query<(Entity, &mut Transform, &mut Health, &mut Player)>,
+    mut snap_to_floor: EventWriter<SnapToFloor>,
+    terrain: Res<Terrain>,
+    time: Res<Time>,
+) {
+    game_mode.phase_timer.tick(time.delta());
+    game_mode.state.remaining = game_mode.phase_timer.remaining();
+    let finished = game_mode.phase_timer.finished();
+
+    match game_mode.state.phase {
+        GamePhase::Warmup => {
+            if finished {
+                game_mode.start_round();
+                for (entity, mut transform, mut health, mut player) in players.iter_mut() {
+                    respawn_player(
+                        entity,
+                        &mut transform,
+                        &mut health,
+                        &mut player,
+                        &mut snap_to_floor,
+                        &terrain,
+                    );
+                }
+            }
+        }
+        GamePhase::InProgress => {
+            let score_limit = game_mode.state.score_limit;
+            let reached_limit = game_mode
+                .state
+                .scores
+                .iter()
+                .find(|(_, score)| *score >= score_limit)
+                .map(|(scorer, _)| scorer.clone());
+            if let Some(winner) = reached_limit {
+                game_mode.end_round(Some(winner));
+            } else if finished {
+                let winner = game_mode.mode().time_limit_winner(&game_mode.state);
+                game_mode.end_round(winner);
+            }
+        }
+        GamePhase::RoundEnd => {
+            if finished {
+                game_mode.start_warmup();
+            }
+        }
+    }
+}
+
+/// Scores kills and respawns the players that were killed.
+fn handle_kills(
+    mut kills: EventReader<Killed>,
+    mut game_mode: ResMut<ActiveGameMode>,
+    mut players: Query<
+        (
+            Entity,
+            &NetworkObject,
+            &mut Transform,
+            &mut Health,
+            &mut Player,
+            Option<&Team>,
+        ),
+        With<Player>,
+    >,
+    mut snap_to_floor: EventWriter<SnapToFloor>,
+    terrain: Res<Terrain>,
+) {
+    for kill in kills.read() {
+        // `None` if the killer isn't a player, e.g. a worm.
+        let killer_team = players
+            .iter()
+            .find(|(_, net_obj, ..)| **net_obj == kill.killer)
+            .map(|(.., team)| team.copied());
+        // Non-player victims are handled by whatever owns them.
+        let Some((entity, _, mut transform, mut health, mut player, _)) = players
+            .iter_mut()
+            .find(|(_, net_obj, ..)| **net_obj == kill.victim)
+        else {
+            continue;
+        };
+
+        if let Some(killer_team) = killer_team {
+            if game_mode.state.phase == GamePhase::InProgress && kill.killer != kill.victim {
+                let scorer = game_mode.mode().scorer(&kill.killer, killer_team);
+                game_mode.add_score(scorer, 1);
+            }
+        }
+        respawn_player(
+            entity,
+            &mut transform,
+            &mut health,
+            &mut player,
+            &mut snap_to_floor,
+            &terrain,
+        );
+    }
+}
+
+/// Sends the game mode state to clients when it changes, when a client becomes
+/// ready, and every `SYNC_INTERVAL`. Each sync replaces the whole state, so
+/// they go over an ordered channel to keep an older one from arriving last.
+fn send_game_mode(
+    mut game_mode: ResMut<ActiveGameMode>,
+    mut player_updates: EventReader<PlayerWantsUpdates>,
+    mut server: ResMut<RenetServer>,
+    time: Res<Time>,
+) {
+    game_mode.sync_timer.tick(time.delta());
+    let new_players = player_updates.read().count() > 0;
+    if !game_mode.dirty && !new_players && !game_mode.sync_timer.just_finished() {
+        return;
+    }
+    game_mode.dirty = false;
+
+    let message = ReliableMessageFromServer::GameModeSync(game_mode.state.clone());
+    let bytes = bincode::serialize(&message).unwrap();
+    server.broadcast_message(DefaultChannel::ReliableOrdered, bytes);
+}
+
+fn recv_game_mode(reader: Res<MessageReaderOnClient>, mut commands: Commands) {
+    for msg in reader.reliable_messages() {
+        if let ReliableMessageFromServer::GameModeSync(state) = msg {
+            commands.insert_resource(state.clone());
+        }
+    }
+}
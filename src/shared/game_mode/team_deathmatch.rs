@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use crate::shared::objects::NetworkObject;
+
+use super::{GameMode, GameModeKind, Scorer, Team};
+
+/// Two teams, kills score for the killer's team.
+pub struct TeamDeathmatch {
+    pub score_limit: u32,
+    pub time_limit: Duration,
+    /// If players can damage players on their own team.
+    pub friendly_fire: bool,
+}
+
+impl Default for TeamDeathmatch {
+    fn default() -> Self {
+        Self {
+            score_limit: 50,
+            time_limit: Duration::from_secs(10 * 60),
+            friendly_fire: false,
+        }
+    }
+}
+
+impl GameMode for TeamDeathmatch {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::TeamDeathmatch
+    }
+
+    fn score_limit(&self) -> u32 {
+        self.score_limit
+    }
+
+    fn time_limit(&self) -> Duration {
+        self.time_limit
+    }
+
+    /// Puts the player on the team with the fewest players, red on a tie.
+    fn assign_team(&self, teams: &[Team]) -> Option<Team> {
+        let red = teams.iter().filter(|team| **team == Team::Red).count();
+        let blue = teams.len() - red;
+        if blue < red {
+            Some(Team::Blue)
+        } else {
+            Some(Team::Red)
+        }
+    }
+
+    fn can_damage(&self, attacker: Option<Team>, victim: Option<Team>) -> bool {
+        self.friendly_fire || attacker.is_none() || attacker != victim
+    }
+
+    fn scorer(&self, killer: &NetworkObject, killer_team: Option<Team>) -> Scorer {
+        match killer_team {
+            Some(team) => Scorer::Team(team),
+            None => Scorer::Player(killer.clone()),
+        }
+    }
+}
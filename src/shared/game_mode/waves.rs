@@ -0,0 +1,257 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_renet::renet::RenetServer;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{
+    despawn_recursive_and_broadcast,
+    objects::{player::Player, worm::Worm, NetworkObject},
+    proc::{
+        chunk::Chunk,
+        terrain::{Terrain, TerrainChunkData},
+        utils::SnapToFloor,
+    },
+    tick::Tick,
+};
+
+use super::{ActiveGameMode, GameMode, GameModeKind, GameModeState, GamePhase, Scorer, Team};
+
+/// Everyone is on this team so that players can't hurt each other.
+const PLAYER_TEAM: Team = Team::Blue;
+const TIME_BETWEEN_WAVES: Duration = Duration::from_secs(10);
+/// How long to wait before trying again when no enemy of a wave could spawn.
+const WAVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const FIRST_WAVE_ENEMIES: u32 = 3;
+const EXTRA_ENEMIES_PER_WAVE: u32 = 2;
+const FIRST_WAVE_HEALTH: f32 = 50.0;
+/// Each wave, enemies get this fraction of `FIRST_WAVE_HEALTH` extra health.
+const EXTRA_HEALTH_PER_WAVE: f32 = 0.25;
+const MIN_SPAWN_DISTANCE: f32 = 20.0;
+const MAX_SPAWN_DISTANCE: f32 = 40.0;
+/// How many random positions to try before giving up on spawning an enemy.
+const SPAWN_ATTEMPTS: usize = 10;
+
+/// Players work together to survive waves of worms. Each cleared wave scores
+/// a point for the players, and the round is won by clearing `score_limit` waves.
+pub struct Waves {
+    pub waves_to_win: u32,
+    pub time_limit: Duration,
+}
+
+impl Default for Waves {
+    fn default() -> Self {
+        Self {
+            waves_to_win: 10,
+            time_limit: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+impl GameMode for Waves {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::Waves
+    }
+
+    fn score_limit(&self) -> u32 {
+        self.waves_to_win
+    }
+
+    fn time_limit(&self) -> Duration {
+        self.time_limit
+    }
+
+    fn assign_team(&self, _teams: &[Team]) -> Option<Team> {
+        Some(PLAYER_TEAM)
+    }
+
+    /// Players can only damage things not on their team, i.e. the worms.
+    fn can_damage(&self, attacker: Option<Team>, victim: Option<Team>) -> bool {
+        attacker != victim
+    }
+
+    fn scorer(&self, _killer: &NetworkObject, _killer_team: Option<Team>) -> Scorer {
+        Scorer::Team(PLAYER_TEAM)
+    }
+
+    /// Running out of time means the players didn't survive.
+    fn time_limit_winner(&self, _state: &GameModeState) -> Option<Scorer> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WaveInfo {
+    /// Zero before the first wave.
+    pub wave: u32,
+    pub remaining_enemies: u32,
+    /// Time until the next wave starts. `None` while a wave is being fought.
+    pub next_wave_in: Option<Duration>,
+}
+
+/// Marks worms that were spawned as part of a wave.
+#[derive(Component)]
+pub struct WaveEnemy;
+
+pub struct WaveState {
+    wave: u32,
+    /// Set between waves.
+    next_wave_timer: Option<Timer>,
+}
+
+impl Default for WaveState {
+    fn default() -> Self {
+        Self {
+            wave: 0,
+            next_wave_timer: Some(Timer::new(TIME_BETWEEN_WAVES, TimerMode::Once)),
+        }
+    }
+}
+
+fn enemies_in_wave(wave: u32) -> u32 {
+    FIRST_WAVE_ENEMIES + EXTRA_ENEMIES_PER_WAVE * wave.saturating_sub(1)
+}
+
+fn enemy_health_in_wave(wave: u32) -> f32 {
+    FIRST_WAVE_HEALTH * (1.0 + EXTRA_HEALTH_PER_WAVE * wave.saturating_sub(1) as f32)
+}
+
+/// Picks a position around a random player that is on a loaded chunk, just
+/// above the ground.
+fn pick_spawn_position(
+    players: &[Vec3],
+    loaded_chunks: &HashSet<IVec2>,
+    terrain: &Terrain,
+) -> Option<Vec3> {
+    let mut rng = rand::thread_rng();
+    for _ in 0..SPAWN_ATTEMPTS {
+        let around = players[rng.gen_range(0..players.len())];
+        let angle = rng.gen_range(0.0..TAU);
+        let distance = rng.gen_range(MIN_SPAWN_DISTANCE..MAX_SPAWN_DISTANCE);
+        let position = around + Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+        if loaded_chunks.contains(&terrain.world_position_to_chunk(position)) {
+            let height = terrain.height_at(position.x, position.z);
+            return Some(Vec3::new(position.x, height + 1.0, position.z));
+        }
+    }
+    None
+}
+
+/// Spawns waves of worms while the round is in progress, and scores a point
+/// for the players whenever a wave is cleared.
+pub fn run_waves(
+    mut wave_state: Local<WaveState>,
+    mut game_mode: ResMut<ActiveGameMode>,
+    mut server: ResMut<RenetServer>,
+    mut commands: Commands,
+    mut snap_to_floor: EventWriter<SnapToFloor>,
+    enemies: Query<(Entity, &NetworkObject), With<WaveEnemy>>,
+    players: Query<&Transform, With<Player>>,
+    chunks: Query<&Chunk<TerrainChunkData>>,
+    terrain: Option<Res<Terrain>>,
+    time: Res<Time>,
+    tick: Res<Tick>,
+) {
+    if game_mode.state.phase != GamePhase::InProgress {
+        // Clean up the last round before the next one starts.
+        if wave_state.wave != 0 || game_mode.state.waves.is_some() {
+            for (entity, net_obj) in enemies.iter() {
+                despawn_recursive_and_broadcast(
+                    &mut server,
+                    &mut commands,
+                    entity,
+                    net_obj.clone(),
+                );
+            }
+            *wave_state = WaveState::default();
+            game_mode.set_waves(None);
+        }
+        return;
+    }
+
+    let remaining_enemies = enemies.iter().count() as u32;
+    let next_wave = wave_state.wave + 1;
+    match wave_state.next_wave_timer.as_mut() {
+        Some(timer) => {
+            timer.tick(time.delta());
+            if timer.finished() {
+                let spawned = spawn_wave(
+                    next_wave,
+                    &mut commands,
+                    &mut snap_to_floor,
+                    &players,
+                    &chunks,
+                    terrain.as_deref(),
+                    *tick,
+                );
+                if spawned > 0 {
+                    wave_state.next_wave_timer = None;
+                    wave_state.wave = next_wave;
+                } else {
+                    // An empty wave would count as cleared right away, so the
+                    // wave is held back until its enemies can spawn.
+                    *timer = Timer::new(WAVE_RETRY_DELAY, TimerMode::Once);
+                }
+            }
+        }
+        None => {
+            if remaining_enemies == 0 {
+                info!("wave {} cleared", wave_state.wave);
+                game_mode.add_score(Scorer::Team(PLAYER_TEAM), 1);
+                wave_state.next_wave_timer = Some(Timer::new(TIME_BETWEEN_WAVES, TimerMode::Once));
+            }
+        }
+    }
+
+    game_mode.set_waves(Some(WaveInfo {
+        wave: wave_state.wave,
+        remaining_enemies,
+        next_wave_in: wave_state
+            .next_wave_timer
+            .as_ref()
+            .map(|timer| timer.remaining()),
+    }));
+}
+
+fn spawn_wave(
+    wave: u32,
+    commands: &mut Commands,
+    snap_to_floor: &mut EventWriter<SnapToFloor>,
+    players: &Query<&Transform, With<Player>>,
+    chunks: &Query<&Chunk<TerrainChunkData>>,
+    terrain: Option<&Terrain>,
+    tick: Tick,
+) -> u32 {
+    let Some(terrain) = terrain else {
+        warn!("no terrain to spawn wave on");
+        return 0;
+    };
+    let players: Vec<Vec3> = players.iter().map(|t| t.translation).collect();
+    if players.is_empty() {
+        return 0;
+    }
+    let loaded_chunks: HashSet<IVec2> = chunks.iter().map(|chunk| chunk.meta.position).collect();
+
+    let count = enemies_in_wave(wave);
+    let health = enemy_health_in_wave(wave);
+    info!("spawning wave {wave} with {count} enemies");
+    let mut spawned = 0;
+    for _ in 0..count {
+        let Some(position) = pick_spawn_position(&players, &loaded_chunks, terrain) else {
+            warn!("couldn't find a loaded chunk to spawn enemy on");
+            continue;
+        };
+        let entity = commands
+            .spawn((
+                Worm::new(health, tick),
+                WaveEnemy,
+                SpatialBundle::from_transform(Transform::from_translation(position)),
+                NetworkObject::new_rand(),
+            ))
+            .id();
+        snap_to_floor.send(SnapToFloor::new(entity));
+        spawned += 1;
+    }
+    spawned
+}
@@ -1,30 +1,40 @@
 use bevy::prelude::*;
 use bevy_renet::renet::{DefaultChannel, RenetServer};
 use objects::{gun::GunPlugin, health::HealthPlugin, tracer::TracerPlugin};
-use proc::TerrainPlugin;
+use proc::{edits::TerrainEditPlugin, tree::TreeNetworkPlugin, TerrainPlugin};
 
 use crate::message::{client::MessageReaderOnClient, server::ReliableMessageFromServer};
 
 use self::{
+    chat::ChatPlugin,
     console::ConsolePlugin,
     objects::{
-        gizmo::GizmoPlugin, grounded::GroundedPlugin, player::PlayerPlugin, worm::WormPlugin,
-        NetworkObject,
+        gizmo::GizmoPlugin, grounded::GroundedPlugin, physics_prop::PhysicsPropPlugin,
+        player::PlayerPlugin, worm::WormPlugin, NetworkObject,
     },
     physics::PhysicsPlugin,
+    relevancy::RelevancyPlugin,
 };
 
+pub mod chat;
 pub mod console;
+pub mod game_mode;
 pub mod ik;
 pub mod objects;
 pub mod physics;
 pub mod proc;
+pub mod relevancy;
 pub mod render;
 pub mod scenes;
 pub mod tick;
 
 pub const SERVER_ADDR: &str = "127.0.0.1:5000";
 
+/// Marks entities that stay when the client leaves a game. Everything else is
+/// despawned.
+#[derive(Component)]
+pub struct Persistent;
+
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AppState {
     MainMenu,
@@ -93,7 +103,12 @@ impl Plugin for Game {
                 debug: self.is_server,
             },
             GizmoPlugin,
-            ConsolePlugin,
+            ConsolePlugin {
+                is_server: self.is_server,
+            },
+            ChatPlugin {
+                is_server: self.is_server,
+            },
             GroundedPlugin,
             TerrainPlugin,
             GunPlugin,
@@ -104,11 +119,21 @@ impl Plugin for Game {
             WormPlugin {
                 is_server: self.is_server,
             },
+            TreeNetworkPlugin {
+                is_server: self.is_server,
+            },
+            TerrainEditPlugin {
+                is_server: self.is_server,
+            },
+            PhysicsPropPlugin {
+                is_server: self.is_server,
+            },
         ));
         if !self.is_server {
             app.add_systems(FixedUpdate, despawn.in_set(GameLogic::Spawn));
         } else {
             app.init_resource::<IsServer>();
+            app.add_plugins(RelevancyPlugin);
         }
         app.configure_sets(
             FixedUpdate,
@@ -137,7 +162,8 @@ pub fn despawn_recursive_and_broadcast(
 ) {
     let message = ReliableMessageFromServer::Despawn(net_obj);
     let bytes = bincode::serialize(&message).unwrap();
-    server.broadcast_message(DefaultChannel::ReliableUnordered, bytes);
+    // Same channel as spawns, so the two stay in order.
+    server.broadcast_message(DefaultChannel::ReliableOrdered, bytes);
     commands.entity(entity).despawn_recursive();
 }
 
@@ -6,7 +6,7 @@ use crate::{
         client::MessageReaderOnClient,
         server::{HealthSync, ReliableMessageFromServer},
     },
-    shared::{tick::Tick, GameLogic},
+    shared::{proc::tree::Tree, relevancy::Relevancy, tick::Tick, GameLogic},
 };
 
 use super::{LastSyncTracker, NetworkObject};
@@ -21,6 +21,20 @@ impl Health {
     pub fn new(max: f32) -> Self {
         Self { current: max, max }
     }
+
+    /// Removes `amount` of health. Returns true if this damage killed it.
+    pub fn take_damage(&mut self, amount: f32) -> bool {
+        let was_alive = self.current > 0.0;
+        self.current = (self.current - amount).max(0.0);
+        was_alive && self.current <= 0.0
+    }
+}
+
+/// Sent on the server when something's health is brought to zero.
+#[derive(Event)]
+pub struct Killed {
+    pub killer: NetworkObject,
+    pub victim: NetworkObject,
 }
 
 pub struct HealthPlugin {
@@ -30,6 +44,7 @@ pub struct HealthPlugin {
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
         if self.is_server {
+            app.add_event::<Killed>();
             app.add_systems(FixedUpdate, send_health.in_set(GameLogic::Sync));
         } else {
             app.add_systems(FixedUpdate, sync_health.in_set(GameLogic::Sync));
@@ -54,9 +69,13 @@ fn sync_health(
     }
 }
 
+/// Sends health to the clients near each entity, and to the player it
+/// belongs to.
 fn send_health(
     mut server: ResMut<RenetServer>,
-    query: Query<(&NetworkObject, &Health)>,
+    // Trees only matter once they're cut down, which is sent on its own.
+    query: Query<(&NetworkObject, &Health), Without<Tree>>,
+    relevancy: Res<Relevancy>,
     tick: Res<Tick>,
 ) {
     for (net_obj, health) in query.iter() {
@@ -66,6 +85,8 @@ fn send_health(
             health: health.current,
         });
         let bytes = bincode::serialize(&message).unwrap();
-        server.broadcast_message(DefaultChannel::ReliableUnordered, bytes);
+        for client_id in relevancy.clients_for(net_obj, *tick) {
+            server.send_message(client_id, DefaultChannel::ReliableUnordered, bytes.clone());
+        }
     }
 }
@@ -9,6 +9,7 @@ pub mod gizmo;
 pub mod grounded;
 pub mod gun;
 pub mod health;
+pub mod physics_prop;
 pub mod player;
 pub mod tracer;
 pub mod worm;
@@ -33,6 +34,11 @@ impl NetworkObject {
     }
 }
 
+/// Marks entities the server sends to clients. They're spawned on a client
+/// when they come near it, using the `SpawnSerializers` entry for their kind.
+#[derive(Component)]
+pub struct Replicated;
+
 #[derive(Component, Clone, Debug)]
 pub struct LastSyncTracker<T> {
     _component: PhantomData<T>,
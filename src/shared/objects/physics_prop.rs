@@ -0,0 +1,402 @@
+use bevy::{
+    color::palettes::css::{BURLYWOOD, ORANGE_RED},
+    ecs::{
+        component::{ComponentHooks, StorageType},
+        world::EntityRef,
+    },
+    prelude::*,
+    utils::HashSet,
+};
+use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::{DefaultChannel, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{
+        client::MessageReaderOnClient,
+        server::{PhysicsPropSync, ReliableMessageFromServer, UnreliableMessageFromServer},
+        spawn::{NetworkSpawn, PhysicsPropSpawn, SpawnAppExt},
+    },
+    shared::{
+        proc::{
+            chunk::Chunk,
+            terrain::{Terrain, TerrainChunkData},
+        },
+        relevancy::Relevancy,
+        tick::Tick,
+        GameLogic, IsServer,
+    },
+};
+
+use super::{player::server::ShotHit, LastSyncTracker, NetworkObject, Replicated};
+
+pub struct PhysicsPropPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for PhysicsPropPlugin {
+    fn build(&self, app: &mut App) {
+        if self.is_server {
+            app.add_spawn_serializer(serialize_physics_prop);
+            app.add_systems(
+                FixedUpdate,
+                (
+                    setup.in_set(GameLogic::Spawn),
+                    send_physics_props.in_set(GameLogic::Sync),
+                    (push_shot_physics_props, settle_physics_props).in_set(GameLogic::Game),
+                ),
+            );
+        } else {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    spawn_physics_props.in_set(GameLogic::Spawn),
+                    recv_physics_props.in_set(GameLogic::Sync),
+                    follow_targets.in_set(GameLogic::Game),
+                ),
+            );
+        }
+    }
+}
+
+const BALL_RADIUS: f32 = 0.5;
+const CRATE_HALF_SIZE: f32 = 0.5;
+/// Impulse given to a prop by each point of damage a shot would do.
+const SHOT_IMPULSE_PER_DAMAGE: f32 = 0.3;
+/// Props moving slower than this (in m/s and rad/s) are at rest.
+const REST_SPEED: f32 = 0.05;
+/// Props at rest are only sent this often, in ticks.
+const REST_SYNC_INTERVAL: u64 = 64;
+/// Props this far below the terrain fell through it and are put back.
+const FALL_DEPTH: f32 = 2.0;
+/// How far above the terrain props are held while the chunk under them isn't
+/// loaded.
+const HOLD_HEIGHT: f32 = 1.0;
+/// Fraction of the distance to the synced position that client props move
+/// each tick.
+const SMOOTHING: f32 = 0.3;
+/// Client props further than this from the synced position jump to it.
+const SNAP_DISTANCE: f32 = 2.0;
+
+/// Where the server puts props when it starts, around the origin.
+const PHYSICS_PROP_LAYOUT: [(PhysicsPropKind, Vec2); 6] = [
+    (PhysicsPropKind::Ball, Vec2::new(4.0, 2.0)),
+    (PhysicsPropKind::Ball, Vec2::new(-3.0, 5.0)),
+    (PhysicsPropKind::Crate, Vec2::new(6.0, -2.0)),
+    (PhysicsPropKind::Crate, Vec2::new(6.0, -3.5)),
+    (PhysicsPropKind::Crate, Vec2::new(-5.0, -4.0)),
+    (PhysicsPropKind::Ball, Vec2::new(0.0, -6.0)),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicsPropKind {
+    Ball,
+    Crate,
+}
+
+impl PhysicsPropKind {
+    fn collider(&self) -> Collider {
+        match self {
+            Self::Ball => Collider::ball(BALL_RADIUS),
+            Self::Crate => Collider::cuboid(CRATE_HALF_SIZE, CRATE_HALF_SIZE, CRATE_HALF_SIZE),
+        }
+    }
+
+    fn mesh(&self) -> Mesh {
+        match self {
+            Self::Ball => Sphere::new(BALL_RADIUS).mesh().build(),
+            Self::Crate => Cuboid::from_length(CRATE_HALF_SIZE * 2.0).into(),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Self::Ball => ORANGE_RED.into(),
+            Self::Crate => BURLYWOOD.into(),
+        }
+    }
+
+    /// Balls are light enough to kick around, crates take some effort.
+    fn density(&self) -> f32 {
+        match self {
+            Self::Ball => 0.5,
+            Self::Crate => 2.0,
+        }
+    }
+}
+
+/// An object that's simulated by the physics engine on the server. Clients
+/// only move it towards where the server says it is.
+pub struct PhysicsProp {
+    kind: PhysicsPropKind,
+    spawn_tick: Tick,
+}
+
+impl PhysicsProp {
+    pub fn new(kind: PhysicsPropKind, spawn_tick: Tick) -> Self {
+        Self { kind, spawn_tick }
+    }
+}
+
+impl Component for PhysicsProp {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+
+    fn register_component_hooks(hooks: &mut ComponentHooks) {
+        hooks.on_add(|mut world, entity, _component_id| {
+            let is_server = world.get_resource::<IsServer>().is_some();
+            let prop = world.get::<PhysicsProp>(entity).unwrap();
+            let kind = prop.kind;
+            let spawn_tick = prop.spawn_tick;
+            let asset_server = world.resource::<AssetServer>();
+            let mesh = asset_server.add(kind.mesh());
+            let material = asset_server.add(StandardMaterial {
+                base_color: kind.color(),
+                ..default()
+            });
+            if is_server {
+                let body = match world.get::<Frozen>(entity) {
+                    Some(_) => RigidBody::Fixed,
+                    None => RigidBody::Dynamic,
+                };
+                world.commands().entity(entity).insert((
+                    Replicated,
+                    mesh,
+                    material,
+                    body,
+                    kind.collider(),
+                    ColliderMassProperties::Density(kind.density()),
+                    Velocity::default(),
+                    ExternalImpulse::default(),
+                    Damping {
+                        linear_damping: 0.1,
+                        angular_damping: 0.5,
+                    },
+                    Sleeping::default(),
+                ));
+            } else {
+                // Kinematic, so players still bump into props locally but
+                // only the server simulates them.
+                world.commands().entity(entity).insert((
+                    mesh,
+                    material,
+                    RigidBody::KinematicPositionBased,
+                    kind.collider(),
+                    LastSyncTracker::<Transform>::new(spawn_tick),
+                ));
+            }
+        });
+    }
+}
+
+/// Held above the terrain and not simulated, because the chunk under the prop
+/// has no collider for it to land on yet.
+#[derive(Component)]
+struct Frozen;
+
+/// The last state of a prop received from the server, moved along by its
+/// velocity until the next one arrives.
+#[derive(Component)]
+struct PhysicsPropTarget {
+    translation: Vec3,
+    rotation: Quat,
+    linvel: Vec3,
+    angvel: Vec3,
+}
+
+#[derive(Default)]
+struct DidRun(bool);
+
+fn setup(mut did_run: Local<DidRun>, tick: Res<Tick>, mut commands: Commands) {
+    if did_run.0 {
+        return;
+    }
+    did_run.0 = true;
+
+    for (kind, position) in PHYSICS_PROP_LAYOUT {
+        commands.spawn((
+            PhysicsProp::new(kind, *tick),
+            Frozen,
+            SpatialBundle::from_transform(Transform::from_xyz(position.x, 0.0, position.y)),
+            NetworkObject::new_rand(),
+        ));
+    }
+}
+
+/// Holds props over chunks that aren't loaded, and lets them go once the
+/// chunk is. Props that fell through the terrain are put back on top of it.
+fn settle_physics_props(
+    mut props: Query<(Entity, &mut Transform, &mut Velocity, Option<&Frozen>), With<PhysicsProp>>,
+    chunks: Query<&Chunk<TerrainChunkData>>,
+    terrain: Res<Terrain>,
+    mut commands: Commands,
+) {
+    let loaded: HashSet<IVec2> = chunks.iter().map(|chunk| chunk.meta.position).collect();
+    for (entity, mut t, mut velocity, frozen) in props.iter_mut() {
+        let is_loaded = loaded.contains(&terrain.world_position_to_chunk(t.translation));
+        let floor = terrain.height_at(t.translation.x, t.translation.z);
+        match (frozen.is_some(), is_loaded) {
+            (true, true) => {
+                commands
+                    .entity(entity)
+                    .remove::<Frozen>()
+                    .insert(RigidBody::Dynamic);
+            }
+            (true, false) => {
+                t.translation.y = floor + HOLD_HEIGHT;
+            }
+            (false, is_loaded) => {
+                if is_loaded && t.translation.y > floor - FALL_DEPTH {
+                    continue;
+                }
+                t.translation.y = floor + HOLD_HEIGHT;
+                *velocity = Velocity::zero();
+                if !is_loaded {
+                    commands.entity(entity).insert((Frozen, RigidBody::Fixed));
+                }
+            }
+        }
+    }
+}
+
+/// Knocks shot props away from the shooter, at the point they were hit.
+fn push_shot_physics_props(
+    mut shots: EventReader<ShotHit>,
+    mut props: Query<(&NetworkObject, &Transform, &mut ExternalImpulse), With<PhysicsProp>>,
+) {
+    for shot in shots.read() {
+        for (net_obj, t, mut impulse) in props.iter_mut() {
+            if *net_obj != shot.target {
+                continue;
+            }
+            let point = t.translation + shot.relative_position;
+            let direction = (point - shot.origin).normalize_or_zero();
+            let hit = ExternalImpulse::at_point(
+                direction * shot.gun_type.damage() * SHOT_IMPULSE_PER_DAMAGE,
+                point,
+                t.translation,
+            );
+            impulse.impulse += hit.impulse;
+            impulse.torque_impulse += hit.torque_impulse;
+        }
+    }
+}
+
+fn is_at_rest(velocity: &Velocity) -> bool {
+    velocity.linvel.length() < REST_SPEED && velocity.angvel.length() < REST_SPEED
+}
+
+/// Sends props to the clients near them. Props at rest rarely change, so
+/// they're only sent now and then in case a message was lost.
+fn send_physics_props(
+    mut server: ResMut<RenetServer>,
+    props: Query<(&NetworkObject, &Transform, &Velocity), With<PhysicsProp>>,
+    relevancy: Res<Relevancy>,
+    tick: Res<Tick>,
+) {
+    for (net_obj, t, velocity) in props.iter() {
+        if is_at_rest(velocity) && tick.get() % REST_SYNC_INTERVAL != 0 {
+            continue;
+        }
+        let message = UnreliableMessageFromServer::PhysicsPropSync(PhysicsPropSync {
+            net_obj: net_obj.clone(),
+            translation: t.translation,
+            rotation: t.rotation,
+            linvel: velocity.linvel,
+            angvel: velocity.angvel,
+            tick: *tick,
+        });
+        let bytes = bincode::serialize(&message).unwrap();
+        for client_id in relevancy.clients_for(net_obj, *tick) {
+            server.send_message(client_id, DefaultChannel::Unreliable, bytes.clone());
+        }
+    }
+}
+
+fn recv_physics_props(
+    reader: Res<MessageReaderOnClient>,
+    mut props: Query<(
+        &NetworkObject,
+        &mut PhysicsPropTarget,
+        &mut LastSyncTracker<Transform>,
+    )>,
+) {
+    for msg in reader.unreliable_messages() {
+        let UnreliableMessageFromServer::PhysicsPropSync(sync) = msg else {
+            continue;
+        };
+        for (obj, mut target, mut tracker) in props.iter_mut() {
+            if *obj == sync.net_obj && tracker.should_update(sync.tick) {
+                *target = PhysicsPropTarget {
+                    translation: sync.translation,
+                    rotation: sync.rotation,
+                    linvel: sync.linvel,
+                    angvel: sync.angvel,
+                };
+            }
+        }
+    }
+}
+
+/// Moves the target of each prop along by its velocity, and the prop smoothly
+/// towards its target.
+fn follow_targets(mut props: Query<(&mut Transform, &mut PhysicsPropTarget)>, time: Res<Time>) {
+    let delta = time.delta_seconds();
+    for (mut t, mut target) in props.iter_mut() {
+        let linvel = target.linvel;
+        let angvel = target.angvel;
+        target.translation += linvel * delta;
+        if angvel.length_squared() > 0.0 {
+            target.rotation =
+                (Quat::from_scaled_axis(angvel * delta) * target.rotation).normalize();
+        }
+        if t.translation.distance(target.translation) > SNAP_DISTANCE {
+            t.translation = target.translation;
+            t.rotation = target.rotation;
+        } else {
+            t.translation = t.translation.lerp(target.translation, SMOOTHING);
+            t.rotation = t.rotation.slerp(target.rotation, SMOOTHING);
+        }
+    }
+}
+
+fn serialize_physics_prop(entity: EntityRef) -> Option<NetworkSpawn> {
+    let prop = entity.get::<PhysicsProp>()?;
+    let velocity = entity.get::<Velocity>().copied().unwrap_or_default();
+    Some(NetworkSpawn::PhysicsProp(PhysicsPropSpawn {
+        kind: prop.kind,
+        transform: *entity.get::<Transform>()?,
+        linvel: velocity.linvel,
+        angvel: velocity.angvel,
+    }))
+}
+
+/// Handles `Spawn` events from the server and spawns props. Props that are
+/// already spawned are skipped.
+fn spawn_physics_props(
+    reader: Res<MessageReaderOnClient>,
+    existing: Query<&NetworkObject>,
+    mut commands: Commands,
+) {
+    for msg in reader.reliable_messages() {
+        let ReliableMessageFromServer::Spawn(spawn) = msg else {
+            continue;
+        };
+        let NetworkSpawn::PhysicsProp(prop_spawn) = &spawn.net_spawn else {
+            continue;
+        };
+        if existing.iter().any(|net_obj| *net_obj == spawn.net_obj) {
+            continue;
+        }
+        commands.spawn((
+            PhysicsProp::new(prop_spawn.kind, spawn.tick),
+            PhysicsPropTarget {
+                translation: prop_spawn.transform.translation,
+                rotation: prop_spawn.transform.rotation,
+                linvel: prop_spawn.linvel,
+                angvel: prop_spawn.angvel,
+            },
+            SpatialBundle::from_transform(prop_spawn.transform),
+            spawn.net_obj.clone(),
+        ));
+    }
+}
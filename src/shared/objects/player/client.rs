@@ -21,28 +21,45 @@ use crate::{
         spawn::NetworkSpawn,
     },
     shared::{
+        console::{
+            cvar::{CVarValue, CVars},
+            ConsoleAppExt,
+        },
         objects::{
             grounded::Grounded,
             gun::{Gun, GunType, LocalPlayerGun},
             player::PlayerHead,
             tracer::Tracer,
+            worm::WormSegment,
             LastSyncTracker, NetworkObject,
         },
         physics::apply_kinematics,
+        proc::{tree::TreeCollider, water::ignore_water},
         GameLogic,
     },
     utils,
 };
 
 use super::{
-    spawn::PlayerSpawnRequest, Input, LocalPlayer, LocalPlayerTag, Player, PlayerKinematics, Shot,
-    ShotNothing, ShotPosition, ShotTarget, ShotType,
+    name::NameplatePlugin, spawn::PlayerSpawnRequest, Input, LocalPlayer, LocalPlayerTag, Player,
+    PlayerKinematics, Shot, ShotNothing, ShotPosition, ShotTarget, ShotType,
 };
 
 pub struct PlayerClientPlugin;
 
 impl Plugin for PlayerClientPlugin {
     fn build(&self, app: &mut App) {
+        app.add_plugins(NameplatePlugin);
+        app.add_cvar(
+            "sensitivity",
+            CVarValue::Float(1.0),
+            "Multiplies how far the mouse turns the camera.",
+        )
+        .add_cvar(
+            "fov",
+            CVarValue::Float(60.0),
+            "Vertical field of view in degrees, widened while sprinting.",
+        );
         app.insert_resource(InputBuffer::default());
         app.insert_resource(TickBuffer::<PlayerSnapshot>::default());
         app.add_systems(
@@ -178,6 +195,7 @@ pub fn rotate_player(
     mut player: Query<(&mut Transform, Entity), (With<LocalPlayerTag>, Without<PlayerHead>)>,
     mut player_head: Query<(&mut Transform, &Parent), With<PlayerHead>>,
     q_windows: Query<&Window, With<PrimaryWindow>>,
+    cvars: Res<CVars>,
 ) {
     let primary_window = q_windows.single();
     if primary_window.cursor.grab_mode != CursorGrabMode::Locked {
@@ -201,10 +219,11 @@ pub fn rotate_player(
     let pitch_max = 85_f32.to_radians();
 
     let mut current_pitch = player_head_t.rotation.to_euler(EulerRot::XYZ).0;
+    let sensitivity = cvars.get::<f32>("sensitivity").unwrap_or(1.0);
 
     for motion in mouse_motion.read() {
-        let yaw = -motion.delta.x * 0.003;
-        let pitch = -motion.delta.y * 0.002;
+        let yaw = -motion.delta.x * 0.003 * sensitivity;
+        let pitch = -motion.delta.y * 0.002 * sensitivity;
 
         player_t.rotate_y(yaw);
         current_pitch = (current_pitch + pitch).clamp(pitch_min, pitch_max);
@@ -274,7 +293,9 @@ pub fn read_input(
     camera: Query<Entity, With<PlayerCamera>>,
     mut local_gun: Query<&mut Gun, With<LocalPlayerGun>>,
     global_transform_query: Query<&GlobalTransform>,
-    net_objs: Query<(&NetworkObject, &Transform)>,
+    net_objs: Query<(&NetworkObject, &GlobalTransform)>,
+    worm_segments: Query<&WormSegment>,
+    tree_colliders: Query<&TreeCollider>,
     mut client: ResMut<RenetClient>,
     context: Res<RapierContext>,
     mut commands: Commands,
@@ -307,6 +328,8 @@ pub fn read_input(
                     cam_global_t,
                     bullet_point_global_t,
                     &net_objs,
+                    &worm_segments,
+                    &tree_colliders,
                     gun_type,
                 ),
                 None => None,
@@ -322,6 +345,7 @@ pub fn read_input(
         direction: world_direction_xz.normalize_or_zero(),
         sprint: keyboard_input.pressed(KeyCode::ShiftLeft),
         jump: keyboard_input.pressed(KeyCode::Space),
+        crouch: keyboard_input.pressed(KeyCode::ControlLeft),
         shot,
     };
     let order = ibuf.push_input(input.clone());
@@ -362,7 +386,9 @@ fn get_shot(
     shooter: Entity,
     camera: &GlobalTransform,
     bullet_point: &GlobalTransform,
-    net_objs: &Query<(&NetworkObject, &Transform)>,
+    net_objs: &Query<(&NetworkObject, &GlobalTransform)>,
+    worm_segments: &Query<&WormSegment>,
+    tree_colliders: &Query<&TreeCollider>,
     gun_type: GunType,
 ) -> Option<Shot> {
     let bullet_range = gun_type.range();
@@ -379,7 +405,9 @@ fn get_shot(
         cam_ray_dir,
         cam_range,
         false,
-        QueryFilter::default().exclude_collider(shooter),
+        QueryFilter::default()
+            .exclude_collider(shooter)
+            .groups(ignore_water()),
     );
     let cam_hit_point = cam_ray_pos
         + match cam_raycast {
@@ -395,7 +423,9 @@ fn get_shot(
         bullet_ray_dir,
         bullet_range,
         false,
-        QueryFilter::default().exclude_collider(shooter),
+        QueryFilter::default()
+            .exclude_collider(shooter)
+            .groups(ignore_water()),
     );
     let shot_type = match raycast {
         Some((entity, toi)) => {
@@ -406,9 +436,17 @@ fn get_shot(
                 SpatialBundle::from_transform(Transform::from_translation(bullet_ray_pos)),
             ));
             let impact_point = bullet_ray_pos + (bullet_ray_dir * toi);
+            // Worm segments and tree colliders aren't network objects, the
+            // shot goes to the worm's head or the tree.
+            let entity = worm_segments
+                .get(entity)
+                .map_or(entity, |segment| segment.worm);
+            let entity = tree_colliders
+                .get(entity)
+                .map_or(entity, |collider| collider.tree);
             match net_objs.get(entity).ok() {
                 Some((obj, transform)) => {
-                    let relative_position = impact_point - transform.translation;
+                    let relative_position = impact_point - transform.translation();
                     Some(ShotType::ShotTarget(ShotTarget {
                         target: obj.clone(),
                         relative_position,
@@ -445,7 +483,7 @@ pub fn recv_player_shot(
     player_head_query: Query<(&Parent, Entity), With<PlayerHead>>,
     gun_query: Query<(&Parent, &Gun)>,
     global_transform_query: Query<&GlobalTransform>,
-    net_obj_query: Query<(&NetworkObject, &Transform)>,
+    net_obj_query: Query<(&NetworkObject, &GlobalTransform)>,
 ) {
     for msg in reader.unreliable_messages() {
         let UnreliableMessageFromServer::PlayerShot(shooter, shot) = msg else {
@@ -509,7 +547,7 @@ pub fn recv_player_shot(
                     error!("tried to recv shot for non existant: {:?}", shot);
                     continue;
                 };
-                let target_shot_pos = target_pos.translation + shot.relative_position;
+                let target_shot_pos = target_pos.translation() + shot.relative_position;
                 let ray = target_shot_pos - shooter_pos;
                 commands.spawn((
                     Tracer {
@@ -535,11 +573,13 @@ pub fn spawn_players(
         if spawn.net_obj == local_player.0 {
             continue;
         };
-        if let NetworkSpawn::Player(transform) = spawn.net_spawn {
+        if let NetworkSpawn::Player(player_spawn) = &spawn.net_spawn {
             player_spawn_requests.send(PlayerSpawnRequest::Remote(
-                transform,
+                player_spawn.transform,
                 spawn.net_obj.clone(),
                 spawn.tick.clone(),
+                player_spawn.team,
+                player_spawn.name.clone(),
             ));
         }
     }
@@ -552,7 +592,6 @@ pub struct LocalPlayerQueryForSync {
     transform: &'static mut Transform,
     net_obj: &'static NetworkObject,
     last_sync_tracker: &'static mut LastSyncTracker<Transform>,
-    collider: &'static Collider,
     controller: &'static KinematicCharacterController,
     grounded: &'static mut Grounded,
     player: &'static mut Player,
@@ -565,6 +604,7 @@ pub struct NonLocalPlayers {
     transform: &'static mut Transform,
     net_obj: &'static NetworkObject,
     last_sync_tracker: &'static mut LastSyncTracker<Transform>,
+    player: &'static mut Player,
 }
 
 /// Receives player synchronization events. For `PlayerPositionSync`, this just
@@ -619,6 +659,7 @@ fn sync_nonlocal(
         if is_same_player && is_most_recent {
             player.last_sync_tracker.last_tick = pos_sync.tick.clone();
             player.transform.translation = pos_sync.translation;
+            player.player.kinematics.crouching = pos_sync.crouching;
             utils::transform::set_body_rotation_pitch(
                 &mut player.transform,
                 pos_sync.body_rotation,
@@ -669,7 +710,6 @@ fn check_and_rollback(
             context,
             &input.input,
             &mut record.transform,
-            record.collider,
             record.controller,
             &time,
             record.entity,
@@ -681,8 +721,11 @@ fn check_and_rollback(
             record.entity,
             record.controller,
             &mut record.transform,
-            record.collider,
-            record.player.kinematics.get_velocity(),
+            &super::player_collider(record.player.kinematics.crouching),
+            record
+                .player
+                .kinematics
+                .get_velocity(&record.player.profile),
             Some(&mut record.grounded),
             time.delta_seconds(),
         );
@@ -700,7 +743,6 @@ fn check_and_rollback(
 pub struct LocalPlayerQuery {
     entity: Entity,
     transform: &'static mut Transform,
-    collider: &'static Collider,
     controller: &'static KinematicCharacterController,
     grounded: &'static mut Grounded,
     player: &'static mut Player,
@@ -736,7 +778,6 @@ pub fn predict_movement(
         &mut context,
         &input.input,
         &mut local_player.transform,
-        local_player.collider,
         local_player.controller,
         &time,
         local_player.entity,
@@ -772,8 +813,13 @@ fn sync_player_rotation(
     }
 }
 
-fn set_sprint_fov(ibuf: Res<InputBuffer>, mut proj: Query<&mut Projection, With<PlayerCamera>>) {
-    let Some(input) = ibuf.get_latest() else {
+fn set_sprint_fov(
+    local_player: Query<&Player, With<LocalPlayerTag>>,
+    mut proj: Query<&mut Projection, With<PlayerCamera>>,
+    cvars: Res<CVars>,
+) {
+    let fov = cvars.get::<f32>("fov").unwrap_or(60.0);
+    let Ok(player) = local_player.get_single() else {
         return;
     };
 
@@ -782,10 +828,10 @@ fn set_sprint_fov(ibuf: Res<InputBuffer>, mut proj: Query<&mut Projection, With<
     };
 
     if let Projection::Perspective(ref mut perspective) = *proj {
-        if input.input.sprint {
-            perspective.fov = perspective.fov.lerp(70f32.to_radians(), 0.1);
+        if player.kinematics.is_fast() {
+            perspective.fov = perspective.fov.lerp((fov + 10.0).to_radians(), 0.1);
         } else {
-            perspective.fov = perspective.fov.lerp(60f32.to_radians(), 0.1);
+            perspective.fov = perspective.fov.lerp(fov.to_radians(), 0.1);
         }
     }
 }
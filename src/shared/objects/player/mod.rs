@@ -6,18 +6,39 @@ use serde::{Deserialize, Serialize};
 use spawn::{spawn_players_from_spawn_requests, PlayerSpawnRequest};
 
 use crate::shared::{
-    physics::{char_ctrl_to_move_opts, VelocityCalculator},
+    console::{ConsoleAppExt, ConsoleCommand},
+    physics::{char_ctrl_to_move_opts, VelocityCalculator, CHARACTER_MASS},
+    proc::water::is_in_water,
     GameLogic,
 };
 
-use self::{client::PlayerClientPlugin, server::PlayerServerPlugin};
+use self::{
+    client::PlayerClientPlugin,
+    movement::{accelerate, apply_friction, MovementProfile},
+    server::PlayerServerPlugin,
+};
 
 use super::{grounded::Grounded, gun::GunType, NetworkObject};
 
 pub mod client;
+pub mod movement;
+pub mod name;
 pub mod server;
 pub mod spawn;
 
+/// Movement speed while noclipping.
+const NOCLIP_SPEED: f32 = 20.0;
+const PLAYER_RADIUS: f32 = 0.25;
+/// Half the length of the straight part of the capsule while standing.
+const STANDING_HALF_HEIGHT: f32 = 0.5;
+/// Half the length of the straight part of the capsule while crouching.
+const CROUCHING_HALF_HEIGHT: f32 = 0.2;
+const STANDING_HEAD_HEIGHT: f32 = 0.5;
+const CROUCHING_HEAD_HEIGHT: f32 = 0.0;
+/// Gap left under the standing collider when checking for room to stand up,
+/// so the floor doesn't count.
+const STAND_CHECK_CLEARANCE: f32 = 0.05;
+
 pub struct PlayerPlugin {
     pub is_server: bool,
 }
@@ -25,11 +46,29 @@ pub struct PlayerPlugin {
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<PlayerSpawnRequest>();
+        app.add_console_command(
+            ConsoleCommand::new("noclip", "Toggles flying through everything.")
+                .on_server()
+                .admin(),
+        )
+        .add_console_command(
+            ConsoleCommand::new("tp", "Teleports you, onto the ground if y is left out.")
+                .usage("<x> [y] <z>")
+                .on_server()
+                .admin(),
+        )
+        .add_console_command(
+            ConsoleCommand::new("give", "Gives you something.")
+                .usage("health [amount]")
+                .on_server()
+                .admin(),
+        );
         app.add_systems(
             FixedUpdate,
             (
                 spawn_players_from_spawn_requests.in_set(GameLogic::Spawn),
                 tick_jump_cooldown.in_set(GameLogic::Start),
+                update_crouch_shape.in_set(GameLogic::PreKinematics),
             ),
         );
 
@@ -81,6 +120,7 @@ pub struct Input {
     direction: Vec3,
     sprint: bool,
     jump: bool,
+    crouch: bool,
     shot: Option<Shot>,
 }
 
@@ -88,6 +128,7 @@ pub struct Input {
 pub struct Player {
     pub jump_cooldown_timer: Timer,
     pub kinematics: PlayerKinematics,
+    pub profile: MovementProfile,
 }
 
 /// Represents where the head (or camera target, on the client) of the player is. Guns are children of this.
@@ -95,17 +136,88 @@ pub struct Player {
 pub struct PlayerHead;
 
 impl Player {
-    fn new() -> Self {
+    fn new(profile: MovementProfile) -> Self {
         Self {
             jump_cooldown_timer: Timer::new(Duration::from_millis(200), TimerMode::Once),
-            kinematics: PlayerKinematics::default(),
+            kinematics: PlayerKinematics::new(&profile),
+            profile,
         }
     }
+
+    /// Stamina left, from 0 to 1.
+    pub fn stamina_fraction(&self) -> f32 {
+        self.kinematics.stamina / self.profile.max_stamina
+    }
 }
 
 #[derive(Component)]
 pub struct LocalPlayerTag;
 
+/// Added to players whose collider and head are lowered for crouching.
+#[derive(Component)]
+pub struct Crouched;
+
+/// The player's capsule. Crouching shortens it from the top, so the feet
+/// stay where they are.
+fn player_collider(crouching: bool) -> Collider {
+    let bottom = Vec3::NEG_Y * STANDING_HALF_HEIGHT;
+    let top = if crouching {
+        bottom + Vec3::Y * 2.0 * CROUCHING_HALF_HEIGHT
+    } else {
+        Vec3::Y * STANDING_HALF_HEIGHT
+    };
+    Collider::capsule(bottom, top, PLAYER_RADIUS)
+}
+
+fn head_height(crouching: bool) -> f32 {
+    if crouching {
+        CROUCHING_HEAD_HEIGHT
+    } else {
+        STANDING_HEAD_HEIGHT
+    }
+}
+
+/// Fits the collider and head of each player to whether it's crouching. The
+/// movement itself doesn't depend on this, `apply_input` picks the collider
+/// from `PlayerKinematics` so that rollback gets the same result.
+fn update_crouch_shape(
+    mut players: Query<(Entity, &Player, &mut Collider, &Children, Has<Crouched>)>,
+    mut heads: Query<&mut Transform, With<PlayerHead>>,
+    mut commands: Commands,
+) {
+    for (entity, player, mut collider, children, crouched) in players.iter_mut() {
+        let crouching = player.kinematics.crouching;
+        if crouching == crouched {
+            continue;
+        }
+        *collider = player_collider(crouching);
+        if crouching {
+            commands.entity(entity).insert(Crouched);
+        } else {
+            commands.entity(entity).remove::<Crouched>();
+        }
+        for child in children.iter() {
+            if let Ok(mut head_t) = heads.get_mut(*child) {
+                head_t.translation.y = head_height(crouching);
+            }
+        }
+    }
+}
+
+/// Whether there's room above a crouching player to stand up.
+fn can_stand_up(context: &RapierContext, transform: &Transform, player: Entity) -> bool {
+    context
+        .intersection_with_shape(
+            transform.translation + Vec3::Y * STAND_CHECK_CLEARANCE,
+            transform.rotation,
+            &player_collider(false),
+            QueryFilter::default()
+                .exclude_collider(player)
+                .exclude_sensors(),
+        )
+        .is_none()
+}
+
 fn tick_jump_cooldown(mut query: Query<&mut Player>, time: Res<Time>) {
     for mut player in query.iter_mut() {
         player.jump_cooldown_timer.tick(time.delta());
@@ -116,15 +228,38 @@ fn apply_input(
     context: &mut RapierContext,
     input: &Input,
     transform: &mut Transform,
-    shape: &Collider,
     char_controller: &KinematicCharacterController,
     time: &Time,
     curr_player: Entity,
     player: &mut Player,
     grounded: &mut Grounded,
 ) {
-    let speed = if input.sprint { 10.0 } else { 5.0 };
-    let movement = input.direction * speed * time.delta_seconds();
+    if player.kinematics.noclip {
+        // Jump flies up and sprint flies down, through anything.
+        let vertical = match (input.jump, input.sprint) {
+            (true, false) => Vec3::Y,
+            (false, true) => Vec3::NEG_Y,
+            _ => Vec3::ZERO,
+        };
+        transform.translation += (input.direction + vertical) * NOCLIP_SPEED * time.delta_seconds();
+        player.kinematics.velocity = Vec3::ZERO;
+        player.kinematics.update(true, false);
+        grounded.set_is_grounded(false);
+        return;
+    }
+    let in_water = is_in_water(context, transform.translation, curr_player);
+    let can_stand = !player.kinematics.crouching
+        || input.crouch
+        || can_stand_up(context, transform, curr_player);
+    player.kinematics.update_movement(
+        input,
+        &player.profile,
+        grounded.is_grounded(),
+        in_water,
+        can_stand,
+        time.delta(),
+    );
+    let movement = player.kinematics.velocity * time.delta_seconds();
     if input.jump && grounded.is_grounded() && player.jump_cooldown_timer.finished() {
         player.kinematics.update(false, true);
         player.jump_cooldown_timer.reset();
@@ -132,19 +267,28 @@ fn apply_input(
         player.kinematics.update(grounded.is_grounded(), false);
     }
     player.kinematics.tick(time.delta());
+    player.kinematics.set_in_water(in_water);
 
+    let mut move_opts = char_ctrl_to_move_opts(char_controller);
+    move_opts.max_slope_climb_angle = player.profile.max_slope_climb_angle;
+    move_opts.min_slope_slide_angle = player.profile.min_slope_slide_angle;
     let out = context.move_shape(
         movement,
-        shape,
+        &player_collider(player.kinematics.crouching),
         transform.translation,
         transform.rotation,
-        0f32,
-        &char_ctrl_to_move_opts(char_controller),
-        QueryFilter::default().exclude_collider(curr_player),
+        CHARACTER_MASS,
+        &move_opts,
+        QueryFilter::default()
+            .exclude_collider(curr_player)
+            .exclude_sensors(),
         |_| {},
     );
     transform.translation += out.effective_translation;
     grounded.set_is_grounded(out.grounded);
+    player
+        .kinematics
+        .clip_velocity(out.effective_translation, time.delta_seconds());
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -153,22 +297,156 @@ pub enum AirTime {
     Airborne(Duration),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Slide {
+    elapsed: Duration,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PlayerKinematics {
     time_in_air: AirTime,
     is_jumping: bool,
+    /// Whether the player's center is under water.
+    in_water: bool,
+    /// Flies through everything, set with the `noclip` console command.
+    pub noclip: bool,
+    /// Horizontal velocity, built up by acceleration and lost to friction.
+    velocity: Vec3,
+    crouching: bool,
+    sprinting: bool,
+    slide: Option<Slide>,
+    /// Used up by sprinting and sliding.
+    stamina: f32,
+    /// Ran out of stamina. Sprinting waits until some of it is back.
+    exhausted: bool,
 }
 
-impl Default for PlayerKinematics {
-    fn default() -> Self {
+impl PlayerKinematics {
+    pub fn new(profile: &MovementProfile) -> Self {
         Self {
             time_in_air: AirTime::Grounded,
             is_jumping: false,
+            in_water: false,
+            noclip: false,
+            velocity: Vec3::ZERO,
+            crouching: false,
+            sprinting: false,
+            slide: None,
+            stamina: profile.max_stamina,
+            exhausted: false,
         }
     }
-}
 
-impl PlayerKinematics {
+    /// Updates crouching, sliding, stamina and the horizontal velocity from an
+    /// input. Crouching while sprinting on the ground starts a slide.
+    /// `can_stand` says whether a crouching player has room to stand up.
+    pub fn update_movement(
+        &mut self,
+        input: &Input,
+        profile: &MovementProfile,
+        is_grounded: bool,
+        in_water: bool,
+        can_stand: bool,
+        delta: Duration,
+    ) {
+        let was_crouching = self.crouching;
+        self.crouching = input.crouch || (was_crouching && !can_stand);
+        let moving = input.direction != Vec3::ZERO;
+
+        let starts_slide = self.crouching
+            && !was_crouching
+            && self.sprinting
+            && is_grounded
+            && moving
+            && self.stamina >= profile.slide_stamina_cost;
+        if starts_slide {
+            self.stamina -= profile.slide_stamina_cost;
+            self.slide = Some(Slide {
+                elapsed: Duration::ZERO,
+            });
+            // Players already going faster than a slide keep their speed.
+            let speed = self.velocity.length().max(profile.slide_speed);
+            self.velocity = input.direction * speed;
+        }
+        if let Some(slide) = self.slide.as_mut() {
+            slide.elapsed += delta;
+        }
+        let slide_over = self
+            .slide
+            .as_ref()
+            .is_some_and(|slide| slide.elapsed >= profile.slide_duration);
+        if !self.crouching || slide_over {
+            self.slide = None;
+        }
+
+        if self.stamina <= 0.0 {
+            self.exhausted = true;
+        } else if self.stamina >= profile.sprint_recover_stamina {
+            self.exhausted = false;
+        }
+        self.sprinting = input.sprint && moving && !self.crouching && !self.exhausted;
+        let seconds = delta.as_secs_f32();
+        if self.sprinting {
+            self.stamina = (self.stamina - profile.sprint_stamina_drain * seconds).max(0.0);
+        } else if self.slide.is_none() {
+            self.stamina =
+                (self.stamina + profile.stamina_regen * seconds).min(profile.max_stamina);
+        }
+
+        let wish_speed = if self.sprinting {
+            profile.sprint_speed
+        } else if self.crouching {
+            profile.crouch_speed
+        } else {
+            profile.walk_speed
+        };
+        let wish_speed = if in_water {
+            wish_speed * profile.water_speed_multiplier
+        } else {
+            wish_speed
+        };
+        if self.slide.is_some() {
+            // Slides coast along, only slowed down by friction.
+            self.velocity = apply_friction(self.velocity, profile.slide_friction, 0.0, seconds);
+        } else if is_grounded || in_water {
+            self.velocity =
+                apply_friction(self.velocity, profile.friction, profile.stop_speed, seconds);
+            self.velocity = accelerate(
+                self.velocity,
+                input.direction,
+                wish_speed,
+                profile.ground_acceleration,
+                seconds,
+            );
+        } else {
+            self.velocity = accelerate(
+                self.velocity,
+                input.direction,
+                wish_speed.min(profile.max_air_speed),
+                profile.air_acceleration,
+                seconds,
+            );
+        }
+    }
+
+    /// Drops the speed that walls took away, so players slide along them
+    /// instead of building up speed against them. `moved` is how far the
+    /// player actually got this tick.
+    fn clip_velocity(&mut self, moved: Vec3, delta: f32) {
+        if delta <= 0.0 {
+            return;
+        }
+        let moved = Vec3::new(moved.x, 0.0, moved.z) / delta;
+        if moved.length_squared() < self.velocity.length_squared() {
+            self.velocity = moved;
+        }
+    }
+
+    /// Sprinting or sliding.
+    pub fn is_fast(&self) -> bool {
+        self.sprinting || self.slide.is_some()
+    }
+
     pub fn tick(&mut self, delta: Duration) {
         self.time_in_air = match self.time_in_air {
             AirTime::Grounded => AirTime::Airborne(delta),
@@ -185,13 +463,31 @@ impl PlayerKinematics {
         }
     }
 
-    pub fn get_velocity(&self) -> Vec3 {
+    /// Buoyancy replaces gravity in water. When the player gets out, they
+    /// start falling from zero speed.
+    pub fn set_in_water(&mut self, in_water: bool) {
+        self.in_water = in_water;
+        if in_water {
+            self.time_in_air = AirTime::Airborne(Duration::ZERO);
+            self.is_jumping = false;
+        }
+    }
+
+    /// Vertical velocity, from gravity, jumping and buoyancy. Horizontal
+    /// movement is done in `apply_input`.
+    pub fn get_velocity(&self, profile: &MovementProfile) -> Vec3 {
+        if self.noclip {
+            return Vec3::ZERO;
+        }
+        if self.in_water {
+            return Vec3::Y * profile.buoyancy;
+        }
         let gravity = match self.time_in_air {
-            AirTime::Airborne(duration) => Vec3::Y * -10.0 * duration.as_secs_f32(),
+            AirTime::Airborne(duration) => Vec3::Y * -profile.gravity * duration.as_secs_f32(),
             AirTime::Grounded => Vec3::ZERO,
         };
         let jump = if self.is_jumping {
-            Vec3::Y * 5.0
+            Vec3::Y * profile.jump_speed
         } else {
             Vec3::ZERO
         };
@@ -217,12 +513,46 @@ impl PlayerKinematics {
             return true;
         }
 
+        if self.in_water != other.in_water {
+            return true;
+        }
+
+        if self.noclip != other.noclip {
+            return true;
+        }
+
+        if self.velocity.distance(other.velocity) > 0.5 {
+            return true;
+        }
+
+        if self.crouching != other.crouching
+            || self.sprinting != other.sprinting
+            || self.exhausted != other.exhausted
+            || (self.stamina - other.stamina).abs() > 1.0
+        {
+            return true;
+        }
+
+        // Sprinting decides if a slide starts, and `elapsed` when it ends.
+        match (&self.slide, &other.slide) {
+            (None, None) => {}
+            (Some(s1), Some(s2)) => {
+                let (d1, d2) = (s1.elapsed, s2.elapsed);
+                if (d1 > d2 && d1 - d2 > Duration::from_millis(100))
+                    || (d2 > d1 && d2 - d1 > Duration::from_millis(100))
+                {
+                    return true;
+                }
+            }
+            _ => return true,
+        }
+
         false // No differences found
     }
 }
 
 impl VelocityCalculator for Player {
     fn get_velocity(&self) -> Vec3 {
-        self.kinematics.get_velocity()
+        self.kinematics.get_velocity(&self.profile)
     }
 }
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Everything that decides how players move. The server picks one and sends it
+/// to clients when they join, so prediction uses the same numbers.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MovementProfile {
+    /// Top speeds on the ground, in m/s.
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    pub crouch_speed: f32,
+    /// How quickly players get up to speed on the ground, in multiples of the
+    /// top speed per second.
+    pub ground_acceleration: f32,
+    /// Same as `ground_acceleration`, in the air.
+    pub air_acceleration: f32,
+    /// The most speed air control can add towards the input direction. Turning
+    /// while moving sideways can still build up speed, as in strafe jumping.
+    pub max_air_speed: f32,
+    /// How quickly players slow down on the ground, per second.
+    pub friction: f32,
+    /// Friction acts as if players were moving at least this fast, so they
+    /// come to a stop instead of slowing down forever.
+    pub stop_speed: f32,
+    /// Speed at the start of a slide, if the player isn't going faster already.
+    pub slide_speed: f32,
+    /// Friction while sliding.
+    pub slide_friction: f32,
+    pub slide_duration: Duration,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    /// Slopes steeper than this, in radians, can't be walked up.
+    pub max_slope_climb_angle: f32,
+    /// Players slide down slopes steeper than this, in radians.
+    pub min_slope_slide_angle: f32,
+    pub max_stamina: f32,
+    /// Stamina used per second of sprinting.
+    pub sprint_stamina_drain: f32,
+    /// Stamina regained per second while not sprinting or sliding.
+    pub stamina_regen: f32,
+    pub slide_stamina_cost: f32,
+    /// After running out, sprinting only works again once stamina is back up
+    /// to this.
+    pub sprint_recover_stamina: f32,
+    /// Top speeds are multiplied by this while in water.
+    pub water_speed_multiplier: f32,
+    /// Upwards speed while in water, replacing gravity.
+    pub buoyancy: f32,
+}
+
+impl MovementProfile {
+    /// Grounded movement with little air control.
+    pub fn standard() -> Self {
+        Self {
+            walk_speed: 5.0,
+            sprint_speed: 10.0,
+            crouch_speed: 2.5,
+            ground_acceleration: 10.0,
+            air_acceleration: 2.0,
+            max_air_speed: 1.0,
+            friction: 6.0,
+            stop_speed: 2.0,
+            slide_speed: 12.0,
+            slide_friction: 1.0,
+            slide_duration: Duration::from_millis(800),
+            jump_speed: 5.0,
+            gravity: 10.0,
+            max_slope_climb_angle: 45.0_f32.to_radians(),
+            min_slope_slide_angle: 30.0_f32.to_radians(),
+            max_stamina: 100.0,
+            sprint_stamina_drain: 20.0,
+            stamina_regen: 15.0,
+            slide_stamina_cost: 15.0,
+            sprint_recover_stamina: 30.0,
+            water_speed_multiplier: 0.5,
+            buoyancy: 2.0,
+        }
+    }
+
+    /// Fast movement with strong air control, for arena fights.
+    pub fn arena() -> Self {
+        Self {
+            walk_speed: 7.0,
+            sprint_speed: 12.0,
+            ground_acceleration: 12.0,
+            air_acceleration: 12.0,
+            max_air_speed: 1.5,
+            friction: 5.0,
+            slide_speed: 15.0,
+            slide_friction: 0.5,
+            slide_duration: Duration::from_millis(1200),
+            jump_speed: 6.0,
+            sprint_stamina_drain: 10.0,
+            stamina_regen: 25.0,
+            ..Self::standard()
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Self::standard()),
+            "arena" => Some(Self::arena()),
+            _ => None,
+        }
+    }
+
+    /// Uses the profile named by `MOVEMENT_PROFILE` if it's set.
+    pub fn from_env_or_default() -> Self {
+        match std::env::var("MOVEMENT_PROFILE") {
+            Ok(name) => Self::from_name(&name)
+                .unwrap_or_else(|| panic!("{name} is not a valid movement profile")),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+/// Slows down a horizontal velocity on the ground.
+pub fn apply_friction(velocity: Vec3, friction: f32, stop_speed: f32, delta: f32) -> Vec3 {
+    let speed = velocity.length();
+    if speed <= 0.0 {
+        return Vec3::ZERO;
+    }
+    let drop = speed.max(stop_speed) * friction * delta;
+    velocity * ((speed - drop).max(0.0) / speed)
+}
+
+/// Speeds a velocity up towards `wish_speed` in `wish_dir`. Only the speed
+/// along `wish_dir` is capped, so speed in other directions is kept.
+pub fn accelerate(
+    velocity: Vec3,
+    wish_dir: Vec3,
+    wish_speed: f32,
+    acceleration: f32,
+    delta: f32,
+) -> Vec3 {
+    let current = velocity.dot(wish_dir);
+    let add = wish_speed - current;
+    if add <= 0.0 {
+        return velocity;
+    }
+    velocity + wish_dir * (acceleration * wish_speed * delta).min(add)
+}
@@ -0,0 +1,210 @@
+use bevy::{
+    color::{
+        palettes::css::{DARK_GRAY, LIME},
+        ColorToPacked,
+    },
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+use bevy_mod_billboard::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{game_mode::Team, objects::health::Health};
+
+use super::{LocalPlayerTag, Player};
+
+/// Longest name, in characters.
+pub const MAX_NAME_LENGTH: usize = 24;
+/// How many lengths the health bar has.
+const HEALTH_BAR_STEPS: usize = 20;
+const HEALTH_BAR_WIDTH: f32 = 0.8;
+const HEALTH_BAR_HEIGHT: f32 = 0.08;
+/// Height of the nameplate above the player's center.
+const NAMEPLATE_HEIGHT: f32 = 1.1;
+/// Nameplate text is laid out in pixels, so it's scaled down to world units.
+const NAME_SCALE: f32 = 0.01;
+
+/// The name a player chose when connecting.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerName(pub String);
+
+/// Trims a name and removes control characters. Returns `None` if there's
+/// nothing left.
+pub fn sanitize_name(name: &str) -> Option<String> {
+    let name: String = name
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    let name = name.trim_end().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Shows names and health bars over remote players. They're billboards, so
+/// they always face the camera, and they're depth tested, so they're hidden
+/// behind terrain.
+pub struct NameplatePlugin;
+
+impl Plugin for NameplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(BillboardPlugin);
+        app.init_resource::<NameplateAssets>();
+        app.add_systems(Update, (spawn_nameplates, update_health_bars).chain());
+    }
+}
+
+/// Health bar meshes for every length, split into the filled part and the
+/// empty part so that they don't overlap.
+#[derive(Resource)]
+struct NameplateAssets {
+    filled: Vec<Handle<Mesh>>,
+    empty: Vec<Handle<Mesh>>,
+    filled_texture: Handle<Image>,
+    empty_texture: Handle<Image>,
+}
+
+/// A horizontal bar from `start` to `end`, as fractions of the health bar's
+/// width, centered on the nameplate.
+fn bar_mesh(start: f32, end: f32) -> Mesh {
+    let width = (end - start) * HEALTH_BAR_WIDTH;
+    let center = ((start + end) / 2.0 - 0.5) * HEALTH_BAR_WIDTH;
+    Rectangle::new(width, HEALTH_BAR_HEIGHT)
+        .mesh()
+        .build()
+        .translated_by(Vec3::X * center)
+}
+
+fn color_texture(color: Srgba) -> Image {
+    Image::new_fill(
+        Extent3d::default(),
+        TextureDimension::D2,
+        &color.to_u8_array(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+impl FromWorld for NameplateAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let (filled, empty) = (0..=HEALTH_BAR_STEPS)
+            .map(|step| {
+                let fraction = step as f32 / HEALTH_BAR_STEPS as f32;
+                (
+                    meshes.add(bar_mesh(0.0, fraction)),
+                    meshes.add(bar_mesh(fraction, 1.0)),
+                )
+            })
+            .unzip();
+        let mut images = world.resource_mut::<Assets<Image>>();
+        Self {
+            filled,
+            empty,
+            filled_texture: images.add(color_texture(LIME)),
+            empty_texture: images.add(color_texture(DARK_GRAY)),
+        }
+    }
+}
+
+impl NameplateAssets {
+    fn step(health: &Health) -> usize {
+        let fraction = (health.current / health.max).clamp(0.0, 1.0);
+        (fraction * HEALTH_BAR_STEPS as f32).ceil() as usize
+    }
+}
+
+#[derive(Component)]
+struct HealthBarFilled;
+
+#[derive(Component)]
+struct HealthBarEmpty;
+
+fn spawn_nameplates(
+    players: Query<
+        (Entity, &PlayerName, &Health, Option<&Team>),
+        (Added<PlayerName>, With<Player>, Without<LocalPlayerTag>),
+    >,
+    assets: Res<NameplateAssets>,
+    mut commands: Commands,
+) {
+    for (entity, name, health, team) in players.iter() {
+        let step = NameplateAssets::step(health);
+        let color = team.map_or(Color::WHITE, |team| team.color());
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                    0.0,
+                    NAMEPLATE_HEIGHT,
+                    0.0,
+                )))
+                .with_children(|parent| {
+                    parent.spawn(BillboardTextBundle {
+                        transform: Transform::from_xyz(0.0, 0.15, 0.0)
+                            .with_scale(Vec3::splat(NAME_SCALE)),
+                        text: Text::from_section(
+                            name.0.clone(),
+                            TextStyle {
+                                font_size: 20.0,
+                                color,
+                                ..default()
+                            },
+                        ),
+                        billboard_depth: BillboardDepth(true),
+                        ..default()
+                    });
+                    parent.spawn((
+                        BillboardTextureBundle {
+                            mesh: BillboardMeshHandle(assets.filled[step].clone()),
+                            texture: BillboardTextureHandle(assets.filled_texture.clone()),
+                            billboard_depth: BillboardDepth(true),
+                            ..default()
+                        },
+                        HealthBarFilled,
+                    ));
+                    parent.spawn((
+                        BillboardTextureBundle {
+                            mesh: BillboardMeshHandle(assets.empty[step].clone()),
+                            texture: BillboardTextureHandle(assets.empty_texture.clone()),
+                            billboard_depth: BillboardDepth(true),
+                            ..default()
+                        },
+                        HealthBarEmpty,
+                    ));
+                });
+        });
+    }
+}
+
+/// Health is synced every tick, so the bar's meshes are only swapped when
+/// its length changes.
+fn update_health_bars(
+    players: Query<(&Health, &Children), (Changed<Health>, With<PlayerName>)>,
+    nameplates: Query<&Children>,
+    mut filled: Query<&mut BillboardMeshHandle, (With<HealthBarFilled>, Without<HealthBarEmpty>)>,
+    mut empty: Query<&mut BillboardMeshHandle, (With<HealthBarEmpty>, Without<HealthBarFilled>)>,
+    assets: Res<NameplateAssets>,
+) {
+    for (health, children) in players.iter() {
+        let step = NameplateAssets::step(health);
+        let bars = children
+            .iter()
+            .filter_map(|child| nameplates.get(*child).ok())
+            .flat_map(|children| children.iter());
+        for bar in bars {
+            if let Ok(mut mesh) = filled.get_mut(*bar) {
+                if mesh.0 != assets.filled[step] {
+                    mesh.0 = assets.filled[step].clone();
+                }
+            }
+            if let Ok(mut mesh) = empty.get_mut(*bar) {
+                if mesh.0 != assets.empty[step] {
+                    mesh.0 = assets.empty[step].clone();
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use bevy::{ecs::query::QueryData, prelude::*, utils::HashMap};
+use bevy::{
+    ecs::{query::QueryData, world::EntityRef},
+    prelude::*,
+    utils::HashMap,
+};
 use bevy_rapier3d::prelude::*;
 use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer};
 use rand::Rng;
@@ -8,26 +12,41 @@ use crate::{
         client::{OrderedInput, UnreliableMessageFromClient},
         server::{
             self, OwnedPlayerSync, PlayerInit, PlayerPositionSync, ReliableMessageFromServer,
-            Spawn, UnreliableMessageFromServer,
+            UnreliableMessageFromServer,
         },
-        spawn::NetworkSpawn,
+        spawn::{NetworkSpawn, PlayerSpawn, SpawnAppExt},
     },
-    server::{ClientNetworkObjectMap, PlayerNeedsInit, PlayerWantsUpdates},
+    server::{ClientNetworkObjectMap, PlayerNeedsInit},
     shared::{
-        objects::{grounded::Grounded, health::Health, player::Player, NetworkObject},
+        console::command::{CommandOutput, CommandSender, RunCommand},
+        game_mode::{ActiveGameMode, Team},
+        objects::{
+            grounded::Grounded,
+            gun::GunType,
+            health::{Health, Killed},
+            player::Player,
+            worm::{Worm, WormSegment},
+            NetworkObject,
+        },
+        proc::{terrain::Terrain, WorldSeed},
+        relevancy::Relevancy,
         tick::Tick,
         GameLogic,
     },
     utils,
 };
 
-use super::{spawn::PlayerSpawnRequest, PlayerHead, ShotType};
+use super::{
+    movement::MovementProfile, name::PlayerName, spawn::PlayerSpawnRequest, PlayerHead, ShotType,
+};
 
 pub struct PlayerServerPlugin;
 
 impl Plugin for PlayerServerPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ClientInputs::default());
+        app.add_event::<ShotHit>();
+        app.add_spawn_serializer(serialize_player);
         app.add_systems(
             FixedUpdate,
             (
@@ -35,14 +54,25 @@ impl Plugin for PlayerServerPlugin {
                 apply_inputs.in_set(GameLogic::Game),
                 read_inputs.in_set(GameLogic::ReadInput),
                 broadcast_player_data.in_set(GameLogic::Sync),
-                broadcast_player_spawns.in_set(GameLogic::Sync),
-                load_player.in_set(GameLogic::Sync),
                 init_players.in_set(GameLogic::Spawn),
+                run_player_commands.in_set(GameLogic::Game),
             ),
         );
     }
 }
 
+/// Sent when a player's shot hits a network object, whether or not it can be
+/// damaged.
+#[derive(Event)]
+pub struct ShotHit {
+    /// Where the shooter was.
+    pub origin: Vec3,
+    pub target: NetworkObject,
+    /// Where the shot hit, relative to the target.
+    pub relative_position: Vec3,
+    pub gun_type: GunType,
+}
+
 /// Tracks the order of the most recent processed input for a player. The input
 /// for the player should be processed by the time this is updated.
 #[derive(Component, Default)]
@@ -52,8 +82,6 @@ pub struct LastInputTracker {
 
 /// Stores a mapping of player network objects to a list of unprocessed inputs.
 /// Also stores a mapping of network objects to their client id.
-///
-/// TODO: fix memory leaks as this doesn't clean up disconnected clients.
 #[derive(Resource, Default)]
 pub struct ClientInputs {
     inputs: HashMap<NetworkObject, Vec<OrderedInput>>,
@@ -103,54 +131,114 @@ impl ClientInputs {
     fn get_client_id(&self, net_obj: &NetworkObject) -> Option<ClientId> {
         self.clients.get(net_obj).cloned()
     }
+
+    /// Drops the inputs of a player whose client disconnected.
+    pub fn forget(&mut self, net_obj: &NetworkObject) {
+        self.inputs.remove(net_obj);
+        self.clients.remove(net_obj);
+    }
 }
 
-/// Spawns a new player when a `PlayerNeedsInit` event is received.
+/// How many random points are tried before settling for a bad one.
+const SPAWN_ATTEMPTS: usize = 10;
+/// Spawn points steeper than this are avoided.
+const MAX_SPAWN_SLOPE: f32 = std::f32::consts::FRAC_PI_6;
+
+/// Returns a random position on the ground near the center of the map,
+/// preferring flat ground that isn't under water.
+pub fn random_spawn_translation(terrain: &Terrain) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let mut pick = || Vec2::new(rng.gen_range(-30.0..30.0), rng.gen_range(-30.0..30.0));
+    let mut point = pick();
+    for _ in 1..SPAWN_ATTEMPTS {
+        let flat = terrain.normal_at(point.x, point.y).angle_between(Vec3::Y) <= MAX_SPAWN_SLOPE;
+        if flat && terrain.height_at(point.x, point.y) > terrain.water_level {
+            break;
+        }
+        point = pick();
+    }
+    Vec3::new(point.x, terrain.height_at(point.x, point.y), point.y)
+}
+
+/// Spawns a new player when a `PlayerNeedsInit` event is received. The player's
+/// team is picked by the active game mode. Players that were left behind by a
+/// reconnecting client are given back as they are.
+#[allow(clippy::too_many_arguments)]
 pub fn init_players(
     mut player_init: EventReader<PlayerNeedsInit>,
     mut player_spawn_reqs: EventWriter<PlayerSpawnRequest>,
     mut server: ResMut<RenetServer>,
+    mut inputs: ResMut<ClientInputs>,
+    game_mode: Res<ActiveGameMode>,
+    mut players: Query<
+        (
+            &NetworkObject,
+            &Transform,
+            Option<&Team>,
+            &mut LastInputTracker,
+        ),
+        With<Player>,
+    >,
+    seed: Res<WorldSeed>,
+    movement: Res<MovementProfile>,
+    terrain: Res<Terrain>,
     tick: Res<Tick>,
 ) {
+    let mut assigned_teams: Vec<Team> = players
+        .iter()
+        .filter_map(|(_, _, team, _)| team.copied())
+        .collect();
     for init in player_init.read() {
-        let mut rng = rand::thread_rng();
-        let transform =
-            Transform::from_xyz(rng.gen_range(-30.0..30.0), 0.0, rng.gen_range(-30.0..30.0));
-        player_spawn_reqs.send(PlayerSpawnRequest::Server(transform, init.net_obj.clone()));
+        let held = players
+            .iter_mut()
+            .find(|(net_obj, ..)| init.resumed && **net_obj == init.net_obj);
+        let (transform, team) = match held {
+            Some((_, transform, team, mut input_tracker)) => {
+                // The reconnected client counts its inputs from the start.
+                *input_tracker = LastInputTracker::default();
+                inputs.forget(&init.net_obj);
+                (*transform, team.copied())
+            }
+            None => {
+                let transform = Transform::from_translation(random_spawn_translation(&terrain));
+                let team = game_mode.mode().assign_team(&assigned_teams);
+                assigned_teams.extend(team);
+                player_spawn_reqs.send(PlayerSpawnRequest::Server(
+                    transform,
+                    init.net_obj.clone(),
+                    team,
+                    init.name.clone(),
+                ));
+                (transform, team)
+            }
+        };
 
         info!("sending player init");
         let message = ReliableMessageFromServer::InitPlayer(PlayerInit {
             net_obj: init.net_obj.clone(),
             transform,
             tick: tick.clone(),
+            team,
+            seed: seed.0,
+            movement: movement.clone(),
+            session: init.session,
         });
         let bytes = bincode::serialize(&message).unwrap();
         server.send_message(init.client_id, DefaultChannel::ReliableUnordered, bytes);
     }
 }
 
-/// Broadcasts a player spawn event whenever a new player is added.
-pub fn broadcast_player_spawns(
-    query: Query<(&NetworkObject, &Transform), Added<Player>>,
-    mut server: ResMut<RenetServer>,
-    tick: Res<Tick>,
-) {
-    for (network_obj, transform) in query.iter() {
-        let net_spawn = NetworkSpawn::Player(transform.clone());
-        let spawn = Spawn {
-            net_spawn,
-            net_obj: network_obj.clone(),
-            tick: tick.clone(),
-        };
-        let message = ReliableMessageFromServer::Spawn(spawn);
-        let bytes = bincode::serialize(&message).unwrap();
-        server.broadcast_message(DefaultChannel::ReliableUnordered, bytes);
-        println!("spawning player");
-    }
+fn serialize_player(entity: EntityRef) -> Option<NetworkSpawn> {
+    entity.get::<Player>()?;
+    Some(NetworkSpawn::Player(PlayerSpawn {
+        transform: *entity.get::<Transform>()?,
+        team: entity.get::<Team>().copied(),
+        name: entity.get::<PlayerName>()?.clone(),
+    }))
 }
 
-/// Sends a `PlayerPositionSync` to everyone player except the player whose position
-/// it is. Sends an `OwnedPlayerSync` to the player who owns the position.
+/// Sends a `PlayerPositionSync` to every player near it except the player whose
+/// position it is. Sends an `OwnedPlayerSync` to the player who owns the position.
 pub fn broadcast_player_data(
     player_query: Query<(
         &NetworkObject,
@@ -161,14 +249,14 @@ pub fn broadcast_player_data(
     )>,
     player_head_query: Query<(&Transform, &Parent), With<PlayerHead>>,
     client_netmap: Res<ClientNetworkObjectMap>,
+    relevancy: Res<Relevancy>,
     mut server: ResMut<RenetServer>,
     tick: Res<Tick>,
 ) {
     for (obj, transform, input_tracker, player, player_entity) in player_query.iter() {
-        let Some(client_id) = client_netmap.net_obj_to_client.get(obj) else {
-            warn!("no client id for player obj in broadcast_player_data");
-            continue;
-        };
+        // Players waiting for their client to reconnect are still shown to
+        // everyone else.
+        let client_id = client_netmap.net_obj_to_client.get(obj);
 
         let mut head_rotation = 0.0;
         for (head_t, head_parent) in player_head_query.iter() {
@@ -183,9 +271,17 @@ pub fn broadcast_player_data(
             tick: tick.clone(),
             body_rotation: utils::transform::get_body_rotation_pitch(transform),
             head_rotation,
+            crouching: player.kinematics.crouching,
         });
         let bytes = bincode::serialize(&message).unwrap();
-        server.broadcast_message_except(*client_id, DefaultChannel::Unreliable, bytes);
+        for other in relevancy.clients_for(obj, *tick) {
+            if Some(&other) != client_id {
+                server.send_message(other, DefaultChannel::Unreliable, bytes.clone());
+            }
+        }
+        let Some(client_id) = client_id else {
+            continue;
+        };
 
         let message = UnreliableMessageFromServer::OwnedPlayerSync(OwnedPlayerSync {
             net_obj: obj.clone(),
@@ -208,19 +304,31 @@ pub struct InputQuery {
     net_obj: &'static NetworkObject,
     last_input_tracker: &'static mut LastInputTracker,
     controller: &'static KinematicCharacterController,
-    collider: &'static Collider,
     grounded: &'static mut Grounded,
     player: &'static mut Player,
+    team: Option<&'static Team>,
 }
 
 /// Grabs the most recent input for each player and applies it using `apply_input`.
+/// Shots damage their target if the game mode allows it.
+#[allow(clippy::too_many_arguments)]
 pub fn apply_inputs(
-    mut health: Query<(&NetworkObject, &mut Health)>,
+    mut health: Query<(
+        &NetworkObject,
+        &mut Health,
+        Option<&Team>,
+        &GlobalTransform,
+        Option<&Worm>,
+    )>,
+    worm_segments: Query<(&GlobalTransform, &WormSegment)>,
     mut query: Query<InputQuery, With<Player>>,
     time: Res<Time>,
     mut inputs: ResMut<ClientInputs>,
     mut context: ResMut<RapierContext>,
     mut server: ResMut<RenetServer>,
+    game_mode: Res<ActiveGameMode>,
+    mut kills: EventWriter<Killed>,
+    mut hits: EventWriter<ShotHit>,
 ) {
     let net_obj_inputs = inputs.pop_inputs();
     for mut item in query.iter_mut() {
@@ -235,9 +343,32 @@ pub fn apply_inputs(
                 let bytes = bincode::serialize(&message).unwrap();
                 server.broadcast_message_except(inputter, DefaultChannel::Unreliable, bytes);
                 if let ShotType::ShotTarget(target) = &shot.shot_type {
-                    for (net_obj, mut health) in health.iter_mut() {
-                        if *net_obj == target.target {
-                            health.current = (health.current - shot.gun_type.damage()).max(0.0);
+                    hits.send(ShotHit {
+                        origin: item.transform.translation,
+                        target: target.target.clone(),
+                        relative_position: target.relative_position,
+                        gun_type: shot.gun_type.clone(),
+                    });
+                    for (net_obj, mut health, victim_team, victim_t, worm) in health.iter_mut() {
+                        if *net_obj != target.target {
+                            continue;
+                        }
+                        let attacker_team = item.team.copied();
+                        if !game_mode
+                            .mode()
+                            .can_damage(attacker_team, victim_team.copied())
+                        {
+                            continue;
+                        }
+                        let multiplier = worm.map_or(1.0, |worm| {
+                            let point = victim_t.translation() + target.relative_position;
+                            worm.hit_multiplier(victim_t, point, &worm_segments)
+                        });
+                        if health.take_damage(shot.gun_type.damage() * multiplier) {
+                            kills.send(Killed {
+                                killer: item.net_obj.clone(),
+                                victim: net_obj.clone(),
+                            });
                         }
                     }
                 }
@@ -246,7 +377,6 @@ pub fn apply_inputs(
                 &mut context,
                 &input.input,
                 &mut item.transform,
-                item.collider,
                 item.controller,
                 &time,
                 item.entity,
@@ -307,24 +437,81 @@ pub fn handle_player_looking_at(
     }
 }
 
-/// Informs the new player of all the currently spawned players whenever a
-/// `PlayerWantsUpdates` event is received.
-pub fn load_player(
-    mut player_load: EventReader<PlayerWantsUpdates>,
-    player_query: Query<(&NetworkObject, &Transform), With<Player>>,
-    tick: Res<Tick>,
-    mut server: ResMut<RenetServer>,
+/// Runs the console commands that change the player who ran them. They can't
+/// be run from the server's own console, since it doesn't have a player.
+fn run_player_commands(
+    mut run: EventReader<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+    client_map: Res<ClientNetworkObjectMap>,
+    mut players: Query<(&NetworkObject, &mut Player, &mut Transform, &mut Health)>,
+    terrain: Option<Res<Terrain>>,
 ) {
-    for load in player_load.read() {
-        for (net_obj, transform) in player_query.iter() {
-            let net_spawn = NetworkSpawn::Player(transform.clone());
-            let message = ReliableMessageFromServer::Spawn(Spawn {
-                net_obj: net_obj.clone(),
-                tick: tick.clone(),
-                net_spawn,
-            });
-            let bytes = bincode::serialize(&message).unwrap();
-            server.send_message(load.client_id, DefaultChannel::ReliableUnordered, bytes);
+    for command in run.read() {
+        if !["noclip", "tp", "give"].contains(&command.name.as_str()) {
+            continue;
         }
+        let CommandSender::Client(client_id) = command.sender else {
+            output.send(command.error(format!("{} needs a player", command.name)));
+            continue;
+        };
+        let Some((_, mut player, mut transform, mut health)) = client_map
+            .client_to_net_obj
+            .get(&client_id)
+            .and_then(|net_obj| {
+                players
+                    .iter_mut()
+                    .find(|(player_obj, ..)| *player_obj == net_obj)
+            })
+        else {
+            output.send(command.error("you don't have a player"));
+            continue;
+        };
+
+        let reply = match command.name.as_str() {
+            "noclip" => {
+                player.kinematics.noclip = !player.kinematics.noclip;
+                Ok(format!("noclip {}", player.kinematics.noclip))
+            }
+            "tp" => teleport(&command.args, terrain.as_deref()).map(|translation| {
+                transform.translation = translation;
+                format!("teleported to {translation}")
+            }),
+            _ => match command.args.as_slice() {
+                [item, rest @ ..] if item == "health" => {
+                    match rest.first().map(|amount| amount.parse::<f32>()) {
+                        None => {
+                            health.current = health.max;
+                            Ok("health restored".to_string())
+                        }
+                        Some(Ok(amount)) => {
+                            health.current = (health.current + amount).clamp(0.0, health.max);
+                            Ok(format!("health is {}", health.current))
+                        }
+                        Some(Err(_)) => Err("amount must be a number".to_string()),
+                    }
+                }
+                _ => Err("usage: give health [amount]".to_string()),
+            },
+        };
+        output.send(match reply {
+            Ok(text) => command.reply(text),
+            Err(text) => command.error(text),
+        });
+    }
+}
+
+/// Where `tp` moves a player to. Without a height, the player lands on the
+/// ground.
+fn teleport(args: &[String], terrain: Option<&Terrain>) -> Result<Vec3, String> {
+    let coords: Vec<f32> = args
+        .iter()
+        .map(|arg| arg.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "coordinates must be numbers".to_string())?;
+    match (coords.as_slice(), terrain) {
+        ([x, y, z], _) => Ok(Vec3::new(*x, *y, *z)),
+        ([x, z], Some(terrain)) => Ok(Vec3::new(*x, terrain.height_at(*x, *z) + 1.0, *z)),
+        ([_, _], None) => Err("the terrain isn't loaded yet".to_string()),
+        _ => Err("usage: tp <x> [y] <z>".to_string()),
     }
 }
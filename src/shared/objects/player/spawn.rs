@@ -1,12 +1,16 @@
-use bevy::{color::palettes::css::BLUE, prelude::*, render::view::RenderLayers};
+use bevy::{
+    color::palettes::css::BLUE, core_pipeline::prepass::DepthPrepass, prelude::*,
+    render::view::RenderLayers, utils::HashMap,
+};
 use bevy_rapier3d::prelude::*;
 
 use crate::shared::{
+    game_mode::Team,
     objects::{
         grounded::Grounded,
         gun::{Gun, GunType, LocalPlayerGun},
         health::Health,
-        LastSyncTracker, NetworkObject,
+        LastSyncTracker, NetworkObject, Replicated,
     },
     proc::{utils::SnapToFloor, LoadsChunks},
     render::{DEFAULT_CAMERA_ORDER, DEFAULT_RENDER_LAYER},
@@ -15,15 +19,19 @@ use crate::shared::{
 
 use super::{
     client::{PlayerCamera, PlayerCameraTarget},
+    head_height,
+    movement::MovementProfile,
+    name::PlayerName,
+    player_collider,
     server::LastInputTracker,
     LocalPlayerTag, Player, PlayerHead,
 };
 
 #[derive(Event)]
 pub enum PlayerSpawnRequest {
-    Server(Transform, NetworkObject),
-    Local(Transform, NetworkObject, Tick),
-    Remote(Transform, NetworkObject, Tick),
+    Server(Transform, NetworkObject, Option<Team>, PlayerName),
+    Local(Transform, NetworkObject, Tick, Option<Team>),
+    Remote(Transform, NetworkObject, Tick, Option<Team>, PlayerName),
 }
 
 #[derive(Bundle)]
@@ -37,7 +45,7 @@ impl Default for PlayerPhysicsBundle {
     fn default() -> Self {
         Self {
             controller: KinematicCharacterController::default(),
-            collider: Collider::capsule_y(0.5, 0.25),
+            collider: player_collider(false),
             rigid_body: RigidBody::KinematicPositionBased,
         }
     }
@@ -46,20 +54,23 @@ impl Default for PlayerPhysicsBundle {
 #[derive(Default)]
 pub struct PlayerVisualHandles {
     mesh: Option<Handle<Mesh>>,
-    material: Option<Handle<StandardMaterial>>,
+    /// One material per team, `None` is for players without a team.
+    materials: HashMap<Option<Team>, Handle<StandardMaterial>>,
 }
 
 fn get_player_visual(
     handles: &mut PlayerVisualHandles,
     meshes: &mut Assets<Mesh>,
     materials: &mut Assets<StandardMaterial>,
+    team: Option<Team>,
 ) -> impl Bundle {
     let mesh = handles
         .mesh
         .get_or_insert_with(|| meshes.add(Capsule3d::new(0.25, 1.0).mesh()));
-    let material = handles
-        .material
-        .get_or_insert_with(|| materials.add(StandardMaterial::from_color(BLUE)));
+    let material = handles.materials.entry(team).or_insert_with(|| {
+        let color = team.map(|team| team.color()).unwrap_or(BLUE.into());
+        materials.add(StandardMaterial::from_color(color))
+    });
     PbrBundle {
         mesh: mesh.clone(),
         material: material.clone(),
@@ -76,19 +87,23 @@ pub fn spawn_players_from_spawn_requests(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    movement: Option<Res<MovementProfile>>,
 ) {
+    let movement = movement.as_deref().cloned().unwrap_or_default();
     for req in player_spawn_reqs.read() {
         match req {
-            PlayerSpawnRequest::Server(transform, net_obj) => {
+            PlayerSpawnRequest::Server(transform, net_obj, team, name) => {
                 let entity = commands
                     .spawn((
-                        Player::new(),
+                        Player::new(movement.clone()),
+                        name.clone(),
                         PlayerPhysicsBundle::default(),
                         Grounded::default(),
                         net_obj.clone(),
                         LoadsChunks,
-                        get_player_visual(&mut visual_handles, &mut meshes, &mut materials),
+                        get_player_visual(&mut visual_handles, &mut meshes, &mut materials, *team),
                         Health::new(PLAYER_HEALTH),
+                        Replicated,
                     ))
                     .insert(SpatialBundle::from_transform(*transform))
                     .insert(Visibility::Hidden)
@@ -97,7 +112,11 @@ pub fn spawn_players_from_spawn_requests(
                         parent
                             .spawn((
                                 PlayerHead,
-                                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.5, 0.0)),
+                                SpatialBundle::from_transform(Transform::from_xyz(
+                                    0.0,
+                                    head_height(false),
+                                    0.0,
+                                )),
                             ))
                             .with_children(|parent| {
                                 parent.spawn((
@@ -107,12 +126,15 @@ pub fn spawn_players_from_spawn_requests(
                             });
                     })
                     .id();
+                if let Some(team) = team {
+                    commands.entity(entity).insert(*team);
+                }
                 snap_to_floor.send(SnapToFloor::new(entity).set_visible().with_offset(1.0));
             }
-            PlayerSpawnRequest::Local(transform, net_obj, tick) => {
-                commands
+            PlayerSpawnRequest::Local(transform, net_obj, tick, team) => {
+                let entity = commands
                     .spawn((
-                        Player::new(),
+                        Player::new(movement.clone()),
                         PlayerPhysicsBundle::default(),
                         SpatialBundle::from_transform(*transform),
                         Grounded::default(),
@@ -127,9 +149,17 @@ pub fn spawn_players_from_spawn_requests(
                         parent.spawn((
                             PlayerCameraTarget,
                             PlayerHead,
-                            SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.5, 0.0)),
+                            SpatialBundle::from_transform(Transform::from_xyz(
+                                0.0,
+                                head_height(false),
+                                0.0,
+                            )),
                         ));
-                    });
+                    })
+                    .id();
+                if let Some(team) = team {
+                    commands.entity(entity).insert(*team);
+                }
                 commands
                     .spawn((
                         PlayerCamera,
@@ -145,6 +175,8 @@ pub fn spawn_players_from_spawn_requests(
                             },
                             ..default()
                         },
+                        // Water uses the depth prepass.
+                        DepthPrepass,
                     ))
                     .insert(RenderLayers::layer(DEFAULT_RENDER_LAYER))
                     .insert(SpatialBundle::default())
@@ -157,15 +189,16 @@ pub fn spawn_players_from_spawn_requests(
                         ));
                     });
             }
-            PlayerSpawnRequest::Remote(transform, net_obj, tick) => {
-                commands
+            PlayerSpawnRequest::Remote(transform, net_obj, tick, team, name) => {
+                let entity = commands
                     .spawn((
-                        Player::new(),
+                        Player::new(movement.clone()),
+                        name.clone(),
                         PlayerPhysicsBundle::default(),
                         net_obj.clone(),
                         LastSyncTracker::<Transform>::new(*tick),
                         LastSyncTracker::<Health>::new(*tick),
-                        get_player_visual(&mut visual_handles, &mut meshes, &mut materials),
+                        get_player_visual(&mut visual_handles, &mut meshes, &mut materials, *team),
                         Health::new(PLAYER_HEALTH),
                     ))
                     .insert(SpatialBundle::from_transform(*transform))
@@ -173,7 +206,11 @@ pub fn spawn_players_from_spawn_requests(
                         parent
                             .spawn((
                                 PlayerHead,
-                                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.5, 0.0)),
+                                SpatialBundle::from_transform(Transform::from_xyz(
+                                    0.0,
+                                    head_height(false),
+                                    0.0,
+                                )),
                             ))
                             .with_children(|parent| {
                                 parent.spawn((
@@ -181,7 +218,11 @@ pub fn spawn_players_from_spawn_requests(
                                     Gun::new(GunType::PurpleRifle),
                                 ));
                             });
-                    });
+                    })
+                    .id();
+                if let Some(team) = team {
+                    commands.entity(entity).insert(*team);
+                }
             }
         }
     }
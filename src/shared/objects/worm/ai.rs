@@ -0,0 +1,331 @@
+use std::{
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, TAU},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+
+use crate::shared::{
+    game_mode::{ActiveGameMode, Team},
+    objects::{
+        health::{Health, Killed},
+        player::Player,
+        NetworkObject,
+    },
+    proc::navmesh::{NavMesh, NAV_CELL_SIZE},
+};
+
+use super::Worm;
+
+/// How far away a worm can notice players.
+const SIGHT_RANGE: f32 = 25.0;
+/// Distance between the worm's and the player's centers at which the worm can bite.
+const ATTACK_RANGE: f32 = 1.5;
+const ATTACK_DAMAGE: f32 = 10.0;
+const ATTACK_COOLDOWN: Duration = Duration::from_secs(1);
+/// Below this fraction of its max health, a worm runs away from its target.
+const FLEE_HEALTH_FRACTION: f32 = 0.25;
+const FLEE_DURATION: Duration = Duration::from_secs(4);
+const IDLE_DURATION: Duration = Duration::from_secs(3);
+/// Gives up on wandering to a destination after this long, e.g. if it's stuck.
+const WANDER_TIMEOUT: Duration = Duration::from_secs(8);
+const WANDER_RADIUS: f32 = 10.0;
+
+const WANDER_SPEED: f32 = 2.0;
+const CHASE_SPEED: f32 = 4.5;
+const FLEE_SPEED: f32 = 5.5;
+
+/// Paths are planned again this often, since the navmesh changes as chunks
+/// load and unload.
+const REPATH_INTERVAL: Duration = Duration::from_millis(500);
+/// Paths are also planned again when their goal moved this far.
+const REPATH_DISTANCE: f32 = NAV_CELL_SIZE;
+/// A waypoint counts as reached this close to it, horizontally.
+const WAYPOINT_RADIUS: f32 = NAV_CELL_SIZE * 0.5;
+
+/// How far ahead the ground is checked when steering.
+const SLOPE_PROBE_DISTANCE: f32 = 1.0;
+/// Probes start this far above the worm so that they can find uphill ground.
+const SLOPE_PROBE_HEIGHT: f32 = 2.0;
+/// Directions to try, relative to the desired one, when the way ahead is too steep.
+const STEER_ANGLES: [f32; 5] = [0.0, FRAC_PI_4, -FRAC_PI_4, FRAC_PI_2, -FRAC_PI_2];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WormState {
+    Idle,
+    Wander { destination: Vec3 },
+    Chase { target: Entity },
+    Attack { target: Entity },
+    Flee { from: Entity },
+}
+
+/// Server side brain of a worm.
+#[derive(Component)]
+pub struct WormAi {
+    state: WormState,
+    /// How long to stay in the current state. Used by idle, wander and flee.
+    state_timer: Timer,
+    attack_cooldown: Timer,
+    /// Waypoints left on the way to `path_goal`. Empty if the navmesh had no
+    /// path, in which case the worm walks straight to the goal.
+    path: Vec<Vec3>,
+    path_goal: Option<Vec3>,
+    repath_timer: Timer,
+}
+
+impl Default for WormAi {
+    fn default() -> Self {
+        Self {
+            state: WormState::Idle,
+            state_timer: Timer::new(IDLE_DURATION, TimerMode::Once),
+            attack_cooldown: Timer::new(ATTACK_COOLDOWN, TimerMode::Once),
+            path: Vec::new(),
+            path_goal: None,
+            repath_timer: Timer::new(REPATH_INTERVAL, TimerMode::Once),
+        }
+    }
+}
+
+impl WormAi {
+    pub fn state(&self) -> &WormState {
+        &self.state
+    }
+
+    fn set_state(&mut self, state: WormState, duration: Duration) {
+        if self.state != state {
+            self.state = state;
+            self.state_timer = Timer::new(duration, TimerMode::Once);
+            self.path_goal = None;
+        }
+    }
+
+    /// Returns where to walk next to get to `goal`. Follows the navmesh if
+    /// there is a path, and otherwise walks straight there. The path is kept
+    /// between ticks and only planned again when the goal moved, or every
+    /// `REPATH_INTERVAL`.
+    fn next_waypoint(&mut self, navmesh: Option<&NavMesh>, from: Vec3, goal: Vec3) -> Vec3 {
+        let goal_moved = !self
+            .path_goal
+            .is_some_and(|path_goal| path_goal.distance(goal) <= REPATH_DISTANCE);
+        if goal_moved || self.repath_timer.finished() {
+            // The first point is the cell the worm is already in.
+            self.path = navmesh
+                .and_then(|navmesh| navmesh.find_path(from, goal))
+                .map(|path| path.into_iter().skip(1).collect())
+                .unwrap_or_default();
+            self.path_goal = Some(goal);
+            self.repath_timer.reset();
+        }
+        while self
+            .path
+            .first()
+            .is_some_and(|waypoint| waypoint.xz().distance(from.xz()) < WAYPOINT_RADIUS)
+        {
+            self.path.remove(0);
+        }
+        self.path.first().copied().unwrap_or(goal)
+    }
+}
+
+/// Returns the nearest player in sight range that isn't hidden behind something.
+fn find_target(
+    context: &RapierContext,
+    worm: Entity,
+    position: Vec3,
+    players: &Query<(Entity, &Transform), (With<Player>, Without<Worm>)>,
+) -> Option<(Entity, Vec3)> {
+    players
+        .iter()
+        .map(|(entity, t)| (entity, t.translation))
+        .filter(|(_, player_pos)| position.distance(*player_pos) <= SIGHT_RANGE)
+        .filter(|(player, player_pos)| {
+            let to_player = *player_pos - position;
+            let filter = QueryFilter::default()
+                .exclude_collider(worm)
+                .exclude_sensors();
+            match context.cast_ray(
+                position,
+                to_player.normalize_or_zero(),
+                to_player.length(),
+                true,
+                filter,
+            ) {
+                Some((hit, _)) => hit == *player,
+                None => true,
+            }
+        })
+        .min_by(|(_, a), (_, b)| position.distance(*a).total_cmp(&position.distance(*b)))
+}
+
+/// Picks the next state for every worm.
+pub fn think(
+    context: Res<RapierContext>,
+    mut worms: Query<(Entity, &Transform, &Health, &mut WormAi), With<Worm>>,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Worm>)>,
+    time: Res<Time>,
+) {
+    let mut rng = rand::thread_rng();
+    for (entity, transform, health, mut ai) in worms.iter_mut() {
+        ai.state_timer.tick(time.delta());
+        ai.attack_cooldown.tick(time.delta());
+        ai.repath_timer.tick(time.delta());
+        let position = transform.translation;
+        let target = find_target(&context, entity, position, &players);
+
+        if let WormState::Flee { .. } = ai.state {
+            if !ai.state_timer.finished() {
+                continue;
+            }
+        }
+
+        if let Some((target, target_pos)) = target {
+            if health.current < health.max * FLEE_HEALTH_FRACTION {
+                ai.set_state(WormState::Flee { from: target }, FLEE_DURATION);
+            } else if position.distance(target_pos) <= ATTACK_RANGE {
+                ai.set_state(WormState::Attack { target }, Duration::ZERO);
+            } else {
+                ai.set_state(WormState::Chase { target }, Duration::ZERO);
+            }
+            continue;
+        }
+
+        match ai.state {
+            WormState::Idle if ai.state_timer.finished() => {
+                let angle = rng.gen_range(0.0..TAU);
+                let distance = rng.gen_range(0.0..WANDER_RADIUS);
+                let destination = position + Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+                ai.set_state(WormState::Wander { destination }, WANDER_TIMEOUT);
+            }
+            WormState::Idle => {}
+            WormState::Wander { destination } => {
+                let arrived = position.xz().distance(destination.xz()) < 1.0;
+                if arrived || ai.state_timer.finished() {
+                    ai.set_state(WormState::Idle, IDLE_DURATION);
+                }
+            }
+            // The target went out of sight, or the worm is done fleeing.
+            WormState::Chase { .. } | WormState::Attack { .. } | WormState::Flee { .. } => {
+                ai.set_state(WormState::Idle, IDLE_DURATION);
+            }
+        }
+    }
+}
+
+/// Returns a direction close to `direction` that the worm can walk in without
+/// running into a slope steeper than the controller can climb, or `None` if
+/// there isn't one.
+fn climbable_direction(
+    context: &RapierContext,
+    position: Vec3,
+    direction: Vec3,
+    max_slope_climb_angle: f32,
+) -> Option<Vec3> {
+    STEER_ANGLES.iter().find_map(|angle| {
+        let dir = Quat::from_rotation_y(*angle) * direction;
+        let probe = position + dir * SLOPE_PROBE_DISTANCE + Vec3::Y * SLOPE_PROBE_HEIGHT;
+        let (_, hit) = context.cast_ray_and_get_normal(
+            probe,
+            -Vec3::Y,
+            SLOPE_PROBE_HEIGHT * 2.0,
+            true,
+            QueryFilter::only_fixed().exclude_sensors(),
+        )?;
+        let uphill = hit.point.y > position.y;
+        let too_steep = hit.normal.angle_between(Vec3::Y) > max_slope_climb_angle;
+        (!uphill || !too_steep).then_some(dir)
+    })
+}
+
+/// Turns the worm's state into movement.
+pub fn steer(
+    context: Res<RapierContext>,
+    navmesh: Option<Res<NavMesh>>,
+    mut worms: Query<(
+        &mut Transform,
+        &mut Worm,
+        &mut WormAi,
+        &KinematicCharacterController,
+    )>,
+    players: Query<&Transform, (With<Player>, Without<Worm>)>,
+) {
+    let navmesh = navmesh.as_deref();
+    for (mut transform, mut worm, mut ai, controller) in worms.iter_mut() {
+        let position = transform.translation;
+        let (towards, speed) = match ai.state {
+            WormState::Idle => (None, 0.0),
+            WormState::Wander { destination } => (
+                Some(ai.next_waypoint(navmesh, position, destination) - position),
+                WANDER_SPEED,
+            ),
+            WormState::Chase { target } => (
+                players
+                    .get(target)
+                    .ok()
+                    .map(|t| ai.next_waypoint(navmesh, position, t.translation) - position),
+                CHASE_SPEED,
+            ),
+            WormState::Attack { target } => (
+                players.get(target).ok().map(|t| t.translation - position),
+                0.0,
+            ),
+            WormState::Flee { from } => (
+                players.get(from).ok().map(|t| position - t.translation),
+                FLEE_SPEED,
+            ),
+        };
+        let direction = towards
+            .map(|v| Vec3::new(v.x, 0.0, v.z).normalize_or_zero())
+            .filter(|v| *v != Vec3::ZERO);
+
+        let Some(direction) = direction else {
+            worm.kinematics.walk_velocity = Vec3::ZERO;
+            continue;
+        };
+        transform.look_to(direction, Vec3::Y);
+        worm.kinematics.walk_velocity = if speed > 0.0 {
+            climbable_direction(
+                &context,
+                position,
+                direction,
+                controller.max_slope_climb_angle,
+            )
+            .map_or(Vec3::ZERO, |dir| dir * speed)
+        } else {
+            Vec3::ZERO
+        };
+    }
+}
+
+/// Bites players that attacking worms are next to.
+pub fn melee(
+    mut worms: Query<(&NetworkObject, &mut WormAi), With<Worm>>,
+    mut players: Query<(&NetworkObject, &mut Health, Option<&Team>), (With<Player>, Without<Worm>)>,
+    game_mode: Option<Res<ActiveGameMode>>,
+    mut kills: EventWriter<Killed>,
+) {
+    for (worm_obj, mut ai) in worms.iter_mut() {
+        let WormState::Attack { target } = ai.state else {
+            continue;
+        };
+        if !ai.attack_cooldown.finished() {
+            continue;
+        }
+        let Ok((player_obj, mut health, team)) = players.get_mut(target) else {
+            continue;
+        };
+        if let Some(game_mode) = &game_mode {
+            if !game_mode.mode().can_damage(None, team.copied()) {
+                continue;
+            }
+        }
+        ai.attack_cooldown.reset();
+        if health.take_damage(ATTACK_DAMAGE) {
+            kills.send(Killed {
+                killer: worm_obj.clone(),
+                victim: player_obj.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::time::Duration;
+
+use bevy::{
+    color::palettes::css::BROWN,
+    ecs::{
+        component::{ComponentHooks, StorageType},
+        world::{DeferredWorld, EntityRef},
+    },
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::{DefaultChannel, RenetServer};
+
+use crate::{
+    message::{
+        client::MessageReaderOnClient,
+        server::{ReliableMessageFromServer, UnreliableMessageFromServer},
+        spawn::{NetworkSpawn, SpawnAppExt, WormSpawn},
+    },
+    shared::{
+        despawn_recursive_and_broadcast, physics::VelocityCalculator, relevancy::Relevancy,
+        tick::Tick, GameLogic, IsServer, NetworkObject,
+    },
+};
+
+use self::ai::WormAi;
+
+use super::{grounded::Grounded, health::Health, LastSyncTracker, Replicated};
+
+pub mod ai;
+
+pub struct WormPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for WormPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                tick_kinematics.in_set(GameLogic::PreKinematics),
+                follow_head.in_set(GameLogic::End),
+            ),
+        );
+        if self.is_server {
+            app.add_spawn_serializer(serialize_worm);
+            app.add_systems(
+                FixedUpdate,
+                (
+                    setup.in_set(GameLogic::Spawn),
+                    send_transforms.in_set(GameLogic::Sync),
+                    despawn_dead_worms.in_set(GameLogic::Game),
+                    (ai::think, ai::melee).chain().in_set(GameLogic::Game),
+                    ai::steer.in_set(GameLogic::PreKinematics),
+                ),
+            );
+        } else {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    recv_transforms.in_set(GameLogic::Sync),
+                    spawn_worms.in_set(GameLogic::Spawn),
+                ),
+            );
+        }
+    }
+}
+
+const WORM_HEALTH: f32 = 50.0;
+const HEAD_RADIUS: f32 = 0.5;
+/// Number of body segments behind the head.
+const SEGMENT_COUNT: usize = 6;
+/// Radius of the segment right behind the head. Segments get thinner towards
+/// the tail.
+const SEGMENT_RADIUS: f32 = 0.45;
+const TAIL_RADIUS: f32 = 0.2;
+/// Distance between the centers of neighbouring segments.
+const SEGMENT_SPACING: f32 = 0.6;
+/// Shots that hit the head do extra damage.
+const HEAD_HIT_MULTIPLIER: f32 = 2.0;
+const BODY_HIT_MULTIPLIER: f32 = 1.0;
+const TAIL_HIT_MULTIPLIER: f32 = 0.5;
+/// How many of the last segments count as the tail.
+const TAIL_SEGMENTS: usize = 2;
+
+pub struct Worm {
+    kinematics: WormKinematics,
+    spawn_tick: Tick,
+    max_health: f32,
+    /// Body segments, ordered from the head to the tail.
+    segments: Vec<Entity>,
+}
+
+impl Worm {
+    pub fn new(max_health: f32, spawn_tick: Tick) -> Self {
+        Self {
+            max_health,
+            spawn_tick,
+            ..default()
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        self.max_health
+    }
+
+    /// Returns the damage multiplier for a hit at `point`, based on whether the
+    /// head or a body segment is closest to it.
+    pub fn hit_multiplier(
+        &self,
+        head: &GlobalTransform,
+        point: Vec3,
+        segments: &Query<(&GlobalTransform, &WormSegment)>,
+    ) -> f32 {
+        let head_hit = (head.translation().distance(point), HEAD_HIT_MULTIPLIER);
+        self.segments
+            .iter()
+            .filter_map(|segment| segments.get(*segment).ok())
+            .map(|(t, segment)| (t.translation().distance(point), segment.hit_multiplier))
+            .chain(std::iter::once(head_hit))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map_or(BODY_HIT_MULTIPLIER, |(_, multiplier)| multiplier)
+    }
+}
+
+/// A piece of a worm's body. Segments aren't children of the head, they trail
+/// behind it in world space using follow-the-leader constraints. Only the
+/// head is replicated, segments are simulated locally on the server and the
+/// clients.
+#[derive(Component)]
+pub struct WormSegment {
+    /// The head this segment belongs to.
+    pub worm: Entity,
+    /// 0 is the segment right behind the head.
+    pub index: usize,
+    pub hit_multiplier: f32,
+}
+
+/// Spawns the body segments of a worm in a line behind the head.
+fn spawn_segments(
+    world: &mut DeferredWorld,
+    head: Entity,
+    material: Handle<StandardMaterial>,
+) -> Vec<Entity> {
+    let head_t = world.get::<Transform>(head).copied().unwrap_or_default();
+    (0..SEGMENT_COUNT)
+        .map(|index| {
+            let along = index as f32 / (SEGMENT_COUNT - 1) as f32;
+            let radius = SEGMENT_RADIUS + (TAIL_RADIUS - SEGMENT_RADIUS) * along;
+            let hit_multiplier = if index >= SEGMENT_COUNT - TAIL_SEGMENTS {
+                TAIL_HIT_MULTIPLIER
+            } else {
+                BODY_HIT_MULTIPLIER
+            };
+            let mesh = world
+                .resource::<AssetServer>()
+                .add(Sphere::new(radius).mesh().build());
+            let translation =
+                head_t.translation + head_t.back() * SEGMENT_SPACING * (index + 1) as f32;
+            world
+                .commands()
+                .spawn((
+                    WormSegment {
+                        worm: head,
+                        index,
+                        hit_multiplier,
+                    },
+                    PbrBundle {
+                        mesh,
+                        material: material.clone(),
+                        transform: Transform::from_translation(translation),
+                        ..default()
+                    },
+                    // Sensors so that the body doesn't get in the way of
+                    // movement, but can still be shot.
+                    RigidBody::KinematicPositionBased,
+                    Collider::ball(radius),
+                    Sensor,
+                ))
+                .id()
+        })
+        .collect()
+}
+
+impl VelocityCalculator for Worm {
+    fn get_velocity(&self) -> Vec3 {
+        self.kinematics.get_velocity()
+    }
+}
+
+impl Component for Worm {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+
+    fn register_component_hooks(hooks: &mut ComponentHooks) {
+        hooks.on_add(|mut world, entity, _component_id| {
+            let is_server = world.get_resource::<IsServer>().is_some();
+            let max_health = world.get::<Worm>(entity).unwrap().max_health;
+            let asset_server = world.resource::<AssetServer>();
+            let mesh = asset_server.add(Sphere::new(HEAD_RADIUS).mesh().build());
+            let material = asset_server.add(StandardMaterial {
+                base_color: BROWN.into(),
+                ..default()
+            });
+            let segments = spawn_segments(&mut world, entity, material.clone());
+            world.get_mut::<Worm>(entity).unwrap().segments = segments;
+            if is_server {
+                world.commands().entity(entity).insert((
+                    Replicated,
+                    mesh,
+                    material,
+                    RigidBody::KinematicPositionBased,
+                    KinematicCharacterController::default(),
+                    Collider::ball(HEAD_RADIUS),
+                    Health::new(max_health),
+                    Grounded::default(),
+                    WormAi::default(),
+                ));
+            } else {
+                let spawn_tick = world.get::<Worm>(entity).unwrap().spawn_tick.clone();
+                world.commands().entity(entity).insert((
+                    mesh,
+                    material,
+                    RigidBody::KinematicPositionBased,
+                    Collider::ball(HEAD_RADIUS),
+                    Health::new(max_health),
+                    LastSyncTracker::<Transform>::new(spawn_tick),
+                    LastSyncTracker::<Health>::new(spawn_tick),
+                ));
+            }
+        });
+        hooks.on_remove(|mut world, entity, _component_id| {
+            let segments = world.get::<Worm>(entity).unwrap().segments.clone();
+            for segment in segments {
+                world.commands().entity(segment).despawn();
+            }
+        });
+    }
+}
+
+impl Default for Worm {
+    fn default() -> Self {
+        Worm {
+            kinematics: WormKinematics::default(),
+            spawn_tick: Tick::new(0),
+            max_health: WORM_HEALTH,
+            segments: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct DidRun(bool);
+
+fn setup(mut did_run: Local<DidRun>, mut commands: Commands) {
+    if did_run.0 {
+        return;
+    }
+    did_run.0 = true;
+
+    commands.spawn((
+        Worm::default(),
+        SpatialBundle::from_transform(Transform::from_translation(Vec3::new(0.0, 10.0, 0.0))),
+        NetworkObject::new_static(1),
+    ));
+}
+
+#[derive(Debug, Clone)]
+pub enum AirTime {
+    Grounded,
+    Airborne(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct WormKinematics {
+    time_in_air: AirTime,
+    /// Horizontal movement, set by the AI on the server.
+    walk_velocity: Vec3,
+}
+
+impl Default for WormKinematics {
+    fn default() -> Self {
+        Self {
+            time_in_air: AirTime::Grounded,
+            walk_velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl WormKinematics {
+    pub fn tick(&mut self, delta: Duration) {
+        self.time_in_air = match self.time_in_air {
+            AirTime::Grounded => AirTime::Airborne(delta),
+            AirTime::Airborne(time) => AirTime::Airborne(time + delta),
+        };
+    }
+
+    pub fn update(&mut self, is_grounded: bool) {
+        if is_grounded {
+            self.time_in_air = AirTime::Grounded;
+        }
+    }
+
+    pub fn get_velocity(&self) -> Vec3 {
+        let gravity = match self.time_in_air {
+            AirTime::Airborne(duration) => Vec3::Y * -10.0 * duration.as_secs_f32(),
+            AirTime::Grounded => Vec3::ZERO,
+        };
+        gravity + self.walk_velocity
+    }
+}
+
+fn tick_kinematics(mut worms: Query<(&mut Worm, &Grounded)>, time: Res<Time>) {
+    for (mut worm, grounded) in worms.iter_mut() {
+        worm.kinematics.update(grounded.is_grounded());
+        worm.kinematics.tick(time.delta());
+    }
+}
+
+/// Drags each segment towards the one in front of it, starting from the head,
+/// so that the body follows the path of the head.
+fn follow_head(worms: Query<(Entity, &Worm)>, mut transforms: Query<&mut Transform>) {
+    for (head, worm) in worms.iter() {
+        let Ok(mut leader) = transforms.get(head).map(|t| t.translation) else {
+            continue;
+        };
+        for segment in worm.segments.iter() {
+            let Ok(mut t) = transforms.get_mut(*segment) else {
+                continue;
+            };
+            let offset = t.translation - leader;
+            if offset.length() > SEGMENT_SPACING {
+                t.translation = leader + offset.normalize() * SEGMENT_SPACING;
+            }
+            if offset.length_squared() > 0.0 {
+                t.look_at(leader, Vec3::Y);
+            }
+            leader = t.translation;
+        }
+    }
+}
+
+fn send_transforms(
+    mut server: ResMut<RenetServer>,
+    worms: Query<(&Transform, &NetworkObject), With<Worm>>,
+    relevancy: Res<Relevancy>,
+    tick: Res<Tick>,
+) {
+    for (worm_t, net_obj) in worms.iter() {
+        let message =
+            UnreliableMessageFromServer::TransformSync(net_obj.clone(), *worm_t, tick.clone());
+        let bytes = bincode::serialize(&message).unwrap();
+        for client_id in relevancy.clients_for(net_obj, *tick) {
+            server.send_message(client_id, DefaultChannel::Unreliable, bytes.clone());
+        }
+    }
+}
+
+fn recv_transforms(
+    reader: Res<MessageReaderOnClient>,
+    mut worms: Query<
+        (
+            &mut Transform,
+            &NetworkObject,
+            &mut LastSyncTracker<Transform>,
+        ),
+        With<Worm>,
+    >,
+) {
+    for msg in reader.unreliable_messages() {
+        let UnreliableMessageFromServer::TransformSync(net_obj, new_t, tick) = msg else {
+            continue;
+        };
+        for (mut t, obj, mut sync) in worms.iter_mut() {
+            if obj == net_obj && sync.should_update(*tick) {
+                *t = *new_t;
+            }
+        }
+    }
+}
+
+fn serialize_worm(entity: EntityRef) -> Option<NetworkSpawn> {
+    let worm = entity.get::<Worm>()?;
+    Some(NetworkSpawn::Worm(WormSpawn {
+        transform: *entity.get::<Transform>()?,
+        max_health: worm.max_health,
+    }))
+}
+
+fn despawn_dead_worms(
+    mut server: ResMut<RenetServer>,
+    mut commands: Commands,
+    worms: Query<(Entity, &NetworkObject, &Health), With<Worm>>,
+) {
+    for (entity, net_obj, health) in worms.iter() {
+        if health.current <= 0.0 {
+            despawn_recursive_and_broadcast(&mut server, &mut commands, entity, net_obj.clone());
+        }
+    }
+}
+
+/// Handles `Spawn` events from the server and spawns worms. Worms that are
+/// already spawned are skipped.
+fn spawn_worms(
+    reader: Res<MessageReaderOnClient>,
+    existing: Query<&NetworkObject>,
+    mut commands: Commands,
+) {
+    for msg in reader.reliable_messages() {
+        let ReliableMessageFromServer::Spawn(spawn) = msg else {
+            continue;
+        };
+        let NetworkSpawn::Worm(worm_spawn) = &spawn.net_spawn else {
+            continue;
+        };
+        if existing.iter().any(|net_obj| *net_obj == spawn.net_obj) {
+            continue;
+        }
+        commands.spawn((
+            Worm::new(worm_spawn.max_health, spawn.tick),
+            SpatialBundle::from_transform(worm_spawn.transform),
+            spawn.net_obj.clone(),
+        ));
+    }
+}
@@ -11,6 +11,9 @@ use super::{
     GameLogic,
 };
 
+/// Mass characters push dynamic bodies with when they walk into them.
+pub(crate) const CHARACTER_MASS: f32 = 40.0;
+
 pub struct PhysicsPlugin {
     pub debug: bool,
 }
@@ -82,9 +85,11 @@ pub fn apply_kinematics(
         collider,
         transform.translation,
         transform.rotation,
-        0.0,
+        CHARACTER_MASS,
         &char_ctrl_to_move_opts(controller),
-        QueryFilter::default().exclude_collider(entity),
+        QueryFilter::default()
+            .exclude_collider(entity)
+            .exclude_sensors(),
         |_| {},
     );
     set_grounded(grounded, output.grounded);
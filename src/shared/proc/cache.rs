@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{chunk::ChunkMetadata, scatter::PropPlacement};
+
+/// Bump this when generation changes, so old chunks on disk aren't used.
+const CACHE_VERSION: u32 = 2;
+/// How many chunks are kept in memory. Each one is about 40KB.
+pub const DEFAULT_CACHE_CAPACITY: usize = 128;
+
+/// Everything expensive about generating a chunk, that doesn't depend on the
+/// Bevy world.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ChunkData {
+    /// Surface heights at the chunk's LOD, row by row along X.
+    pub heights: Vec<f32>,
+    /// Ground colors, as sRGBA bytes with one pixel per world unit.
+    pub albedo: Vec<u8>,
+    pub props: Vec<PropPlacement>,
+    /// Lowest point of the full detail surface.
+    pub lowest_point: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkKey {
+    pub seed: u64,
+    pub position: IVec2,
+    pub lod: usize,
+}
+
+impl ChunkKey {
+    pub fn new(seed: u64, meta: &ChunkMetadata) -> Self {
+        Self {
+            seed,
+            position: meta.position,
+            lod: meta.lod,
+        }
+    }
+}
+
+/// Chunks that were generated before. The most recently used ones are kept in
+/// memory, and if a directory is set, every chunk is also written to disk so
+/// that it survives restarts. Cloned into chunk generation tasks.
+#[derive(Resource, Clone)]
+pub struct ChunkCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+struct CacheInner {
+    capacity: usize,
+    /// The value is when the chunk was last used.
+    memory: HashMap<ChunkKey, (Arc<ChunkData>, u64)>,
+    uses: u64,
+    directory: Option<PathBuf>,
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize, directory: Option<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(CacheInner {
+                capacity,
+                memory: HashMap::new(),
+                uses: 0,
+                directory,
+            })),
+        }
+    }
+
+    /// Keeps chunks in memory, and on disk if `CHUNK_CACHE_DIR` is set.
+    pub fn from_env() -> Self {
+        let directory = std::env::var_os("CHUNK_CACHE_DIR").map(PathBuf::from);
+        if let Some(directory) = &directory {
+            info!("caching chunks in {}", directory.display());
+        }
+        Self::new(DEFAULT_CACHE_CAPACITY, directory)
+    }
+
+    /// Looks in memory first, then on disk.
+    pub fn get(&self, key: &ChunkKey) -> Option<Arc<ChunkData>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.uses += 1;
+        let uses = inner.uses;
+        if let Some((data, last_used)) = inner.memory.get_mut(key) {
+            *last_used = uses;
+            return Some(data.clone());
+        }
+        let path = inner.path(key)?;
+        // Reading is done without holding the lock, so other tasks aren't
+        // blocked on the disk.
+        drop(inner);
+        let data = match fs::read(&path).map(|bytes| bincode::deserialize::<ChunkData>(&bytes)) {
+            Ok(Ok(data)) => Arc::new(data),
+            Ok(Err(e)) => {
+                warn!("corrupt cached chunk {}: {e}", path.display());
+                return None;
+            }
+            Err(_) => return None,
+        };
+        self.inner.lock().unwrap().remember(*key, data.clone());
+        Some(data)
+    }
+
+    pub fn insert(&self, key: ChunkKey, data: Arc<ChunkData>) {
+        let mut inner = self.inner.lock().unwrap();
+        let path = inner.path(&key);
+        inner.remember(key, data.clone());
+        drop(inner);
+        let Some(path) = path else {
+            return;
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, bincode::serialize(&*data).unwrap()));
+        if let Err(e) = written {
+            warn!("couldn't cache chunk to {}: {e}", path.display());
+        }
+    }
+}
+
+impl CacheInner {
+    fn path(&self, key: &ChunkKey) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        Some(
+            directory
+                .join(format!("v{CACHE_VERSION}"))
+                .join(format!("{:016x}", key.seed))
+                .join(format!(
+                    "{}_{}_{}.bin",
+                    key.position.x, key.position.y, key.lod
+                )),
+        )
+    }
+
+    /// Keeps a chunk in memory, evicting the least recently used one if the
+    /// cache is full.
+    fn remember(&mut self, key: ChunkKey, data: Arc<ChunkData>) {
+        self.uses += 1;
+        self.memory.insert(key, (data, self.uses));
+        if self.memory.len() > self.capacity {
+            let oldest = self
+                .memory
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.memory.remove(&oldest);
+            }
+        }
+    }
+}
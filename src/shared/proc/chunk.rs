@@ -1,17 +1,22 @@
 use bevy::prelude::*;
 
+use super::scatter::PropKind;
+
 #[derive(Debug)]
 pub struct ChunkMetadata {
     pub position: IVec2,
     /// Level of Detail. This is only useful for rendering on the client.
     /// This is always 1 on the server. 1 means there is no loss in detail.
     pub lod: usize,
+    /// Whether the chunk has a (full detail) collider. Clients only need them
+    /// near their player.
+    pub has_collider: bool,
 
     // Width and height in world units.
     pub size: usize,
 }
 
-/// Describes a chunk parent entity. Everything local to the chunk (floor, trees,
+/// Describes a chunk parent entity. Everything local to the chunk (floor, props,
 /// but not entities that can walk across chunks) is a child of this.
 #[derive(Component, Debug)]
 pub struct Chunk<B: 'static + Send + Sync> {
@@ -19,12 +24,17 @@ pub struct Chunk<B: 'static + Send + Sync> {
     pub biome_data: B,
 }
 
+/// A kind of terrain, e.g. a grassy desert. The terrain picks and blends
+/// between biomes using its `ClimateMap`, so everything here is sampled at
+/// world positions.
 pub trait BiomeGenerator: Send + Sync + 'static {
-    type BiomeData;
+    /// Height of the biome's surface.
+    fn height(&self, world_x: f64, world_z: f64) -> f32;
+
+    /// Color of the ground. `height` is the already blended terrain height.
+    fn ground_color(&self, world_x: f64, world_z: f64, height: f32) -> LinearRgba;
 
-    fn generate_biome_data(
-        &self,
-        meta: &ChunkMetadata,
-        asset_server: &AssetServer,
-    ) -> Self::BiomeData;
+    /// Whether a prop of a kind can be scattered here. Props are never placed
+    /// in water, whatever the biome says.
+    fn can_scatter(&self, kind: PropKind, world_x: f64, world_z: f64, height: f32) -> bool;
 }
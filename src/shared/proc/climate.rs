@@ -0,0 +1,65 @@
+use noise::{NoiseFn, Perlin};
+
+/// Decides which biome is where. A low frequency noise is split into one band
+/// per biome, and biomes are blended where two bands meet.
+#[derive(Clone)]
+pub struct ClimateMap {
+    noise: Perlin,
+    frequency: f64,
+    /// Upper end of every band but the last, in increasing order.
+    bounds: Vec<f64>,
+    /// How far around a bound the two biomes are blended, in noise units.
+    blend_width: f64,
+}
+
+impl ClimateMap {
+    /// Splits the climate evenly between `biome_count` biomes.
+    pub fn new(seed: u32, biome_count: usize) -> Self {
+        // Perlin noise rarely goes outside of this range.
+        let (min, max) = (-0.5, 0.5);
+        let bounds = (1..biome_count)
+            .map(|i| min + (max - min) * i as f64 / biome_count as f64)
+            .collect();
+        Self {
+            noise: Perlin::new(seed),
+            frequency: 0.0015,
+            bounds,
+            blend_width: 0.05,
+        }
+    }
+
+    pub fn biome_count(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// Returns how much each biome contributes at a world position. The weights
+    /// add up to one.
+    pub fn weights(&self, world_x: f64, world_z: f64) -> Vec<f32> {
+        let climate = self
+            .noise
+            .get([world_x * self.frequency, world_z * self.frequency]);
+        // How far the climate is past each bound, from 0 to 1.
+        let past = |bound: f64| {
+            let t = ((climate - bound) / self.blend_width * 0.5 + 0.5).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let mut weights = Vec::with_capacity(self.biome_count());
+        let mut below = 1.0;
+        for bound in self.bounds.iter() {
+            let above = past(*bound);
+            weights.push((below - above) as f32);
+            below = above;
+        }
+        weights.push(below as f32);
+        weights
+    }
+
+    /// Returns the biome with the most weight at a world position.
+    pub fn dominant_biome(&self, world_x: f64, world_z: f64) -> usize {
+        self.weights(world_x, world_z)
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(0, |(i, _)| i)
+    }
+}
@@ -0,0 +1,366 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_renet::renet::{DefaultChannel, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{client::MessageReaderOnClient, server::ReliableMessageFromServer},
+    server::PlayerWantsUpdates,
+    shared::{
+        console::{
+            command::{CommandOutput, RunCommand},
+            ConsoleAppExt, ConsoleCommand,
+        },
+        GameLogic,
+    },
+};
+
+use super::{terrain::Terrain, ChunkGeneration};
+
+/// Replicates changes to the terrain made while the game is running. The
+/// server decides on edits and clients apply the same ones in the same way.
+pub struct TerrainEditPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for TerrainEditPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_console_command(
+            ConsoleCommand::new("crater", "Digs a crater into the terrain.")
+                .usage("<x> <z> [radius] [depth]")
+                .on_server()
+                .admin(),
+        )
+        .add_console_command(
+            ConsoleCommand::new(
+                "brush",
+                "Raises the terrain, or lowers it with a negative amount.",
+            )
+            .usage("<x> <z> <radius> <amount>")
+            .on_server()
+            .admin(),
+        );
+        if self.is_server {
+            app.add_event::<EditTerrain>();
+            app.init_resource::<TerrainEditLog>();
+            app.add_systems(
+                FixedUpdate,
+                (
+                    (run_edit_commands, apply_terrain_edits)
+                        .chain()
+                        .run_if(resource_exists::<Terrain>)
+                        .in_set(GameLogic::Game),
+                    send_terrain_edits.in_set(GameLogic::Sync),
+                ),
+            );
+        } else {
+            app.init_resource::<ReceivedTerrainEdits>();
+            app.add_systems(FixedUpdate, recv_terrain_edits.in_set(GameLogic::Sync));
+        }
+    }
+}
+
+/// A change to the height of the terrain around a point. Edits only add to
+/// the height, so the order they're applied in doesn't matter.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum TerrainEdit {
+    /// Digs a bowl into the ground, deepest in the middle.
+    Crater {
+        center: Vec2,
+        radius: f32,
+        depth: f32,
+    },
+    /// Smoothly raises the ground, or lowers it if `amount` is negative.
+    Brush {
+        center: Vec2,
+        radius: f32,
+        amount: f32,
+    },
+}
+
+impl TerrainEdit {
+    pub fn center(&self) -> Vec2 {
+        match self {
+            Self::Crater { center, .. } | Self::Brush { center, .. } => *center,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match self {
+            Self::Crater { radius, .. } | Self::Brush { radius, .. } => *radius,
+        }
+    }
+
+    /// How much the height changes at a distance from the center.
+    pub fn delta(&self, distance: f32) -> f32 {
+        let t = (distance / self.radius()).clamp(0.0, 1.0);
+        match self {
+            Self::Crater { depth, .. } => -depth * (1.0 - t * t),
+            Self::Brush { amount, .. } => {
+                let falloff = 1.0 - t;
+                amount * falloff * falloff * (3.0 - 2.0 * falloff)
+            }
+        }
+    }
+}
+
+/// Height changes made to the terrain at runtime, on top of its noise. Each
+/// chunk stores the deltas of its own full detail grid points. The points
+/// along a chunk's far edges belong to its neighbours.
+#[derive(Clone, Default)]
+pub struct TerrainEdits {
+    chunks: HashMap<IVec2, Vec<f32>>,
+}
+
+impl TerrainEdits {
+    /// `point` is in full detail grid points from the world origin.
+    /// `points_per_chunk` is the number of grid cells along a chunk's side.
+    pub fn get(&self, point: IVec2, points_per_chunk: i32) -> f32 {
+        let (chunk, index) = Self::locate(point, points_per_chunk);
+        self.chunks.get(&chunk).map_or(0.0, |deltas| deltas[index])
+    }
+
+    pub fn add(&mut self, point: IVec2, points_per_chunk: i32, delta: f32) {
+        let (chunk, index) = Self::locate(point, points_per_chunk);
+        let points = (points_per_chunk * points_per_chunk) as usize;
+        let deltas = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| vec![0.0; points]);
+        deltas[index] += delta;
+    }
+
+    /// Whether any of a chunk's grid points, including the ones shared with
+    /// its neighbours, were edited.
+    pub fn touches(&self, chunk: IVec2) -> bool {
+        [IVec2::ZERO, IVec2::X, IVec2::Y, IVec2::ONE]
+            .into_iter()
+            .any(|offset| self.chunks.contains_key(&(chunk + offset)))
+    }
+
+    fn locate(point: IVec2, points_per_chunk: i32) -> (IVec2, usize) {
+        let chunk = point.div_euclid(IVec2::splat(points_per_chunk));
+        let local = point - chunk * points_per_chunk;
+        (chunk, (local.y * points_per_chunk + local.x) as usize)
+    }
+}
+
+/// Sent on the server to change the terrain. The edit is applied and sent to
+/// every client. Only the `crater` and `brush` admin commands send it for
+/// now, as there are no explosive weapons to dig craters with yet.
+#[derive(Event)]
+pub struct EditTerrain(pub TerrainEdit);
+
+/// Every edit made so far, so that clients joining later can catch up.
+#[derive(Resource, Default)]
+struct TerrainEditLog(Vec<TerrainEdit>);
+
+/// Edits a client was sent, by their index in the server's log. A client
+/// that becomes ready while edits are being made gets them both as they're
+/// made and in the log, and edits add up, so each must only be applied once.
+#[derive(Resource, Default)]
+pub struct ReceivedTerrainEdits {
+    seen: HashSet<usize>,
+    /// Edits can arrive before the terrain exists, so they wait until it does.
+    pending: Vec<TerrainEdit>,
+}
+
+/// Turns the `crater` and `brush` console commands into edits.
+fn run_edit_commands(
+    mut run: EventReader<RunCommand>,
+    mut output: EventWriter<CommandOutput>,
+    mut edits: EventWriter<EditTerrain>,
+) {
+    for command in run.read() {
+        if !["crater", "brush"].contains(&command.name.as_str()) {
+            continue;
+        }
+        match parse_edit(&command.name, &command.args) {
+            Ok(edit) => {
+                edits.send(EditTerrain(edit));
+                output.send(command.reply(format!("edited terrain at {}", edit.center())));
+            }
+            Err(text) => {
+                output.send(command.error(text));
+            }
+        }
+    }
+}
+
+fn parse_edit(name: &str, args: &[String]) -> Result<TerrainEdit, String> {
+    let numbers: Vec<f32> = args
+        .iter()
+        .map(|arg| arg.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "arguments must be numbers".to_string())?;
+    let edit = match (name, numbers.as_slice()) {
+        ("crater", [x, z, rest @ ..]) if rest.len() <= 2 => TerrainEdit::Crater {
+            center: Vec2::new(*x, *z),
+            radius: rest.first().copied().unwrap_or(8.0),
+            depth: rest.get(1).copied().unwrap_or(3.0),
+        },
+        ("brush", [x, z, radius, amount]) => TerrainEdit::Brush {
+            center: Vec2::new(*x, *z),
+            radius: *radius,
+            amount: *amount,
+        },
+        ("crater", _) => return Err("usage: crater <x> <z> [radius] [depth]".to_string()),
+        _ => return Err("usage: brush <x> <z> <radius> <amount>".to_string()),
+    };
+    if edit.radius() <= 0.0 {
+        return Err("radius must be positive".to_string());
+    }
+    Ok(edit)
+}
+
+/// Applies an edit and regenerates the chunks it changed.
+fn apply_edit(edit: &TerrainEdit, terrain: &mut Terrain, generation: &mut ChunkGeneration) {
+    let changed = terrain.apply_edit(edit);
+    generation.regenerate(changed);
+}
+
+fn apply_terrain_edits(
+    mut edits: EventReader<EditTerrain>,
+    mut terrain: ResMut<Terrain>,
+    mut generation: ResMut<ChunkGeneration>,
+    mut log: ResMut<TerrainEditLog>,
+    mut server: ResMut<RenetServer>,
+) {
+    let edits: Vec<TerrainEdit> = edits.read().map(|edit| edit.0).collect();
+    if edits.is_empty() {
+        return;
+    }
+    for edit in edits.iter() {
+        apply_edit(edit, &mut terrain, &mut generation);
+    }
+    let first = log.0.len();
+    log.0.extend(edits.iter().copied());
+    let message = ReliableMessageFromServer::TerrainEdits(first, edits);
+    let bytes = bincode::serialize(&message).unwrap();
+    server.broadcast_message(DefaultChannel::ReliableUnordered, bytes);
+}
+
+/// Sends every edit so far to clients that just became ready.
+fn send_terrain_edits(
+    mut player_load: EventReader<PlayerWantsUpdates>,
+    log: Res<TerrainEditLog>,
+    mut server: ResMut<RenetServer>,
+) {
+    for load in player_load.read() {
+        if log.0.is_empty() {
+            continue;
+        }
+        let message = ReliableMessageFromServer::TerrainEdits(0, log.0.clone());
+        let bytes = bincode::serialize(&message).unwrap();
+        server.send_message(load.client_id, DefaultChannel::ReliableUnordered, bytes);
+    }
+}
+
+fn recv_terrain_edits(
+    reader: Res<MessageReaderOnClient>,
+    terrain: Option<ResMut<Terrain>>,
+    mut generation: ResMut<ChunkGeneration>,
+    mut received: ResMut<ReceivedTerrainEdits>,
+) {
+    let received = &mut *received;
+    for msg in reader.reliable_messages() {
+        if let ReliableMessageFromServer::TerrainEdits(first, edits) = msg {
+            for (index, edit) in (*first..).zip(edits) {
+                if received.seen.insert(index) {
+                    received.pending.push(*edit);
+                }
+            }
+        }
+    }
+    let Some(mut terrain) = terrain else {
+        return;
+    };
+    for edit in received.pending.drain(..) {
+        apply_edit(&edit, &mut terrain, &mut generation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_renet::renet::ConnectionConfig;
+
+    use crate::shared::console::command::CommandSender;
+
+    use super::*;
+
+    #[test]
+    fn crater_command_reaches_late_joining_client() {
+        let mut server = World::new();
+        server.insert_resource(Terrain::new(42));
+        server.init_resource::<ChunkGeneration>();
+        server.init_resource::<TerrainEditLog>();
+        server.insert_resource(RenetServer::new(ConnectionConfig::default()));
+        server.init_resource::<Events<RunCommand>>();
+        server.init_resource::<Events<CommandOutput>>();
+        server.init_resource::<Events<EditTerrain>>();
+        let before = server.resource::<Terrain>().height_at(0.0, 0.0);
+
+        server.send_event(RunCommand {
+            name: "crater".to_string(),
+            args: ["0", "0", "12", "3"].map(String::from).to_vec(),
+            sender: CommandSender::Local,
+        });
+        server.run_system_once(run_edit_commands);
+        server.run_system_once(apply_terrain_edits);
+
+        let after = server.resource::<Terrain>().height_at(0.0, 0.0);
+        assert!((after - (before - 3.0)).abs() < 1e-4);
+        assert!(server
+            .resource::<ChunkGeneration>()
+            .stale
+            .contains(&IVec2::ZERO));
+        let log = server.resource::<TerrainEditLog>().0.clone();
+        assert_eq!(log.len(), 1);
+
+        // A client that joins later is sent the log and ends up with the same
+        // terrain.
+        let client = recv_edits([ReliableMessageFromServer::TerrainEdits(0, log)]);
+
+        assert_eq!(client.resource::<Terrain>().height_at(0.0, 0.0), after);
+        assert!(client
+            .resource::<ChunkGeneration>()
+            .stale
+            .contains(&IVec2::ZERO));
+    }
+
+    #[test]
+    fn edit_sent_live_and_in_log_is_applied_once() {
+        let edit = TerrainEdit::Crater {
+            center: Vec2::ZERO,
+            radius: 12.0,
+            depth: 3.0,
+        };
+        let before = Terrain::new(42).height_at(0.0, 0.0);
+        // The client became ready right as the edit was made, so it gets the
+        // broadcast and then the log with the same edit in it.
+        let client = recv_edits([
+            ReliableMessageFromServer::TerrainEdits(0, vec![edit]),
+            ReliableMessageFromServer::TerrainEdits(0, vec![edit]),
+        ]);
+        let after = client.resource::<Terrain>().height_at(0.0, 0.0);
+        assert!((after - (before - 3.0)).abs() < 1e-4);
+    }
+
+    /// A client world that received `messages` from the server.
+    fn recv_edits(messages: impl IntoIterator<Item = ReliableMessageFromServer>) -> World {
+        let mut client = World::new();
+        client.insert_resource(Terrain::new(42));
+        client.init_resource::<ChunkGeneration>();
+        client.init_resource::<ReceivedTerrainEdits>();
+        let mut reader = MessageReaderOnClient::default();
+        for message in messages {
+            reader.push_reliable(message);
+        }
+        client.insert_resource(reader);
+        client.run_system_once(recv_terrain_edits);
+        client
+    }
+}
@@ -1,16 +1,32 @@
-use bevy::{prelude::*, utils::HashSet};
-use chunk::{BiomeGenerator, Chunk, ChunkMetadata};
-use grassy_desert::{gen_grassy_desert_trees, GrassyDesertBiomeData, GrassyDesertTerrain};
-use shaders::GrassDesert;
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use cache::ChunkCache;
+use chunk::{Chunk, ChunkMetadata};
+use shaders::TerrainMaterial;
+use terrain::{GeneratedChunk, Terrain, TerrainChunkData};
 use utils::{generate_chunks_around, ProcUtilsPlugin};
+use water::WaterMaterial;
 
-use self::tree::TreePlugin;
+use self::{scatter::ScatterPlugin, tree::TreePlugin};
 
+use super::IsServer;
+
+pub mod cache;
 pub mod chunk;
+pub mod climate;
+pub mod edits;
 pub mod grassy_desert;
+pub mod navmesh;
+pub mod scatter;
 pub mod shaders;
+pub mod snowy_mountains;
+pub mod terrain;
 pub mod tree;
 pub mod utils;
+pub mod water;
 
 fn res_exists<T: Resource>(resource: Option<Res<T>>) -> bool {
     resource.is_some()
@@ -20,64 +36,216 @@ pub struct TerrainPlugin;
 
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (load_grassy_desert_chunks, gen_grassy_desert_trees)
-                .run_if(res_exists::<GrassyDesertTerrain>),
-        );
-        app.add_plugins((TreePlugin, ProcUtilsPlugin));
-        app.add_plugins(MaterialPlugin::<GrassDesert>::default());
+        app.add_systems(FixedUpdate, load_chunks.run_if(res_exists::<Terrain>));
+        app.init_resource::<ChunkGeneration>();
+        app.insert_resource(ChunkCache::from_env());
+        app.add_plugins((TreePlugin, ScatterPlugin, ProcUtilsPlugin));
+        app.add_plugins((
+            MaterialPlugin::<TerrainMaterial>::default(),
+            MaterialPlugin::<WaterMaterial>::default(),
+        ));
     }
 }
 
 const SERVER_LOD: usize = 1;
+/// Maximum number of chunks being generated in the background at once.
+const MAX_CHUNK_TASKS: usize = 8;
+/// Maximum number of generated chunks spawned into the world per tick.
+const CHUNK_SPAWN_BUDGET: usize = 2;
+/// Clients only get colliders for chunks within this Chebyshev distance of
+/// their player.
+const CLIENT_COLLIDER_DISTANCE: i32 = 1;
+
+/// Seed the world is generated from. Chosen by the server and sent to clients
+/// when they join.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    /// Uses `WORLD_SEED` if it's set, so that the same world (and its cached
+    /// chunks) can be used across server restarts.
+    pub fn from_env_or_random() -> Self {
+        match std::env::var("WORLD_SEED").map(|seed| seed.parse()) {
+            Ok(Ok(seed)) => Self(seed),
+            Ok(Err(e)) => panic!("WORLD_SEED is not a valid seed: {e}"),
+            Err(_) => Self(rand::random()),
+        }
+    }
+}
 
 #[derive(Component)]
 pub struct LoadsChunks;
 
+/// How detailed a chunk should be.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct ChunkDetail {
+    lod: usize,
+    has_collider: bool,
+}
+
+impl ChunkDetail {
+    fn of(meta: &ChunkMetadata) -> Self {
+        Self {
+            lod: meta.lod,
+            has_collider: meta.has_collider,
+        }
+    }
+
+    /// Picks the detail of a chunk from its Chebyshev distance to the nearest
+    /// loader. The server always needs full detail and colliders everywhere.
+    fn for_distance(distance: i32, is_server: bool) -> Self {
+        if is_server {
+            return Self {
+                lod: SERVER_LOD,
+                has_collider: true,
+            };
+        }
+        let lod = match distance {
+            0 => 1,
+            1 => 2,
+            _ => 4,
+        };
+        Self {
+            lod,
+            has_collider: distance <= CLIENT_COLLIDER_DISTANCE,
+        }
+    }
+}
+
+/// Chunks that are being generated on the `AsyncComputeTaskPool`, or are
+/// generated and waiting to be spawned.
+#[derive(Resource, Default)]
+pub struct ChunkGeneration {
+    /// Dropping a task cancels it.
+    tasks: HashMap<IVec2, (ChunkDetail, Task<GeneratedChunk>)>,
+    ready: HashMap<IVec2, GeneratedChunk>,
+    /// Chunks that changed since they were generated. They're kept until their
+    /// replacement is spawned.
+    stale: HashSet<IVec2>,
+}
+
+impl ChunkGeneration {
+    /// Regenerates chunks, e.g. after the terrain was edited. Generation that
+    /// already started for them is thrown away, as it used the old terrain.
+    /// Loaded chunks stay until their replacement is ready.
+    pub fn regenerate(&mut self, chunks: impl IntoIterator<Item = IVec2>) {
+        for pos in chunks {
+            self.tasks.remove(&pos);
+            self.ready.remove(&pos);
+            self.stale.insert(pos);
+        }
+    }
+}
+
 /// Loads and unloads chunks based on if an entity with `LoadsChunks` exists
 /// there. Does this for all chunks nearby.
 ///
+/// Chunks are generated in the background, nearest to a loader first. Only
+/// `CHUNK_SPAWN_BUDGET` finished chunks are spawned per tick so that crossing
+/// a chunk border doesn't cause a hitch. On clients, chunks further away get a
+/// lower LOD and no collider, and are regenerated when that changes or when
+/// the chunk is edited. The old chunk stays until its replacement is ready.
+///
 /// On clients, LoadsChunks should only be present on the client's player.
 /// On the server, it should be on all players.
-pub fn load_grassy_desert_chunks(
+pub fn load_chunks(
     loaders: Query<&Transform, With<LoadsChunks>>,
-    chunks: Query<(Entity, &Chunk<GrassyDesertBiomeData>)>,
+    chunks: Query<(Entity, &Chunk<TerrainChunkData>)>,
+    mut generation: ResMut<ChunkGeneration>,
     mut commands: Commands,
-    terrain: Res<GrassyDesertTerrain>,
+    terrain: Res<Terrain>,
+    cache: Res<ChunkCache>,
     asset_server: Res<AssetServer>,
+    is_server: Option<Res<IsServer>>,
 ) {
-    let mut chunks_with_loaders: HashSet<IVec2> = HashSet::new();
+    // Distance to the nearest loader of every chunk that should be loaded.
+    let mut chunks_with_loaders: HashMap<IVec2, i32> = HashMap::new();
     for transform in loaders.iter() {
         let chunk = terrain.world_position_to_chunk(transform.translation);
-        let loaded_chunks = generate_chunks_around(chunk, terrain.radius)
-            .into_iter()
-            .map(|(pos, _)| pos);
-        chunks_with_loaders.extend(loaded_chunks);
+        for (pos, distance) in generate_chunks_around(chunk, terrain.radius) {
+            let nearest = chunks_with_loaders.entry(pos).or_insert(distance);
+            *nearest = (*nearest).min(distance);
+        }
     }
+    let wanted_detail =
+        |pos: &IVec2| ChunkDetail::for_distance(chunks_with_loaders[pos], is_server.is_some());
 
-    let mut loaded_chunks: HashSet<IVec2> = HashSet::new();
+    let mut up_to_date_chunks: HashSet<IVec2> = HashSet::new();
+    // Chunks that are shown until their replacement with a different detail is ready.
+    let mut outdated_chunks: HashMap<IVec2, Entity> = HashMap::new();
     for (entity, chunk) in chunks.iter() {
-        if chunks_with_loaders.contains(&chunk.meta.position) {
-            loaded_chunks.insert(chunk.meta.position);
-        } else {
+        let pos = chunk.meta.position;
+        if !chunks_with_loaders.contains_key(&pos) {
             terrain.unload_chunk(entity, &mut commands);
+        } else if ChunkDetail::of(&chunk.meta) == wanted_detail(&pos)
+            && !generation.stale.contains(&pos)
+        {
+            up_to_date_chunks.insert(pos);
+        } else {
+            outdated_chunks.insert(pos, entity);
         }
     }
 
-    let chunks_to_load = chunks_with_loaders.difference(&loaded_chunks);
-    for chunk_pos in chunks_to_load {
+    // Cancel generation of chunks that nobody is near anymore, or that were
+    // started with a different detail.
+    generation.tasks.retain(|pos, (detail, _)| {
+        chunks_with_loaders.contains_key(pos) && *detail == wanted_detail(pos)
+    });
+    generation.ready.retain(|pos, generated| {
+        chunks_with_loaders.contains_key(pos)
+            && ChunkDetail::of(&generated.meta) == wanted_detail(pos)
+    });
+
+    let mut chunks_to_load: Vec<(IVec2, i32)> = chunks_with_loaders
+        .iter()
+        .filter(|(pos, _)| {
+            !up_to_date_chunks.contains(*pos)
+                && !generation.tasks.contains_key(*pos)
+                && !generation.ready.contains_key(*pos)
+        })
+        .map(|(pos, distance)| (*pos, *distance))
+        .collect();
+    chunks_to_load.sort_by_key(|(_, distance)| *distance);
+    let free_slots = MAX_CHUNK_TASKS.saturating_sub(generation.tasks.len());
+    let task_pool = AsyncComputeTaskPool::get();
+    for (chunk_pos, _) in chunks_to_load.into_iter().take(free_slots) {
+        let detail = wanted_detail(&chunk_pos);
         let metadata = ChunkMetadata {
-            position: *chunk_pos,
-            lod: SERVER_LOD,
+            position: chunk_pos,
+            lod: detail.lod,
+            has_collider: detail.has_collider,
             size: terrain.chunk_size,
         };
-        let chunk = Chunk {
-            biome_data: terrain
-                .biome_generator
-                .generate_biome_data(&metadata, &asset_server),
-            meta: metadata,
-        };
-        terrain.render_chunk(chunk, &mut commands, &asset_server);
+        let terrain = terrain.clone();
+        let cache = cache.clone();
+        let task = task_pool.spawn(async move { terrain.generate_chunk(metadata, &cache) });
+        generation.tasks.insert(chunk_pos, (detail, task));
+    }
+    generation
+        .stale
+        .retain(|pos| chunks_with_loaders.contains_key(pos));
+
+    let ChunkGeneration {
+        tasks,
+        ready,
+        stale,
+    } = &mut *generation;
+    tasks.retain(|pos, (_, task)| match block_on(future::poll_once(task)) {
+        Some(generated) => {
+            ready.insert(*pos, generated);
+            false
+        }
+        None => true,
+    });
+
+    let mut spawn_order: Vec<IVec2> = ready.keys().copied().collect();
+    spawn_order.sort_by_key(|pos| chunks_with_loaders[pos]);
+    for pos in spawn_order.into_iter().take(CHUNK_SPAWN_BUDGET) {
+        if let Some(outdated) = outdated_chunks.remove(&pos) {
+            terrain.unload_chunk(outdated, &mut commands);
+        }
+        stale.remove(&pos);
+        let generated = ready.remove(&pos).unwrap();
+        terrain.render_chunk(generated, &mut commands, &asset_server);
     }
 }
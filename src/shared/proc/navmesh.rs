@@ -0,0 +1,409 @@
+use std::{cmp::Ordering, collections::BinaryHeap, f32::consts::FRAC_PI_4};
+
+use bevy::{
+    color::palettes::css::{GREEN, RED},
+    prelude::*,
+    utils::HashMap,
+};
+
+use super::{
+    chunk::Chunk,
+    res_exists,
+    scatter::Prop,
+    terrain::{Terrain, TerrainChunkData},
+    tree::Tree,
+};
+
+/// Width of a navigation cell in world units. Must divide the chunk size.
+pub const NAV_CELL_SIZE: f32 = 2.0;
+/// Matches the default `KinematicCharacterController::max_slope_climb_angle`.
+const MAX_WALKABLE_SLOPE: f32 = FRAC_PI_4;
+/// Cells within this distance of a tree's trunk are blocked.
+const TREE_BLOCK_RADIUS: f32 = 1.0;
+/// Cells within this distance of a solid prop are blocked, at a scale of one.
+const PROP_BLOCK_RADIUS: f32 = 0.5;
+/// A* gives up after expanding this many cells.
+const MAX_SEARCH_NODES: usize = 20_000;
+
+pub struct NavMeshPlugin;
+
+impl Plugin for NavMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavMesh>();
+        app.add_systems(
+            FixedUpdate,
+            (load_nav_chunks, block_obstacle_cells, unload_nav_chunks)
+                .chain()
+                .run_if(res_exists::<Terrain>),
+        );
+    }
+}
+
+/// The walkable grid of a single chunk.
+pub struct NavChunk {
+    /// Number of cells along each side of the chunk.
+    cells_per_side: usize,
+    walkable: Vec<bool>,
+    /// Terrain height at the center of each cell.
+    heights: Vec<f32>,
+}
+
+impl NavChunk {
+    fn generate(terrain: &Terrain, chunk_pos: IVec2) -> Self {
+        let cells_per_side = (terrain.chunk_size as f32 / NAV_CELL_SIZE) as usize;
+        let origin = chunk_pos * cells_per_side as i32;
+        let mut walkable = Vec::with_capacity(cells_per_side * cells_per_side);
+        let mut heights = Vec::with_capacity(cells_per_side * cells_per_side);
+        for z in 0..cells_per_side {
+            for x in 0..cells_per_side {
+                let (cell_walkable, height) =
+                    survey_cell(terrain, origin + IVec2::new(x as i32, z as i32));
+                walkable.push(cell_walkable);
+                heights.push(height);
+            }
+        }
+        Self {
+            cells_per_side,
+            walkable,
+            heights,
+        }
+    }
+
+    fn index(&self, local: IVec2) -> usize {
+        local.x as usize + local.y as usize * self.cells_per_side
+    }
+}
+
+/// Walkable grid for all loaded chunks. Cells are addressed by global cell
+/// coordinates, so paths can cross chunk borders as if the grid was one piece.
+#[derive(Resource, Default)]
+pub struct NavMesh {
+    chunks: HashMap<IVec2, NavChunk>,
+    /// Chunk entities, so the grid can be unloaded with the chunk.
+    chunk_entities: HashMap<Entity, IVec2>,
+    /// Trees and solid props, by entity.
+    obstacles: HashMap<Entity, Obstacle>,
+    cells_per_chunk: i32,
+}
+
+fn cell_center(cell: IVec2) -> Vec2 {
+    (cell.as_vec2() + Vec2::splat(0.5)) * NAV_CELL_SIZE
+}
+
+/// Returns whether the ground of a cell is walkable, leaving out obstacles,
+/// and its height at the center of the cell.
+fn survey_cell(terrain: &Terrain, cell: IVec2) -> (bool, f32) {
+    let center = cell_center(cell);
+    let height = |dx: f32, dz: f32| terrain.height_at(center.x + dx, center.y + dz);
+    // Central differences over the cell give the steepest slope in it.
+    let half = NAV_CELL_SIZE / 2.0;
+    let gradient = Vec2::new(
+        height(half, 0.0) - height(-half, 0.0),
+        height(0.0, half) - height(0.0, -half),
+    ) / NAV_CELL_SIZE;
+    let under_water = height(0.0, 0.0) < terrain.water_level;
+    let walkable = gradient.length().atan() <= MAX_WALKABLE_SLOPE && !under_water;
+    (walkable, height(0.0, 0.0))
+}
+
+/// Something that blocks the cells around it. Obstacles near a chunk border
+/// also block cells of the neighbouring chunk, so they're kept to block those
+/// again when the neighbour loads later.
+struct Obstacle {
+    /// The chunk the obstacle is in.
+    chunk: IVec2,
+    /// Position in the world.
+    center: Vec2,
+    radius: f32,
+}
+
+impl Obstacle {
+    fn covers(&self, cell: IVec2) -> bool {
+        cell_center(cell).distance(self.center) <= self.radius + NAV_CELL_SIZE / 2.0
+    }
+
+    /// Global coordinates of the cells the obstacle blocks.
+    fn cells(&self) -> impl Iterator<Item = IVec2> + '_ {
+        let reach = (self.radius / NAV_CELL_SIZE).ceil() as i32;
+        let center_cell = (self.center / NAV_CELL_SIZE).floor().as_ivec2();
+        (-reach..=reach)
+            .flat_map(move |z| (-reach..=reach).map(move |x| center_cell + IVec2::new(x, z)))
+            .filter(|cell| self.covers(*cell))
+    }
+}
+
+#[derive(PartialEq)]
+struct OpenCell {
+    /// Cost so far plus the heuristic.
+    estimate: f32,
+    cell: IVec2,
+}
+
+impl Eq for OpenCell {}
+
+impl Ord for OpenCell {
+    /// Reversed so that `BinaryHeap` pops the lowest estimate first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavMesh {
+    pub fn world_position_to_cell(&self, position: Vec3) -> IVec2 {
+        (position.xz() / NAV_CELL_SIZE).floor().as_ivec2()
+    }
+
+    /// Returns the ground position at the center of a cell, or `None` if its
+    /// chunk isn't loaded.
+    pub fn cell_to_world_position(&self, cell: IVec2) -> Option<Vec3> {
+        let (chunk, local) = self.split_cell(cell)?;
+        let center = cell_center(cell);
+        Some(Vec3::new(
+            center.x,
+            chunk.heights[chunk.index(local)],
+            center.y,
+        ))
+    }
+
+    /// Returns the chunk of a cell and the cell's position inside it.
+    fn split_cell(&self, cell: IVec2) -> Option<(&NavChunk, IVec2)> {
+        if self.cells_per_chunk == 0 {
+            return None;
+        }
+        let chunk_pos = cell.div_euclid(IVec2::splat(self.cells_per_chunk));
+        let local = cell.rem_euclid(IVec2::splat(self.cells_per_chunk));
+        self.chunks.get(&chunk_pos).map(|chunk| (chunk, local))
+    }
+
+    /// Does nothing if the cell's chunk isn't loaded.
+    fn set_walkable(&mut self, cell: IVec2, walkable: bool) {
+        if self.cells_per_chunk == 0 {
+            return;
+        }
+        let chunk_pos = cell.div_euclid(IVec2::splat(self.cells_per_chunk));
+        let local = cell.rem_euclid(IVec2::splat(self.cells_per_chunk));
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            let index = chunk.index(local);
+            chunk.walkable[index] = walkable;
+        }
+    }
+
+    /// Blocks the cells of every obstacle that reaches into a chunk, including
+    /// the ones in its neighbours.
+    fn block_obstacles_near(&mut self, chunk_pos: IVec2) {
+        let cells: Vec<IVec2> = self
+            .obstacles
+            .values()
+            .filter(|obstacle| (obstacle.chunk - chunk_pos).abs().max_element() <= 1)
+            .flat_map(|obstacle| obstacle.cells())
+            .collect();
+        for cell in cells {
+            self.set_walkable(cell, false);
+        }
+    }
+
+    /// Lets the cells of an obstacle that's gone, e.g. a felled tree, be walked
+    /// through again, unless the ground or another obstacle blocks them.
+    fn unblock(&mut self, removed: &Obstacle, terrain: &Terrain) {
+        for cell in removed.cells() {
+            let blocked = self.obstacles.values().any(|obstacle| {
+                (obstacle.chunk - removed.chunk).abs().max_element() <= 1 && obstacle.covers(cell)
+            });
+            let (walkable, _) = survey_cell(terrain, cell);
+            self.set_walkable(cell, walkable && !blocked);
+        }
+    }
+
+    /// Unloaded cells are never walkable.
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        self.split_cell(cell)
+            .is_some_and(|(chunk, local)| chunk.walkable[chunk.index(local)])
+    }
+
+    /// Walkable cells around `cell` and the cost of moving to them. Diagonal
+    /// moves aren't allowed to cut past blocked cells.
+    fn neighbours(&self, cell: IVec2) -> impl Iterator<Item = (IVec2, f32)> + '_ {
+        const OFFSETS: [IVec2; 8] = [
+            IVec2::new(1, 0),
+            IVec2::new(-1, 0),
+            IVec2::new(0, 1),
+            IVec2::new(0, -1),
+            IVec2::new(1, 1),
+            IVec2::new(1, -1),
+            IVec2::new(-1, 1),
+            IVec2::new(-1, -1),
+        ];
+        let from = self.cell_to_world_position(cell);
+        OFFSETS.into_iter().filter_map(move |offset| {
+            let next = cell + offset;
+            let diagonal = offset.x != 0 && offset.y != 0;
+            if !self.is_walkable(next)
+                || (diagonal
+                    && !(self.is_walkable(cell + IVec2::new(offset.x, 0))
+                        && self.is_walkable(cell + IVec2::new(0, offset.y))))
+            {
+                return None;
+            }
+            let cost = from?.distance(self.cell_to_world_position(next)?);
+            Some((next, cost))
+        })
+    }
+
+    /// Finds a path between two world positions using A*. The returned points
+    /// are on the ground at cell centers, ending at the cell containing `goal`.
+    /// Returns `None` if either end isn't walkable or there is no path through
+    /// loaded chunks.
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.world_position_to_cell(start);
+        let goal = self.world_position_to_cell(goal);
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+        let goal_pos = self.cell_to_world_position(goal)?;
+        let heuristic = |cell: IVec2| {
+            self.cell_to_world_position(cell)
+                .map_or(f32::INFINITY, |pos| pos.distance(goal_pos))
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+        let mut costs: HashMap<IVec2, f32> = HashMap::new();
+        open.push(OpenCell {
+            estimate: heuristic(start),
+            cell: start,
+        });
+        costs.insert(start, 0.0);
+
+        let mut expanded = 0;
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            if cell == goal {
+                let mut path = vec![cell];
+                while let Some(prev) = came_from.get(path.last().unwrap()) {
+                    path.push(*prev);
+                }
+                path.reverse();
+                return path
+                    .into_iter()
+                    .map(|cell| self.cell_to_world_position(cell))
+                    .collect();
+            }
+            expanded += 1;
+            if expanded > MAX_SEARCH_NODES {
+                return None;
+            }
+            let cost = costs[&cell];
+            for (next, step) in self.neighbours(cell) {
+                let next_cost = cost + step;
+                if costs.get(&next).is_some_and(|c| *c <= next_cost) {
+                    continue;
+                }
+                costs.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(OpenCell {
+                    estimate: next_cost + heuristic(next),
+                    cell: next,
+                });
+            }
+        }
+        None
+    }
+
+    /// Draws walkable cells within `radius` of `around` in green, and blocked
+    /// ones in red.
+    pub fn draw_gizmos(&self, gizmos: &mut Gizmos, around: Vec3, radius: f32) {
+        let center = self.world_position_to_cell(around);
+        let cells = (radius / NAV_CELL_SIZE) as i32;
+        for z in -cells..=cells {
+            for x in -cells..=cells {
+                let cell = center + IVec2::new(x, z);
+                let Some(position) = self.cell_to_world_position(cell) else {
+                    continue;
+                };
+                let color = if self.is_walkable(cell) { GREEN } else { RED };
+                gizmos.rect(
+                    position + Vec3::Y * 0.1,
+                    Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+                    Vec2::splat(NAV_CELL_SIZE * 0.8),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+fn load_nav_chunks(
+    mut navmesh: ResMut<NavMesh>,
+    chunks: Query<(Entity, &Chunk<TerrainChunkData>), Added<Chunk<TerrainChunkData>>>,
+    terrain: Res<Terrain>,
+) {
+    for (entity, chunk) in chunks.iter() {
+        let nav_chunk = NavChunk::generate(&terrain, chunk.meta.position);
+        navmesh.cells_per_chunk = nav_chunk.cells_per_side as i32;
+        navmesh.chunks.insert(chunk.meta.position, nav_chunk);
+        navmesh.chunk_entities.insert(entity, chunk.meta.position);
+        // The fresh grid doesn't have the cells of obstacles that were added
+        // before it, e.g. ones across the border in a neighbour.
+        navmesh.block_obstacles_near(chunk.meta.position);
+    }
+}
+
+/// Blocks the cells around trees and solid props as they are added to chunks,
+/// on either side of chunk borders.
+fn block_obstacle_cells(
+    mut navmesh: ResMut<NavMesh>,
+    obstacles: Query<(Entity, &Parent, &Transform, Option<&Prop>), Or<(Added<Tree>, Added<Prop>)>>,
+) {
+    for (entity, parent, transform, prop) in obstacles.iter() {
+        let radius = match prop {
+            Some(prop) if !prop.kind.is_solid() => continue,
+            Some(_) => PROP_BLOCK_RADIUS * transform.scale.x,
+            None => TREE_BLOCK_RADIUS,
+        };
+        let Some(chunk) = navmesh.chunk_entities.get(&parent.get()).copied() else {
+            continue;
+        };
+        // Transforms are local to the chunk.
+        let origin = (chunk * navmesh.cells_per_chunk).as_vec2() * NAV_CELL_SIZE;
+        let obstacle = Obstacle {
+            chunk,
+            center: origin + transform.translation.xz(),
+            radius,
+        };
+        let cells: Vec<IVec2> = obstacle.cells().collect();
+        for cell in cells {
+            navmesh.set_walkable(cell, false);
+        }
+        navmesh.obstacles.insert(entity, obstacle);
+    }
+}
+
+/// Frees the cells of obstacles that were removed, such as felled trees, and
+/// drops the grid of chunks that were unloaded.
+fn unload_nav_chunks(
+    mut navmesh: ResMut<NavMesh>,
+    mut removed: RemovedComponents<Chunk<TerrainChunkData>>,
+    mut removed_trees: RemovedComponents<Tree>,
+    mut removed_props: RemovedComponents<Prop>,
+    terrain: Res<Terrain>,
+) {
+    for entity in removed_trees.read().chain(removed_props.read()) {
+        if let Some(obstacle) = navmesh.obstacles.remove(&entity) {
+            navmesh.unblock(&obstacle, &terrain);
+        }
+    }
+    for entity in removed.read() {
+        let Some(chunk_pos) = navmesh.chunk_entities.remove(&entity) else {
+            continue;
+        };
+        // The chunk might have been replaced by one with a different LOD.
+        if !navmesh.chunk_entities.values().any(|pos| *pos == chunk_pos) {
+            navmesh.chunks.remove(&chunk_pos);
+        }
+    }
+}
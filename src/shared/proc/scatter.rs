@@ -0,0 +1,243 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    color::palettes::css::{DARK_GREEN, GRAY, OLIVE},
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+use super::{chunk::ChunkMetadata, terrain::Terrain};
+
+/// Props don't grow closer to the water level than this.
+const SHORE_HEIGHT: f32 = 0.5;
+/// Mixed into the world seed so every kind of prop is placed independently.
+const SCATTER_SALT: u64 = 0x7363_6174;
+
+pub struct ScatterPlugin;
+
+impl Plugin for ScatterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PropAssets>();
+        app.add_systems(Update, spawn_props);
+    }
+}
+
+/// Things scattered over the terrain. Trees are spawned by the tree module,
+/// everything else is a small prop spawned here.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PropKind {
+    Tree,
+    Rock,
+    Bush,
+    Cactus,
+}
+
+impl PropKind {
+    pub const ALL: [Self; 4] = [Self::Tree, Self::Rock, Self::Bush, Self::Cactus];
+
+    /// Minimum distance between two props of this kind.
+    fn spacing(&self) -> f64 {
+        match self {
+            Self::Tree => 3.0,
+            Self::Rock => 9.0,
+            Self::Bush => 4.0,
+            Self::Cactus => 12.0,
+        }
+    }
+
+    /// Smallest and largest scale.
+    fn scale(&self) -> (f32, f32) {
+        match self {
+            Self::Tree => (1.0, 1.0),
+            Self::Rock => (0.6, 2.0),
+            Self::Bush => (0.8, 1.6),
+            Self::Cactus => (0.8, 1.3),
+        }
+    }
+
+    /// Solid props block movement, so they're always spawned with a collider
+    /// and never thinned out with distance.
+    pub fn is_solid(&self) -> bool {
+        match self {
+            Self::Tree | Self::Rock | Self::Cactus => true,
+            Self::Bush => false,
+        }
+    }
+
+    /// Collider at a scale of one, with the prop's origin on the ground.
+    /// Trees have their own colliders.
+    fn collider(&self) -> Option<(Vec3, Collider)> {
+        match self {
+            Self::Tree | Self::Bush => None,
+            Self::Rock => Some((Vec3::Y * 0.2, Collider::ball(0.5))),
+            Self::Cactus => Some((Vec3::Y, Collider::capsule_y(0.75, 0.25))),
+        }
+    }
+}
+
+/// A prop's position (local to its chunk), rotation around Y and scale.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PropPlacement {
+    pub kind: PropKind,
+    pub translation: Vec3,
+    pub rotation: f32,
+    pub scale: f32,
+    /// From 0 to 1. Decorative props with a rank above a chunk's density
+    /// aren't spawned, so far away chunks have fewer of them.
+    pub rank: f32,
+}
+
+impl PropPlacement {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation)
+            .with_rotation(Quat::from_rotation_y(self.rotation))
+            .with_scale(Vec3::splat(self.scale))
+    }
+
+    /// Whether the prop is spawned in a chunk at a LOD.
+    pub fn is_visible(&self, lod: usize) -> bool {
+        self.kind.is_solid() || self.rank < 1.0 / lod as f32
+    }
+}
+
+/// Places every kind of prop over a chunk, where the dominant biome allows
+/// them. Each kind is sampled with its own spacing and is seeded per chunk,
+/// so props are the same on the server and clients. Edits to the terrain are
+/// ignored when deciding where props go, so that the same props are placed
+/// in edited chunks and trees keep their `TreeId`s.
+pub fn scatter(terrain: &Terrain, meta: &ChunkMetadata) -> Vec<PropPlacement> {
+    let origin = terrain.chunk_to_world_position(meta.position, Vec3::ZERO);
+    let mut props = Vec::new();
+    for (i, kind) in PropKind::ALL.into_iter().enumerate() {
+        let seed = utils::mix_seed(utils::mix_seed(terrain.seed, SCATTER_SALT), i as u64);
+        let mut rng = utils::create_rng_from_seed(utils::chunk_seed(seed, meta.position));
+        let points = utils::poisson_disk_sampling(
+            meta.size as f64,
+            meta.size as f64,
+            kind.spacing(),
+            30,
+            &mut rng,
+        );
+        for point in points {
+            let (world_x, world_z) = (origin.x + point[0] as f32, origin.z + point[1] as f32);
+            let ground = terrain.unedited_height_at(world_x, world_z);
+            let allowed = ground > terrain.water_level + SHORE_HEIGHT
+                && terrain
+                    .biome_at(world_x as f64, world_z as f64)
+                    .can_scatter(kind, world_x as f64, world_z as f64, ground);
+            // Random values are always drawn so that a prop being left out
+            // doesn't move the ones after it.
+            let rotation = rng.gen_range(0.0..TAU);
+            let (min_scale, max_scale) = kind.scale();
+            let scale = min_scale + (max_scale - min_scale) * rng.gen::<f32>();
+            let rank = rng.gen();
+            if !allowed {
+                continue;
+            }
+            // Far away chunks don't have colliders to snap to, so the height
+            // comes from the terrain itself.
+            let height = terrain.height_at(world_x, world_z);
+            props.push(PropPlacement {
+                kind,
+                translation: Vec3::new(point[0] as f32, height, point[1] as f32),
+                rotation,
+                scale,
+                rank,
+            });
+        }
+    }
+    props
+}
+
+/// A small prop. Its meshes and collider are added by `spawn_props`.
+#[derive(Component)]
+pub struct Prop {
+    pub kind: PropKind,
+}
+
+/// Spawns a prop as a child of its chunk. `transform` is local to the chunk.
+pub fn spawn_prop(parent: &mut ChildBuilder, kind: PropKind, transform: Transform) {
+    parent.spawn((Prop { kind }, SpatialBundle::from_transform(transform)));
+}
+
+/// Meshes and materials shared by every prop of a kind. Entities with the
+/// same mesh and material are drawn in a single instanced batch, so there can
+/// be many props without many draw calls.
+#[derive(Resource)]
+struct PropAssets {
+    rock: (Handle<Mesh>, Handle<StandardMaterial>),
+    bush: (Handle<Mesh>, Handle<StandardMaterial>),
+    cactus: (Handle<Mesh>, Handle<StandardMaterial>),
+}
+
+impl FromWorld for PropAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut rock = Sphere::new(0.5).mesh().ico(1).unwrap();
+        // Flat shading makes the low poly sphere look like a rock.
+        rock.duplicate_vertices();
+        rock.compute_flat_normals();
+        let bush = Sphere::new(0.5).mesh().ico(2).unwrap();
+        let cactus = Capsule3d::new(0.25, 1.5).mesh().build();
+
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let (rock, bush, cactus) = (meshes.add(rock), meshes.add(bush), meshes.add(cactus));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut material = |color: Srgba, perceptual_roughness: f32| {
+            materials.add(StandardMaterial {
+                base_color: color.into(),
+                perceptual_roughness,
+                ..default()
+            })
+        };
+        Self {
+            rock: (rock, material(GRAY, 0.9)),
+            bush: (bush, material(DARK_GREEN, 0.8)),
+            cactus: (cactus, material(OLIVE, 0.6)),
+        }
+    }
+}
+
+impl PropAssets {
+    /// The mesh, its material and its offset from the ground at a scale of one.
+    fn get(&self, kind: PropKind) -> Option<(Handle<Mesh>, Handle<StandardMaterial>, Vec3)> {
+        let ((mesh, material), offset) = match kind {
+            PropKind::Tree => return None,
+            // Sunk into the ground a little, so that it sits on slopes.
+            PropKind::Rock => (&self.rock, Vec3::Y * 0.2),
+            PropKind::Bush => (&self.bush, Vec3::Y * 0.3),
+            PropKind::Cactus => (&self.cactus, Vec3::Y),
+        };
+        Some((mesh.clone(), material.clone(), offset))
+    }
+}
+
+fn spawn_props(
+    props: Query<(Entity, &Prop), Added<Prop>>,
+    assets: Res<PropAssets>,
+    mut commands: Commands,
+) {
+    for (entity, prop) in props.iter() {
+        let Some((mesh, material, offset)) = assets.get(prop.kind) else {
+            continue;
+        };
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh,
+                material,
+                transform: Transform::from_translation(offset),
+                ..default()
+            });
+            if let Some((offset, collider)) = prop.kind.collider() {
+                parent.spawn((
+                    RigidBody::Fixed,
+                    collider,
+                    SpatialBundle::from_transform(Transform::from_translation(offset)),
+                ));
+            }
+        });
+    }
+}
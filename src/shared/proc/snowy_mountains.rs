@@ -0,0 +1,80 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+
+use crate::utils;
+
+use super::{chunk::BiomeGenerator, scatter::PropKind, utils::layered_noise, utils::NoiseLayer};
+
+/// Tall, rocky mountains with snow on top, covered in rocks. A few trees and
+/// bushes grow in the valleys.
+#[derive(Clone)]
+pub struct SnowyMountainsBiome {
+    noise_layers: Vec<NoiseLayer<Perlin>>,
+    /// Breaks up the snow line and decides where trees and bushes grow.
+    patch_noise: Perlin,
+    rock: LinearRgba,
+    snow: LinearRgba,
+    /// Ground above this height is covered in snow.
+    snow_line: f32,
+    /// Height over which rock turns into snow.
+    snow_blend: f32,
+}
+
+impl SnowyMountainsBiome {
+    pub fn new(seed: u64) -> Self {
+        let noise_seed = |layer: u64| utils::mix_seed(seed, layer) as u32;
+        let noise_layers = vec![
+            NoiseLayer {
+                noise: Perlin::new(noise_seed(4)),
+                amplitude: 40.0,
+                frequency: 0.004,
+            },
+            NoiseLayer {
+                noise: Perlin::new(noise_seed(5)),
+                amplitude: 10.0,
+                frequency: 0.012,
+            },
+            NoiseLayer {
+                noise: Perlin::new(noise_seed(6)),
+                amplitude: 1.0,
+                frequency: 0.04,
+            },
+        ];
+        Self {
+            noise_layers,
+            patch_noise: Perlin::new(noise_seed(7)),
+            rock: Color::srgb_u8(110, 105, 100).into(),
+            snow: Color::srgb_u8(245, 248, 255).into(),
+            snow_line: 12.0,
+            snow_blend: 4.0,
+        }
+    }
+
+    /// From -1 to 1, changes every few meters.
+    fn patch(&self, world_x: f64, world_z: f64) -> f32 {
+        self.patch_noise.get([world_x * 0.05, world_z * 0.05]) as f32
+    }
+}
+
+impl BiomeGenerator for SnowyMountainsBiome {
+    fn height(&self, world_x: f64, world_z: f64) -> f32 {
+        // Mountains stick up from roughly the same base height as other biomes.
+        layered_noise(&self.noise_layers, world_x, world_z) + 10.0
+    }
+
+    fn ground_color(&self, world_x: f64, world_z: f64, height: f32) -> LinearRgba {
+        let snow_line = self.snow_line + self.patch(world_x, world_z) * self.snow_blend;
+        let strength = ((height - snow_line) / self.snow_blend).clamp(0.0, 1.0);
+        self.rock.mix(&self.snow, strength)
+    }
+
+    fn can_scatter(&self, kind: PropKind, world_x: f64, world_z: f64, height: f32) -> bool {
+        let below_snow = height < self.snow_line - self.snow_blend;
+        match kind {
+            PropKind::Tree => below_snow && self.patch(world_x, world_z) > 0.3,
+            PropKind::Bush => below_snow && self.patch(world_x, world_z) > 0.1,
+            PropKind::Rock => true,
+            PropKind::Cactus => false,
+        }
+    }
+}
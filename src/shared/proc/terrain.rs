@@ -0,0 +1,669 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use bevy::{
+    color::palettes::css::BLUE,
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    utils::HashSet,
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::utils;
+
+/// How far the skirts around chunk meshes go down.
+const SKIRT_DEPTH: f32 = 10.0;
+
+use super::{
+    cache::{ChunkCache, ChunkData, ChunkKey},
+    chunk::{BiomeGenerator, Chunk, ChunkMetadata},
+    climate::ClimateMap,
+    edits::{TerrainEdit, TerrainEdits},
+    grassy_desert::GrassyDesertBiome,
+    scatter::{scatter, spawn_prop, PropKind, PropPlacement},
+    shaders::TerrainMaterial,
+    snowy_mountains::SnowyMountainsBiome,
+    tree::{spawn_tree, Tree, TreeId},
+    water::{Water, WaterMaterial, WATER_GROUP},
+};
+
+/// Represents the terrain of the world, made out of chunks. Cloned into chunk
+/// generation tasks.
+#[derive(Resource, Clone)]
+pub struct Terrain {
+    /// Everything generated for the world is derived from this. The server
+    /// sends it to clients so they generate the same world.
+    pub seed: u64,
+    pub chunk_size: usize,
+    /// The radius around the player(s) to generate chunks.
+    pub radius: i32,
+    pub grid_spacing: usize,
+    /// Everything below this height is under water.
+    pub water_level: f32,
+    /// Indexed by the biome indices of the climate map.
+    pub biomes: Vec<Arc<dyn BiomeGenerator>>,
+    pub climate: ClimateMap,
+    /// Changes made to the terrain while the game is running. Shared with
+    /// clones until the next edit.
+    pub edits: Arc<TerrainEdits>,
+}
+
+impl Terrain {
+    /// Creates the terrain with every biome in the game.
+    pub fn new(seed: u64) -> Self {
+        Self::with_biomes(
+            seed,
+            vec![
+                Arc::new(GrassyDesertBiome::new(seed)),
+                Arc::new(SnowyMountainsBiome::new(seed)),
+            ],
+        )
+    }
+
+    /// Creates a terrain out of the given biomes. Which biome is where depends
+    /// on the seed, their order only decides which ones are next to each other.
+    pub fn with_biomes(seed: u64, biomes: Vec<Arc<dyn BiomeGenerator>>) -> Self {
+        let climate = ClimateMap::new(utils::mix_seed(seed, 8) as u32, biomes.len());
+        Self {
+            seed,
+            chunk_size: 100,
+            radius: 2,
+            grid_spacing: 5,
+            water_level: -8.0,
+            biomes,
+            climate,
+            edits: Arc::default(),
+        }
+    }
+
+    /// Changes the height of the full detail grid points within an edit's
+    /// radius. Returns every chunk whose grid points changed, which includes
+    /// neighbours sharing an edge with them.
+    pub fn apply_edit(&mut self, edit: &TerrainEdit) -> HashSet<IVec2> {
+        let spacing = self.grid_spacing as f32;
+        let points_per_chunk = self.points_per_chunk();
+        let min = ((edit.center() - edit.radius()) / spacing)
+            .ceil()
+            .as_ivec2();
+        let max = ((edit.center() + edit.radius()) / spacing)
+            .floor()
+            .as_ivec2();
+        let edits = Arc::make_mut(&mut self.edits);
+        let mut changed = HashSet::new();
+        for z in min.y..=max.y {
+            for x in min.x..=max.x {
+                let point = IVec2::new(x, z);
+                let distance = (point.as_vec2() * spacing).distance(edit.center());
+                let delta = edit.delta(distance);
+                if delta == 0.0 {
+                    continue;
+                }
+                edits.add(point, points_per_chunk, delta);
+                // Points on a chunk's edges are shared with its neighbours.
+                let owner = point.div_euclid(IVec2::splat(points_per_chunk));
+                for offset in [IVec2::ZERO, IVec2::NEG_X, IVec2::NEG_Y, IVec2::NEG_ONE] {
+                    let chunk = owner + offset;
+                    let start = chunk * points_per_chunk;
+                    if point.cmple(start + points_per_chunk).all() {
+                        changed.insert(chunk);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn draw_chunk_gizmo(&self, gizmos: &mut Gizmos, chunk_pos: IVec2) {
+        gizmos.rect(
+            self.chunk_to_world_position(chunk_pos, Vec3::ZERO) + self.mid_chunk_offset(),
+            Quat::from_rotation_x(PI / 2.0),
+            Vec2::splat(100.0),
+            BLUE,
+        );
+    }
+
+    pub fn world_position_to_chunk(&self, position: Vec3) -> IVec2 {
+        IVec2::new(
+            (position.x / self.chunk_size as f32).floor() as i32,
+            (position.z / self.chunk_size as f32).floor() as i32,
+        )
+    }
+
+    pub fn chunk_to_world_position(&self, chunk: IVec2, offset: Vec3) -> Vec3 {
+        offset
+            + Vec3::new(
+                chunk.x as f32 * self.chunk_size as f32,
+                0.0,
+                chunk.y as f32 * self.chunk_size as f32,
+            )
+    }
+
+    /// Number of full detail grid cells along a chunk's side.
+    fn points_per_chunk(&self) -> i32 {
+        (self.chunk_size / self.grid_spacing) as i32
+    }
+
+    /// Returns the full detail grid point (counted from the world origin) of a
+    /// chunk's grid point at a LOD.
+    fn chunk_grid_point(&self, chunk: IVec2, offset: IVec2, lod: usize) -> IVec2 {
+        chunk * self.points_per_chunk() + offset * lod as i32
+    }
+
+    /// Height of the terrain at a full detail grid point, with or without the
+    /// edits made to it.
+    fn grid_height(&self, point: IVec2, edited: bool) -> f32 {
+        let spacing = self.grid_spacing as f64;
+        let height = self.noise_height(point.x as f64 * spacing, point.y as f64 * spacing);
+        if edited {
+            height + self.edits.get(point, self.points_per_chunk())
+        } else {
+            height
+        }
+    }
+
+    /// Returns an offset to move from an output of `chunk_to_world_position` to
+    /// the center of the chunk. Y is set to zero.
+    pub fn mid_chunk_offset(&self) -> Vec3 {
+        Vec3::new(self.chunk_size as f32, 0.0, self.chunk_size as f32) / 2.0
+    }
+
+    fn get_num_grid_points(&self, lod: usize) -> usize {
+        (self.chunk_size / (lod * self.grid_spacing)) + 1
+    }
+
+    /// Computes the terrain height at a world position by blending the heights
+    /// of the biomes there. This matches the terrain mesh exactly at its grid
+    /// points. Use `height_at` for the height of the surface in between them.
+    pub fn noise_height(&self, world_x: f64, world_z: f64) -> f32 {
+        self.climate
+            .weights(world_x, world_z)
+            .into_iter()
+            .zip(self.biomes.iter())
+            .filter(|(weight, _)| *weight > 0.0)
+            .map(|(weight, biome)| weight * biome.height(world_x, world_z))
+            .sum()
+    }
+
+    /// Returns the biome with the most influence at a world position.
+    pub fn biome_at(&self, world_x: f64, world_z: f64) -> &dyn BiomeGenerator {
+        self.biomes[self.climate.dominant_biome(world_x, world_z)].as_ref()
+    }
+
+    /// Blends the ground colors of the biomes at a world position.
+    fn ground_color(&self, world_x: f64, world_z: f64, height: f32) -> LinearRgba {
+        self.climate
+            .weights(world_x, world_z)
+            .into_iter()
+            .zip(self.biomes.iter())
+            .filter(|(weight, _)| *weight > 0.0)
+            .fold(LinearRgba::NONE, |color, (weight, biome)| {
+                color + biome.ground_color(world_x, world_z, height) * weight
+            })
+    }
+
+    /// Bakes the ground colors of a chunk into sRGBA bytes with one pixel per
+    /// world unit.
+    fn generate_albedo(&self, meta: &ChunkMetadata) -> Vec<u8> {
+        let origin = self.chunk_to_world_position(meta.position, Vec3::ZERO);
+        let mut data = Vec::with_capacity(meta.size * meta.size * 4);
+        for z in 0..meta.size {
+            for x in 0..meta.size {
+                let world_x = origin.x + x as f32;
+                let world_z = origin.z + z as f32;
+                let height = self.height_at(world_x, world_z);
+                let color = self.ground_color(world_x as f64, world_z as f64, height);
+                data.extend_from_slice(&Color::from(color).to_srgba().to_u8_array());
+            }
+        }
+        data
+    }
+
+    fn albedo_image(&self, meta: &ChunkMetadata, data: Vec<u8>) -> Image {
+        Image::new(
+            Extent3d {
+                width: meta.size as u32,
+                height: meta.size as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    /// Computes the heights of the chunk's grid points at a LOD, row by row
+    /// along X.
+    fn generate_heights(&self, chunk_pos: IVec2, lod: usize) -> Vec<f32> {
+        let grid_points = self.get_num_grid_points(lod);
+        let mut heights = Vec::with_capacity(grid_points * grid_points);
+        for z in 0..grid_points {
+            for x in 0..grid_points {
+                let point = self.chunk_grid_point(chunk_pos, IVec2::new(x as i32, z as i32), lod);
+                heights.push(self.grid_height(point, true));
+            }
+        }
+        heights
+    }
+
+    /// Generates the vertices (local to the chunk) and triangle indices of the
+    /// terrain surface from the output of `generate_heights`.
+    fn generate_surface(&self, heights: &[f32], lod: usize) -> (Vec<[f32; 3]>, Vec<u32>) {
+        let grid_points = self.get_num_grid_points(lod);
+        let mut vertices = Vec::with_capacity(grid_points * grid_points);
+        let mut indices = Vec::new();
+
+        for z in 0..grid_points {
+            for x in 0..grid_points {
+                let x_pos = x as f32 * lod as f32 * self.grid_spacing as f32;
+                let z_pos = z as f32 * lod as f32 * self.grid_spacing as f32;
+                vertices.push([x_pos, heights[z * grid_points + x], z_pos]);
+            }
+        }
+
+        // Generate indices
+        for z in 0..(grid_points - 1) {
+            for x in 0..(grid_points - 1) {
+                let top_left = z * grid_points + x;
+                let bottom_left = (z + 1) * grid_points + x;
+                let top_right = top_left + 1;
+                let bottom_right = bottom_left + 1;
+
+                indices.extend_from_slice(&[
+                    top_left as u32,
+                    bottom_left as u32,
+                    bottom_right as u32,
+                    top_left as u32,
+                    bottom_right as u32,
+                    top_right as u32,
+                ]);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    /// Generates a terrain mesh for this chunk. A skirt hangs down from the
+    /// edges so that there are no cracks next to chunks with a different LOD.
+    fn generate_mesh(&self, heights: &[f32], lod: usize) -> Mesh {
+        let (mut vertices, mut indices) = self.generate_surface(heights, lod);
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices.clone());
+        mesh.insert_indices(Indices::U32(indices.clone()));
+        mesh.compute_normals();
+        // The skirt is added after computing normals so that it doesn't bend
+        // the normals at the edges of the surface.
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("terrain normals to be computed");
+        };
+        let mut normals = normals.clone();
+        self.add_skirt(&mut vertices, &mut normals, &mut indices, lod);
+
+        // Compute UV coordinates
+        let uvs: Vec<[f32; 2]> = vertices
+            .iter()
+            .map(|[x, _, z]| [x / self.chunk_size as f32, z / self.chunk_size as f32])
+            .collect();
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_indices(Indices::U32(indices));
+        mesh.generate_tangents().expect("tangents to be generated");
+
+        mesh
+    }
+
+    /// Adds a strip of faces going down from each edge of the surface, facing
+    /// outwards. Walks around the edge so that the outside is always on the left.
+    fn add_skirt(
+        &self,
+        vertices: &mut Vec<[f32; 3]>,
+        normals: &mut Vec<[f32; 3]>,
+        indices: &mut Vec<u32>,
+        lod: usize,
+    ) {
+        let n = self.get_num_grid_points(lod);
+        let edge: Vec<usize> = (0..n)
+            .chain((1..n).map(|z| z * n + n - 1))
+            .chain((0..n - 1).rev().map(|x| (n - 1) * n + x))
+            .chain((0..n - 1).rev().map(|z| z * n))
+            .collect();
+        for pair in edge.windows(2) {
+            let (top_a, top_b) = (pair[0], pair[1]);
+            let bottom_a = vertices.len();
+            let bottom_b = bottom_a + 1;
+            for top in [top_a, top_b] {
+                let [x, y, z] = vertices[top];
+                vertices.push([x, y - SKIRT_DEPTH, z]);
+                normals.push(normals[top]);
+            }
+            indices.extend_from_slice(&[
+                top_a as u32,
+                top_b as u32,
+                bottom_b as u32,
+                top_a as u32,
+                bottom_b as u32,
+                bottom_a as u32,
+            ]);
+        }
+    }
+
+    /// Generates a collider from the full detail output of `generate_heights`.
+    /// The heightfield is centered on its entity, so it has to be offset by
+    /// `mid_chunk_offset`. Its cells are split along the same diagonal as the
+    /// mesh, so it matches `height_at` exactly.
+    fn generate_collider(&self, heights: &[f32]) -> Collider {
+        let grid_points = self.get_num_grid_points(1);
+        // Rows go along Z and columns along X, stored column by column.
+        let heights = (0..grid_points)
+            .flat_map(|x| (0..grid_points).map(move |z| heights[z * grid_points + x]))
+            .collect();
+        let size = self.chunk_size as f32;
+        Collider::heightfield(
+            heights,
+            grid_points,
+            grid_points,
+            Vec3::new(size, 1.0, size),
+        )
+    }
+
+    /// Heights of the corners of the full detail grid cell containing a world
+    /// position, and how far into the cell the position is (from 0 to 1).
+    fn grid_cell_at(&self, x: f32, z: f32, edited: bool) -> ([f32; 4], Vec2) {
+        let spacing = self.grid_spacing as f32;
+        let cell = (Vec2::new(x, z) / spacing).floor();
+        let fraction = Vec2::new(x, z) / spacing - cell;
+        let cell = cell.as_ivec2();
+        let height = |dx: i32, dz: i32| self.grid_height(cell + IVec2::new(dx, dz), edited);
+        (
+            [height(0, 0), height(1, 0), height(0, 1), height(1, 1)],
+            fraction,
+        )
+    }
+
+    /// Returns the height of the terrain's surface at a world position. This
+    /// interpolates the full detail grid the same way the collider does, and
+    /// works whether or not the chunk is loaded.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.surface_height_at(x, z, true)
+    }
+
+    /// Returns the height of the surface as if the terrain was never edited.
+    pub fn unedited_height_at(&self, x: f32, z: f32) -> f32 {
+        self.surface_height_at(x, z, false)
+    }
+
+    fn surface_height_at(&self, x: f32, z: f32, edited: bool) -> f32 {
+        let ([h00, h10, h01, h11], f) = self.grid_cell_at(x, z, edited);
+        // Cells are split into two triangles along the (0, 0) to (1, 1) diagonal.
+        if f.x >= f.y {
+            h00 + f.x * (h10 - h00) + f.y * (h11 - h10)
+        } else {
+            h00 + f.y * (h01 - h00) + f.x * (h11 - h01)
+        }
+    }
+
+    /// Returns the normal of the terrain's surface at a world position.
+    pub fn normal_at(&self, x: f32, z: f32) -> Vec3 {
+        let ([h00, h10, h01, h11], f) = self.grid_cell_at(x, z, true);
+        let (slope_x, slope_z) = if f.x >= f.y {
+            (h10 - h00, h11 - h10)
+        } else {
+            (h11 - h01, h01 - h00)
+        };
+        let spacing = self.grid_spacing as f32;
+        Vec3::new(-slope_x / spacing, 1.0, -slope_z / spacing).normalize()
+    }
+
+    /// Generates everything that can be cached about a chunk.
+    pub fn generate_chunk_data(&self, meta: &ChunkMetadata) -> ChunkData {
+        let heights = self.generate_heights(meta.position, meta.lod);
+        let lowest_point = if meta.lod == 1 {
+            heights.iter().copied().fold(f32::INFINITY, f32::min)
+        } else {
+            self.generate_heights(meta.position, 1)
+                .into_iter()
+                .fold(f32::INFINITY, f32::min)
+        };
+        ChunkData {
+            heights,
+            albedo: self.generate_albedo(meta),
+            props: scatter(self, meta),
+            lowest_point,
+        }
+    }
+
+    /// Does the expensive part of creating a chunk: noise, the mesh, its
+    /// collider, ground colors and prop placement. Chunk data comes from the
+    /// cache if it's there. Edited chunks are never cached, as the cache only
+    /// knows about the seed. Doesn't touch the Bevy world, so it can run on
+    /// another thread.
+    pub fn generate_chunk(&self, meta: ChunkMetadata, cache: &ChunkCache) -> GeneratedChunk {
+        let data = if self.edits.touches(meta.position) {
+            Arc::new(self.generate_chunk_data(&meta))
+        } else {
+            let key = ChunkKey::new(self.seed, &meta);
+            cache.get(&key).unwrap_or_else(|| {
+                let data = Arc::new(self.generate_chunk_data(&meta));
+                cache.insert(key, data.clone());
+                data
+            })
+        };
+
+        let mesh = self.generate_mesh(&data.heights, meta.lod);
+        let collider = meta.has_collider.then(|| {
+            if meta.lod == 1 {
+                self.generate_collider(&data.heights)
+            } else {
+                self.generate_collider(&self.generate_heights(meta.position, 1))
+            }
+        });
+        let albedo = self.albedo_image(&meta, data.albedo.clone());
+        GeneratedChunk {
+            meta,
+            mesh,
+            collider,
+            albedo,
+            props: data.props.clone(),
+            lowest_point: data.lowest_point,
+        }
+    }
+
+    /// Renders a generated chunk and its props into the Bevy world. Far away
+    /// chunks have fewer decorative props. Chunks that
+    /// dip below the water level get a water surface, and a water volume if
+    /// they have a collider.
+    pub fn render_chunk(
+        &self,
+        generated: GeneratedChunk,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+    ) {
+        let GeneratedChunk {
+            meta,
+            mesh,
+            collider,
+            albedo,
+            props,
+            lowest_point,
+        } = generated;
+        let albedo = asset_server.add(albedo);
+        let chunk = Chunk {
+            biome_data: TerrainChunkData {
+                albedo: albedo.clone(),
+            },
+            meta,
+        };
+        let mesh_handle = asset_server.add(mesh);
+        let material = asset_server.add(TerrainMaterial { albedo });
+        let has_collider = collider.is_some();
+
+        let (position, lod) = (chunk.meta.position, chunk.meta.lod);
+        let world_pos = self.chunk_to_world_position(position, Vec3::ZERO);
+        let spatial = SpatialBundle::from_transform(Transform::from_translation(world_pos));
+
+        commands.spawn((chunk, spatial)).with_children(|parent| {
+            parent.spawn(MaterialMeshBundle {
+                mesh: mesh_handle,
+                material,
+                ..default()
+            });
+            if let Some(collider) = collider {
+                parent.spawn((
+                    collider,
+                    RigidBody::Fixed,
+                    SpatialBundle::from_transform(Transform::from_translation(
+                        self.mid_chunk_offset(),
+                    )),
+                ));
+            }
+            if lowest_point < self.water_level {
+                self.spawn_water(parent, lowest_point, has_collider, asset_server);
+            }
+            // Trees are numbered among themselves, so other props don't
+            // change their ids.
+            let mut tree_index = 0;
+            for prop in props.iter() {
+                if prop.kind == PropKind::Tree {
+                    let id = TreeId {
+                        chunk: position,
+                        index: tree_index,
+                    };
+                    tree_index += 1;
+                    let tree_type_seed = utils::coords_to_u64(prop.translation.xz());
+                    spawn_tree(parent, Tree::rand(id, tree_type_seed), prop.transform());
+                } else if prop.is_visible(lod) {
+                    spawn_prop(parent, prop.kind, prop.transform());
+                }
+            }
+        });
+    }
+
+    fn spawn_water(
+        &self,
+        parent: &mut ChildBuilder,
+        lowest_point: f32,
+        has_collider: bool,
+        asset_server: &AssetServer,
+    ) {
+        let size = self.chunk_size as f32;
+        let surface = self.mid_chunk_offset() + Vec3::Y * self.water_level;
+        parent.spawn(MaterialMeshBundle {
+            mesh: asset_server.add(Plane3d::default().mesh().size(size, size).build()),
+            material: asset_server.add(WaterMaterial::default()),
+            transform: Transform::from_translation(surface),
+            ..default()
+        });
+        if has_collider {
+            let depth = self.water_level - lowest_point;
+            parent.spawn((
+                Water,
+                Collider::cuboid(size / 2.0, depth / 2.0, size / 2.0),
+                Sensor,
+                CollisionGroups::new(WATER_GROUP, Group::ALL),
+                SpatialBundle::from_transform(Transform::from_translation(
+                    surface - Vec3::Y * depth / 2.0,
+                )),
+            ));
+        }
+    }
+
+    pub fn unload_chunk(&self, chunk_entity: Entity, commands: &mut Commands) {
+        commands.entity(chunk_entity).despawn_recursive();
+    }
+}
+
+/// The output of `Terrain::generate_chunk`, ready to be rendered.
+pub struct GeneratedChunk {
+    pub meta: ChunkMetadata,
+    mesh: Mesh,
+    /// Only generated if `meta.has_collider` is set.
+    collider: Option<Collider>,
+    albedo: Image,
+    props: Vec<PropPlacement>,
+    lowest_point: f32,
+}
+
+pub struct TerrainChunkData {
+    /// Ground colors of the chunk, blended between biomes.
+    pub albedo: Handle<Image>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything generated for a chunk: terrain heights, ground colors and
+    /// prop placements.
+    fn generate_chunk(seed: u64, position: IVec2) -> ChunkData {
+        let terrain = Terrain::new(seed);
+        terrain.generate_chunk_data(&ChunkMetadata {
+            position,
+            lod: 1,
+            has_collider: true,
+            size: terrain.chunk_size,
+        })
+    }
+
+    #[test]
+    fn same_seed_generates_identical_chunks() {
+        for position in [IVec2::ZERO, IVec2::new(-3, 7)] {
+            assert_eq!(generate_chunk(42, position), generate_chunk(42, position));
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_chunks() {
+        assert_ne!(
+            generate_chunk(42, IVec2::ZERO),
+            generate_chunk(43, IVec2::ZERO)
+        );
+    }
+
+    #[test]
+    fn biome_weights_add_up_to_one() {
+        let terrain = Terrain::new(42);
+        for i in 0..1000 {
+            let (x, z) = (i as f64 * 37.0, i as f64 * -53.0);
+            let weights = terrain.climate.weights(x, z);
+            assert_eq!(weights.len(), terrain.biomes.len());
+            assert!(weights.iter().all(|weight| *weight >= 0.0));
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn crater_on_chunk_corner_changes_all_four_chunks() {
+        let mut terrain = Terrain::new(42);
+        let before = terrain.height_at(0.0, 0.0);
+        let changed = terrain.apply_edit(&TerrainEdit::Crater {
+            center: Vec2::ZERO,
+            radius: 12.0,
+            depth: 3.0,
+        });
+        assert!((terrain.height_at(0.0, 0.0) - (before - 3.0)).abs() < 1e-4);
+        let expected: HashSet<IVec2> = [IVec2::ZERO, IVec2::NEG_X, IVec2::NEG_Y, IVec2::NEG_ONE]
+            .into_iter()
+            .collect();
+        assert_eq!(changed, expected);
+        // Props are placed as if the terrain wasn't edited, so tree ids stay.
+        let meta = ChunkMetadata {
+            position: IVec2::ZERO,
+            lod: 1,
+            has_collider: true,
+            size: terrain.chunk_size,
+        };
+        let edited_props = terrain.generate_chunk_data(&meta).props.len();
+        assert_eq!(edited_props, generate_chunk(42, IVec2::ZERO).props.len());
+    }
+}
@@ -1,22 +1,144 @@
-use bevy::prelude::*;
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::{DefaultChannel, RenetClient, RenetServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{
+        client::{MessageReaderOnClient, ReliableMessageFromClient},
+        server::{MessageReaderOnServer, ReliableMessageFromServer},
+    },
+    shared::{
+        objects::{
+            health::{Health, Killed},
+            NetworkObject,
+        },
+        GameLogic,
+    },
+    utils,
+};
+
+use super::{chunk::Chunk, terrain::TerrainChunkData};
+
+/// Tree models are scaled up by this much.
+const TREE_SCALE: f32 = 4.0;
+const TREE_HEALTH: f32 = 60.0;
+const FALL_DURATION: Duration = Duration::from_millis(1500);
+/// Mixed into tree ids so they don't collide with other static network objects.
+const TREE_ID_SALT: u64 = 0x7472_6565;
 
 pub struct TreePlugin;
 
 impl Plugin for TreePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<TreeMeshes>();
-        app.add_systems(Update, spawn_trees);
+        app.init_resource::<FelledTrees>();
+        app.add_systems(Update, (spawn_trees, fall_trees));
+        app.add_systems(FixedUpdate, remove_felled_trees);
+    }
+}
+
+/// Replicates cut down trees. Only used in game, the procedural generation
+/// demo doesn't need it.
+pub struct TreeNetworkPlugin {
+    pub is_server: bool,
+}
+
+impl Plugin for TreeNetworkPlugin {
+    fn build(&self, app: &mut App) {
+        if self.is_server {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    fell_killed_trees.in_set(GameLogic::Game),
+                    send_chunk_diffs.in_set(GameLogic::Sync),
+                ),
+            );
+        } else {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    recv_tree_messages.in_set(GameLogic::Sync),
+                    request_chunk_diffs.in_set(GameLogic::Sync),
+                ),
+            );
+        }
+    }
+}
+
+/// Identifies a tree by the chunk it was generated in and its index in that
+/// chunk's placements, which are the same everywhere.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TreeId {
+    pub chunk: IVec2,
+    pub index: u32,
+}
+
+impl TreeId {
+    /// Trees are generated deterministically, so their network objects are too.
+    pub fn network_object(&self) -> NetworkObject {
+        let chunk = ((self.chunk.x as u32 as u64) << 32) | self.chunk.y as u32 as u64;
+        let id = utils::mix_seed(utils::mix_seed(chunk, TREE_ID_SALT), self.index as u64);
+        NetworkObject::new_static(id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TreeFelled {
+    pub id: TreeId,
+    /// Horizontal direction the tree falls in.
+    pub direction: Vec2,
+}
+
+/// The trees of a chunk that have been cut down.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChunkDiff {
+    pub chunk: IVec2,
+    pub felled_trees: Vec<u32>,
+}
+
+/// Trees that were cut down, per chunk. This is the source of truth on the
+/// server. Clients fill it in from `ChunkDiff`s and `TreeFelled`s, so they can
+/// remove trees again when a chunk is reloaded.
+#[derive(Resource, Default)]
+pub struct FelledTrees(HashMap<IVec2, HashSet<u32>>);
+
+impl FelledTrees {
+    pub fn insert(&mut self, id: TreeId) {
+        self.0.entry(id.chunk).or_default().insert(id.index);
+    }
+
+    pub fn contains(&self, id: &TreeId) -> bool {
+        self.0
+            .get(&id.chunk)
+            .is_some_and(|trees| trees.contains(&id.index))
+    }
+
+    pub fn diff(&self, chunk: IVec2) -> ChunkDiff {
+        ChunkDiff {
+            chunk,
+            felled_trees: self
+                .0
+                .get(&chunk)
+                .map(|trees| trees.iter().copied().collect())
+                .unwrap_or_default(),
+        }
     }
 }
 
 #[derive(Component)]
 pub struct Tree {
     tree_type: TreeType,
+    pub id: TreeId,
 }
 
 impl Tree {
-    pub fn rand(seed: u64) -> Self {
+    pub fn rand(id: TreeId, seed: u64) -> Self {
         let tree_type = match seed % 9 {
             0 => TreeType::Default,
             1 => TreeType::Cone,
@@ -29,8 +151,89 @@ impl Tree {
             8 => TreeType::Detailed,
             _ => panic!("seed mod 9 not < 8"),
         };
-        Self { tree_type }
+        Self { tree_type, id }
+    }
+}
+
+/// Spawns a tree as a child of its chunk. `transform` is local to the chunk.
+pub fn spawn_tree(parent: &mut ChildBuilder, tree: Tree, transform: Transform) {
+    parent.spawn((
+        tree.id.network_object(),
+        Health::new(TREE_HEALTH),
+        tree,
+        SpatialBundle::from_transform(transform),
+    ));
+}
+
+/// The collider of a tree. Shots that hit it hit the tree.
+#[derive(Component)]
+pub struct TreeCollider {
+    pub tree: Entity,
+}
+
+/// A tree that was cut down and is tipping over. It's removed once it hits the
+/// ground.
+#[derive(Component)]
+pub struct Falling {
+    timer: Timer,
+    /// The tree rotates around this axis (local to the chunk) at its base.
+    axis: Vec3,
+    upright: Quat,
+}
+
+impl Falling {
+    fn new(direction: Vec2, upright: Quat) -> Self {
+        let direction = Vec3::new(direction.x, 0.0, direction.y);
+        Self {
+            timer: Timer::new(FALL_DURATION, TimerMode::Once),
+            axis: Vec3::Y.cross(direction).normalize_or_zero(),
+            upright,
+        }
+    }
+}
+
+/// Knocks a tree over. Its collider goes away straight away, so it doesn't
+/// block anything while it falls.
+fn start_falling(
+    commands: &mut Commands,
+    tree: Entity,
+    transform: &Transform,
+    direction: Vec2,
+    children: &Query<&Children>,
+    colliders: &Query<(), With<TreeCollider>>,
+) {
+    for child in children.iter_descendants(tree) {
+        if colliders.contains(child) {
+            commands.entity(child).despawn_recursive();
+        }
     }
+    commands
+        .entity(tree)
+        .insert(Falling::new(direction, transform.rotation));
+}
+
+/// Shapes are in world units and eyeballed from the models at `TREE_SCALE`.
+struct TreeShape {
+    trunk_height: f32,
+    trunk_radius: f32,
+    canopy: Canopy,
+    /// Height of the bottom of the canopy.
+    canopy_base: f32,
+}
+
+enum Canopy {
+    Ball {
+        radius: f32,
+    },
+    Cone {
+        height: f32,
+        radius: f32,
+    },
+    /// A flat, wide canopy.
+    Disc {
+        height: f32,
+        radius: f32,
+    },
 }
 
 pub enum TreeType {
@@ -77,8 +280,81 @@ impl TreeType {
         mesh_handle.get_or_insert_with(load_mesh).clone()
     }
 
+    fn shape(&self) -> TreeShape {
+        let (trunk_height, trunk_radius, canopy_base, canopy) = match self {
+            Self::Default => (2.0, 0.2, 1.6, Canopy::Ball { radius: 1.2 }),
+            Self::Cone => (
+                1.2,
+                0.2,
+                1.0,
+                Canopy::Cone {
+                    height: 3.2,
+                    radius: 1.2,
+                },
+            ),
+            Self::Fat => (1.2, 0.3, 1.0, Canopy::Ball { radius: 1.6 }),
+            Self::Oak => (2.0, 0.25, 1.8, Canopy::Ball { radius: 1.5 }),
+            Self::Simple => (
+                2.0,
+                0.2,
+                1.8,
+                Canopy::Cone {
+                    height: 2.4,
+                    radius: 1.0,
+                },
+            ),
+            Self::Small => (1.0, 0.15, 0.8, Canopy::Ball { radius: 0.8 }),
+            Self::Thin => (
+                2.4,
+                0.15,
+                2.0,
+                Canopy::Cone {
+                    height: 3.0,
+                    radius: 0.6,
+                },
+            ),
+            Self::Plateau => (
+                2.4,
+                0.2,
+                2.6,
+                Canopy::Disc {
+                    height: 0.8,
+                    radius: 1.5,
+                },
+            ),
+            Self::Detailed => (2.0, 0.25, 1.8, Canopy::Ball { radius: 1.4 }),
+        };
+        TreeShape {
+            trunk_height,
+            trunk_radius,
+            canopy,
+            canopy_base,
+        }
+    }
+
+    /// A trunk and a canopy, with the tree's origin at the bottom of the trunk.
     fn get_collider(&self) -> Collider {
-        Collider::cylinder(2.0, 0.2)
+        let shape = self.shape();
+        let trunk = (
+            Vec3::Y * shape.trunk_height / 2.0,
+            Quat::IDENTITY,
+            Collider::cylinder(shape.trunk_height / 2.0, shape.trunk_radius),
+        );
+        let canopy = match shape.canopy {
+            Canopy::Ball { radius } => (
+                Vec3::Y * (shape.canopy_base + radius),
+                Collider::ball(radius),
+            ),
+            Canopy::Cone { height, radius } => (
+                Vec3::Y * (shape.canopy_base + height / 2.0),
+                Collider::cone(height / 2.0, radius),
+            ),
+            Canopy::Disc { height, radius } => (
+                Vec3::Y * (shape.canopy_base + height / 2.0),
+                Collider::cylinder(height / 2.0, radius),
+            ),
+        };
+        Collider::compound(vec![trunk, (canopy.0, Quat::IDENTITY, canopy.1)])
     }
 }
 
@@ -104,19 +380,178 @@ fn spawn_trees(
     mut commands: Commands,
 ) {
     for (tree, entity) in new_trees.iter() {
-        if let Some(mut entity) = commands.get_entity(entity) {
-            entity.with_children(|parent| {
+        if let Some(mut entity_commands) = commands.get_entity(entity) {
+            entity_commands.with_children(|parent| {
                 parent.spawn(SceneBundle {
                     scene: tree.tree_type.to_mesh(&mut tree_meshes, &asset_server),
-                    transform: Transform::default().with_scale(Vec3::splat(4.0)),
+                    transform: Transform::default().with_scale(Vec3::splat(TREE_SCALE)),
                     ..Default::default()
                 });
                 parent.spawn((
                     RigidBody::Fixed,
                     tree.tree_type.get_collider(),
-                    SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.5, 0.0)),
+                    TreeCollider { tree: entity },
+                    SpatialBundle::default(),
                 ));
             });
         }
     }
 }
+
+/// Tips falling trees over, speeding up as they go, and removes them when
+/// they hit the ground.
+fn fall_trees(
+    mut trees: Query<(Entity, &mut Transform, &mut Falling)>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut falling) in trees.iter_mut() {
+        falling.timer.tick(time.delta());
+        let t = falling.timer.fraction();
+        let angle = FRAC_PI_2 * t * t;
+        transform.rotation = Quat::from_axis_angle(falling.axis, angle) * falling.upright;
+        if falling.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Removes trees that are known to be cut down when their chunk is loaded.
+fn remove_felled_trees(
+    trees: Query<(Entity, &Tree), Added<Tree>>,
+    felled: Res<FelledTrees>,
+    mut commands: Commands,
+) {
+    for (entity, tree) in trees.iter() {
+        if felled.contains(&tree.id) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Knocks over trees that were killed, away from whoever killed them, and
+/// tells every client.
+fn fell_killed_trees(
+    mut kills: EventReader<Killed>,
+    trees: Query<(Entity, &NetworkObject, &Tree, &Transform, &GlobalTransform)>,
+    killers: Query<(&NetworkObject, &GlobalTransform), Without<Tree>>,
+    children: Query<&Children>,
+    colliders: Query<(), With<TreeCollider>>,
+    mut felled: ResMut<FelledTrees>,
+    mut server: ResMut<RenetServer>,
+    mut commands: Commands,
+) {
+    for kill in kills.read() {
+        let Some((entity, _, tree, transform, global_t)) = trees
+            .iter()
+            .find(|(_, net_obj, ..)| **net_obj == kill.victim)
+        else {
+            continue;
+        };
+        let direction = killers
+            .iter()
+            .find(|(net_obj, _)| **net_obj == kill.killer)
+            .and_then(|(_, killer_t)| {
+                (global_t.translation() - killer_t.translation())
+                    .xz()
+                    .try_normalize()
+            })
+            .unwrap_or(Vec2::X);
+
+        felled.insert(tree.id);
+        start_falling(
+            &mut commands,
+            entity,
+            transform,
+            direction,
+            &children,
+            &colliders,
+        );
+        let message = ReliableMessageFromServer::TreeFelled(TreeFelled {
+            id: tree.id,
+            direction,
+        });
+        let bytes = bincode::serialize(&message).unwrap();
+        server.broadcast_message(DefaultChannel::ReliableUnordered, bytes);
+    }
+}
+
+/// Answers clients asking which trees of a chunk are gone.
+fn send_chunk_diffs(
+    reader: Res<MessageReaderOnServer>,
+    felled: Res<FelledTrees>,
+    mut server: ResMut<RenetServer>,
+) {
+    for (client_id, msg) in reader.reliable_messages() {
+        let ReliableMessageFromClient::RequestChunkDiff(chunk) = msg else {
+            continue;
+        };
+        let message = ReliableMessageFromServer::ChunkDiff(felled.diff(*chunk));
+        let bytes = bincode::serialize(&message).unwrap();
+        server.send_message(*client_id, DefaultChannel::ReliableUnordered, bytes);
+    }
+}
+
+/// Asks the server which trees are gone the first time a chunk is loaded.
+/// After that, `TreeFelled` keeps the client up to date.
+fn request_chunk_diffs(
+    chunks: Query<&Chunk<TerrainChunkData>, Added<Chunk<TerrainChunkData>>>,
+    mut requested: Local<HashSet<IVec2>>,
+    mut client: ResMut<RenetClient>,
+) {
+    // A new client means a new connection, which knows nothing yet.
+    if client.is_added() {
+        requested.clear();
+    }
+    for chunk in chunks.iter() {
+        if requested.insert(chunk.meta.position) {
+            let message = ReliableMessageFromClient::RequestChunkDiff(chunk.meta.position);
+            let bytes = bincode::serialize(&message).unwrap();
+            client.send_message(DefaultChannel::ReliableUnordered, bytes);
+        }
+    }
+}
+
+fn recv_tree_messages(
+    reader: Res<MessageReaderOnClient>,
+    trees: Query<(Entity, &Tree, &Transform), Without<Falling>>,
+    children: Query<&Children>,
+    colliders: Query<(), With<TreeCollider>>,
+    mut felled: ResMut<FelledTrees>,
+    mut commands: Commands,
+) {
+    for msg in reader.reliable_messages() {
+        match msg {
+            ReliableMessageFromServer::TreeFelled(TreeFelled { id, direction }) => {
+                felled.insert(*id);
+                if let Some((entity, _, transform)) =
+                    trees.iter().find(|(_, tree, _)| tree.id == *id)
+                {
+                    start_falling(
+                        &mut commands,
+                        entity,
+                        transform,
+                        *direction,
+                        &children,
+                        &colliders,
+                    );
+                }
+            }
+            // These trees were cut down before the chunk was loaded, so
+            // they're removed without falling.
+            ReliableMessageFromServer::ChunkDiff(diff) => {
+                for index in diff.felled_trees.iter() {
+                    let id = TreeId {
+                        chunk: diff.chunk,
+                        index: *index,
+                    };
+                    felled.insert(id);
+                    if let Some((entity, ..)) = trees.iter().find(|(_, tree, _)| tree.id == id) {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
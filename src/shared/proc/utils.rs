@@ -1,5 +1,7 @@
 use bevy::{ecs::system::RunSystemOnce, prelude::*};
-use bevy_rapier3d::prelude::*;
+use noise::NoiseFn;
+
+use super::terrain::Terrain;
 
 pub struct ProcUtilsPlugin;
 
@@ -42,40 +44,48 @@ impl SnapToFloor {
     }
 }
 
+/// Moves entities onto the terrain using `Terrain::height_at`, so
+/// it works even if the chunk under the entity isn't loaded. Events are kept
+/// until the entity and the terrain exist.
 fn snap_to_floor(
-    context: Res<RapierContext>,
+    terrain: Option<Res<Terrain>>,
     mut events: ResMut<Events<SnapToFloor>>,
-    mut transforms: Query<(&GlobalTransform, &mut Transform, &mut Visibility)>,
+    mut transforms: Query<(
+        &GlobalTransform,
+        &mut Transform,
+        &mut Visibility,
+        Option<&Parent>,
+    )>,
 ) {
     let mut to_add: Vec<SnapToFloor> = vec![];
+    let Some(terrain) = terrain else {
+        return;
+    };
     for event in events.drain() {
-        let Ok((global_t, mut t, mut visibility)) = transforms.get_mut(event.entity_to_move) else {
+        let Ok((global_t, mut t, mut visibility, parent)) =
+            transforms.get_mut(event.entity_to_move)
+        else {
             to_add.push(event);
             continue;
         };
 
-        let global_pos = global_t.translation();
-        let start = Vec3::new(global_pos.x, 100.0, global_pos.z);
-        let intersect = context
-            .cast_ray_and_get_normal(
-                start,
-                -Vec3::Y,
-                150.0,
-                false,
-                QueryFilter::default().exclude_collider(event.entity_to_move),
-            )
-            .map(|v| v.1);
-        let Some(intersect) = intersect else {
-            warn!("no intersect for snap to floor");
-            to_add.push(event);
-            continue;
+        // The global transform is only propagated at the end of the frame, so
+        // it is stale for root entities that were moved this frame (e.g. a respawn).
+        let global_pos = match parent {
+            Some(_) => global_t.translation(),
+            None => t.translation,
         };
+        let floor = Vec3::new(
+            global_pos.x,
+            terrain.height_at(global_pos.x, global_pos.z),
+            global_pos.z,
+        );
 
         if event.set_visible {
             *visibility = Visibility::Visible;
         }
 
-        let diff = -global_pos + intersect.point;
+        let diff = -global_pos + floor;
         t.translation += diff;
         t.translation += Vec3::Y + event.y_offset;
     }
@@ -103,8 +113,29 @@ pub fn generate_chunks_around(position: IVec2, radius: i32) -> Vec<(IVec2, i32)>
     result
 }
 
+#[derive(Clone)]
 pub struct NoiseLayer<N> {
     pub noise: N,
     pub amplitude: f64,
     pub frequency: f64,
 }
+
+/// Sums the layers of noise at a world position.
+pub fn layered_noise<N: NoiseFn<f64, 2>>(
+    layers: &[NoiseLayer<N>],
+    world_x: f64,
+    world_z: f64,
+) -> f32 {
+    let mut value = 0.0f32;
+    for NoiseLayer {
+        noise,
+        amplitude,
+        frequency,
+    } in layers
+    {
+        let sample_x = world_x * *frequency;
+        let sample_z = world_z * *frequency;
+        value += noise.get([sample_x, sample_z]) as f32 * *amplitude as f32;
+    }
+    value
+}
@@ -0,0 +1,66 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+use bevy_rapier3d::prelude::*;
+
+/// Collision group of water volumes, so they can be looked for, or ignored, by
+/// queries.
+pub const WATER_GROUP: Group = Group::GROUP_2;
+
+/// A sensor volume filled with water, spanning a chunk from its lowest point up
+/// to the water level.
+#[derive(Component)]
+pub struct Water;
+
+/// Water surface. Gets lighter where it meets the terrain, using the depth
+/// prepass, so cameras need a `DepthPrepass` for it to look right.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone, Default)]
+pub struct WaterMaterial {}
+
+impl Material for WaterMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/water.wgsl".into()
+    }
+
+    /// Blended materials are left out of the prepass, so the water doesn't
+    /// find its own depth.
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+/// Collision groups for queries that should go straight through water.
+pub fn ignore_water() -> CollisionGroups {
+    CollisionGroups::new(Group::ALL, !WATER_GROUP)
+}
+
+/// Whether a point is under water. Only works for chunks that have colliders.
+/// `exclude` is left out of the check, e.g. the collider of whoever is asking.
+pub fn is_in_water(context: &RapierContext, point: Vec3, exclude: Entity) -> bool {
+    let mut in_water = false;
+    let is_water = |entity| is_water_collider(context, entity);
+    context.intersections_with_point(
+        point,
+        QueryFilter::default()
+            .exclude_collider(exclude)
+            .predicate(&is_water),
+        |_| {
+            in_water = true;
+            false
+        },
+    );
+    in_water
+}
+
+/// Other colliders are members of every group, the water group included, so a
+/// group filter alone can't tell water apart. Water is only in its own group.
+fn is_water_collider(context: &RapierContext, entity: Entity) -> bool {
+    context
+        .entity2collider()
+        .get(&entity)
+        .and_then(|handle| context.colliders.get(*handle))
+        .is_some_and(|collider| {
+            collider.collision_groups().memberships.bits() == WATER_GROUP.bits()
+        })
+}
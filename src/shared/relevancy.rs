@@ -0,0 +1,170 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer, ServerEvent};
+
+use crate::{
+    message::{
+        server::{ReliableMessageFromServer, Spawn},
+        spawn::SpawnSerializers,
+    },
+    server::{ClientNetworkObjectMap, PlayerWantsUpdates},
+    shared::{
+        objects::{NetworkObject, Replicated},
+        proc::terrain::Terrain,
+        tick::Tick,
+        GameLogic,
+    },
+};
+
+/// Entities this many chunks or less away from a client's player are
+/// replicated to it.
+pub const RELEVANCY_RADIUS: i32 = 3;
+
+/// Replicates entities only to the clients near them. Entities are
+/// spawned on a client when they come within its radius and despawned when
+/// they leave it, and farther entities are updated less often. Spawns and
+/// despawns go over an ordered channel, so an entity that leaves and comes
+/// back can't end up despawned on the client.
+pub struct RelevancyPlugin;
+
+impl Plugin for RelevancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Relevancy>();
+        app.init_resource::<SpawnSerializers>();
+        app.add_systems(
+            FixedUpdate,
+            (track_clients, update_relevancy)
+                .chain()
+                .in_set(GameLogic::Spawn),
+        );
+    }
+}
+
+#[derive(Default)]
+struct ClientRelevancy {
+    /// The client's own player, which it always knows about.
+    player: Option<NetworkObject>,
+    /// Entities spawned on the client, with their distance in chunks.
+    relevant: HashMap<NetworkObject, i32>,
+}
+
+/// Which entities each client knows about.
+#[derive(Resource, Default)]
+pub struct Relevancy {
+    clients: HashMap<ClientId, ClientRelevancy>,
+}
+
+/// Ticks between updates of an entity that's `distance` chunks away.
+fn update_interval(distance: i32) -> u64 {
+    1 << (distance - 1).clamp(0, 8)
+}
+
+impl Relevancy {
+    /// Whether an update to `net_obj` should be sent to `client_id` this
+    /// tick. Clients always get updates to their own player.
+    pub fn should_send(&self, client_id: ClientId, net_obj: &NetworkObject, tick: Tick) -> bool {
+        let Some(client) = self.clients.get(&client_id) else {
+            return false;
+        };
+        if client.player.as_ref() == Some(net_obj) {
+            return true;
+        }
+        client
+            .relevant
+            .get(net_obj)
+            .is_some_and(|distance| tick.get() % update_interval(*distance) == 0)
+    }
+
+    /// The clients that should get an update to `net_obj` this tick.
+    pub fn clients_for<'a>(
+        &'a self,
+        net_obj: &'a NetworkObject,
+        tick: Tick,
+    ) -> impl Iterator<Item = ClientId> + 'a {
+        self.clients
+            .keys()
+            .copied()
+            .filter(move |client_id| self.should_send(*client_id, net_obj, tick))
+    }
+}
+
+/// Clients start getting entities once they're ready for updates.
+fn track_clients(
+    mut relevancy: ResMut<Relevancy>,
+    mut player_load: EventReader<PlayerWantsUpdates>,
+    mut server_events: EventReader<ServerEvent>,
+) {
+    for load in player_load.read() {
+        relevancy.clients.entry(load.client_id).or_default();
+    }
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            relevancy.clients.remove(client_id);
+        }
+    }
+}
+
+/// Spawns entities on clients they came near, and despawns them on clients
+/// they left. Clients that just became ready get everything near them, which
+/// is how late joiners catch up.
+fn update_relevancy(world: &mut World) {
+    let tick = *world.resource::<Tick>();
+    let mut replicated =
+        world.query_filtered::<(Entity, &NetworkObject, &Transform), With<Replicated>>();
+    let terrain = world.resource::<Terrain>();
+    let chunks: HashMap<NetworkObject, (Entity, IVec2)> = replicated
+        .iter(world)
+        .map(|(entity, net_obj, transform)| {
+            let chunk = terrain.world_position_to_chunk(transform.translation);
+            (net_obj.clone(), (entity, chunk))
+        })
+        .collect();
+
+    world.resource_scope(|world, mut relevancy: Mut<Relevancy>| {
+        world.resource_scope(|world, mut server: Mut<RenetServer>| {
+            let client_map = world.resource::<ClientNetworkObjectMap>();
+            let serializers = world.resource::<SpawnSerializers>();
+            for (client_id, client) in relevancy.clients.iter_mut() {
+                client.player = client_map.client_to_net_obj.get(client_id).cloned();
+                let center = client.player.as_ref().and_then(|player| chunks.get(player));
+                let mut relevant = HashMap::new();
+                if let Some((_, center)) = center {
+                    for (net_obj, (_, chunk)) in chunks.iter() {
+                        let distance = (*chunk - *center).abs().max_element();
+                        if distance <= RELEVANCY_RADIUS && client.player.as_ref() != Some(net_obj) {
+                            relevant.insert(net_obj.clone(), distance);
+                        }
+                    }
+                }
+
+                let entered = relevant
+                    .keys()
+                    .filter(|net_obj| !client.relevant.contains_key(*net_obj));
+                for net_obj in entered {
+                    let (entity, _) = chunks[net_obj];
+                    let Some(net_spawn) = serializers.serialize(world.entity(entity)) else {
+                        warn!("no spawn serializer for {net_obj:?}");
+                        continue;
+                    };
+                    let message = ReliableMessageFromServer::Spawn(Spawn {
+                        net_obj: net_obj.clone(),
+                        net_spawn,
+                        tick,
+                    });
+                    let bytes = bincode::serialize(&message).unwrap();
+                    server.send_message(*client_id, DefaultChannel::ReliableOrdered, bytes);
+                }
+                // Entities that are gone altogether were already despawned
+                // everywhere.
+                let left = client.relevant.keys().filter(|net_obj| {
+                    !relevant.contains_key(*net_obj) && chunks.contains_key(*net_obj)
+                });
+                for net_obj in left {
+                    let message = ReliableMessageFromServer::Despawn(net_obj.clone());
+                    let bytes = bincode::serialize(&message).unwrap();
+                    server.send_message(*client_id, DefaultChannel::ReliableOrdered, bytes);
+                }
+                client.relevant = relevant;
+            }
+        });
+    });
+}
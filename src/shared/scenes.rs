@@ -2,15 +2,19 @@ use std::f32::consts::PI;
 
 use bevy::{ecs::system::RunSystemOnce, prelude::*};
 
-use super::proc::grassy_desert::GrassyDesertTerrain;
+use super::proc::{terrain::Terrain, WorldSeed};
 
 pub fn setup_scene_1(world: &mut World) {
     world.run_system_once(spawn_world_model);
     world.run_system_once(spawn_lights);
 }
 
-fn spawn_world_model(mut commands: Commands) {
-    commands.insert_resource(GrassyDesertTerrain::new());
+/// Clients don't know the seed until the server sends it, so the terrain is
+/// only created here if the seed is already known.
+fn spawn_world_model(seed: Option<Res<WorldSeed>>, mut commands: Commands) {
+    if let Some(seed) = seed {
+        commands.insert_resource(Terrain::new(seed.0));
+    }
 }
 
 fn spawn_lights(mut commands: Commands) {
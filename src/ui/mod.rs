@@ -1,6 +1,9 @@
 use bevy::{prelude::*, render::view::RenderLayers};
 
-use crate::shared::render::{UI_CAMERA_ORDER, UI_RENDER_LAYER};
+use crate::shared::{
+    render::{UI_CAMERA_ORDER, UI_RENDER_LAYER},
+    AppState,
+};
 
 pub struct UIPlugin {
     pub is_server: bool,
@@ -9,25 +12,29 @@ pub struct UIPlugin {
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         if !self.is_server {
-            app.add_systems(FixedUpdate, (spawn_crosshair, health::spawn_health_bar));
-            app.add_systems(Update, health::draw_local_health_bar);
+            // Everything is despawned when leaving a game, so the HUD is
+            // spawned again every time one is joined.
+            app.add_systems(
+                OnEnter(AppState::InGame),
+                (
+                    spawn_crosshair,
+                    health::spawn_health_bar,
+                    game_mode::spawn_game_mode_text,
+                ),
+            );
+            app.add_systems(
+                Update,
+                (
+                    health::draw_local_health_bar,
+                    health::draw_local_stamina_bar,
+                    game_mode::draw_game_mode_text,
+                ),
+            );
         }
     }
 }
 
-#[derive(Default)]
-struct IsSpawned(bool);
-
-fn spawn_crosshair(
-    mut commands: Commands,
-    mut is_spawned: Local<IsSpawned>,
-    asset_server: Res<AssetServer>,
-) {
-    if is_spawned.0 {
-        return;
-    }
-    is_spawned.0 = true;
-
+fn spawn_crosshair(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn((
         Camera2dBundle {
             camera: Camera {
@@ -73,34 +80,34 @@ fn spawn_crosshair(
 
 pub mod health {
     use bevy::{
-        color::palettes::css::{GREEN, GREY},
+        color::palettes::css::{GOLD, GREEN, GREY},
         prelude::*,
         render::view::RenderLayers,
     };
 
     use crate::shared::{
-        objects::{health::Health, player::LocalPlayerTag},
+        objects::{
+            health::Health,
+            player::{LocalPlayerTag, Player},
+        },
         render::UI_RENDER_LAYER,
     };
 
     #[derive(Component)]
     pub struct LocalPlayerHealthBar;
 
-    #[derive(Default)]
-    pub struct IsSpawned(bool);
-
-    pub fn spawn_health_bar(mut is_spawned: Local<IsSpawned>, mut commands: Commands) {
-        if is_spawned.0 {
-            return;
-        }
-        is_spawned.0 = true;
+    /// The fill of the stamina bar, under the health bar.
+    #[derive(Component)]
+    pub struct LocalPlayerStaminaBar;
 
+    pub fn spawn_health_bar(mut commands: Commands) {
         commands
             .spawn((
                 NodeBundle {
                     style: Style {
                         width: Val::Percent(100.0),
                         position_type: PositionType::Absolute,
+                        flex_direction: FlexDirection::Column,
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         margin: UiRect::top(Val::Percent(5.0)),
@@ -140,9 +147,51 @@ pub mod health {
                             RenderLayers::layer(UI_RENDER_LAYER),
                         ));
                     });
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Px(500.0),
+                                height: Val::Px(6.0),
+                                margin: UiRect::top(Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: BackgroundColor(GREY.into()),
+                            ..default()
+                        },
+                        RenderLayers::layer(UI_RENDER_LAYER),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(100.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(GOLD.into()),
+                                ..default()
+                            },
+                            RenderLayers::layer(UI_RENDER_LAYER),
+                            LocalPlayerStaminaBar,
+                        ));
+                    });
             });
     }
 
+    pub fn draw_local_stamina_bar(
+        player: Query<&Player, With<LocalPlayerTag>>,
+        mut stamina_bar: Query<&mut Style, With<LocalPlayerStaminaBar>>,
+    ) {
+        let Ok(player) = player.get_single() else {
+            return;
+        };
+        let Ok(mut style) = stamina_bar.get_single_mut() else {
+            return;
+        };
+        style.width = Val::Percent(100.0 * player.stamina_fraction());
+    }
+
     pub fn draw_local_health_bar(
         health: Query<&Health, With<LocalPlayerTag>>,
         health_bar: Query<&Children, With<LocalPlayerHealthBar>>,
@@ -166,3 +215,98 @@ pub mod health {
         health_bar_style.width = Val::Percent(100.0 * health.current / health.max);
     }
 }
+
+pub mod game_mode {
+    use bevy::{prelude::*, render::view::RenderLayers};
+
+    use crate::shared::{
+        game_mode::{GameModeState, Scorer},
+        objects::player::LocalPlayer,
+        render::UI_RENDER_LAYER,
+    };
+
+    const FONT_SIZE: f32 = 20.0;
+
+    #[derive(Component)]
+    pub struct GameModeText;
+
+    pub fn spawn_game_mode_text(mut commands: Commands) {
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(5.0),
+                        right: Val::Px(5.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                RenderLayers::layer(UI_RENDER_LAYER),
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: FONT_SIZE,
+                            ..default()
+                        },
+                    )
+                    .with_text_justify(JustifyText::Right),
+                    RenderLayers::layer(UI_RENDER_LAYER),
+                    GameModeText,
+                ));
+            });
+    }
+
+    fn scorer_name(scorer: &Scorer, local_player: Option<&LocalPlayer>) -> String {
+        match scorer {
+            Scorer::Team(team) => format!("{} team", team.name()),
+            Scorer::Player(net_obj) => match local_player {
+                Some(local) if local.0 == *net_obj => "You".to_string(),
+                _ => format!("{:?}", net_obj),
+            },
+        }
+    }
+
+    pub fn draw_game_mode_text(
+        state: Option<Res<GameModeState>>,
+        local_player: Option<Res<LocalPlayer>>,
+        mut text: Query<&mut Text, With<GameModeText>>,
+    ) {
+        let Some(state) = state else {
+            return;
+        };
+        let Ok(mut text) = text.get_single_mut() else {
+            return;
+        };
+        let local_player = local_player.as_deref();
+
+        let seconds = state.remaining.as_secs();
+        let mut content = format!(
+            "{} - {} {}:{:02}\nFirst to {}",
+            state.kind.name(),
+            state.phase.name(),
+            seconds / 60,
+            seconds % 60,
+            state.score_limit,
+        );
+        if let Some(waves) = &state.waves {
+            content += &format!(
+                "\nWave {} - {} enemies left",
+                waves.wave, waves.remaining_enemies
+            );
+            if let Some(next_wave_in) = waves.next_wave_in {
+                content += &format!("\nNext wave in {}s", next_wave_in.as_secs());
+            }
+        }
+        for (scorer, score) in state.scores.iter().take(5) {
+            content += &format!("\n{}: {}", scorer_name(scorer, local_player), score);
+        }
+        if let Some(winner) = &state.winner {
+            content += &format!("\n{} won the round!", scorer_name(winner, local_player));
+        }
+        text.sections[0].value = content;
+    }
+}
@@ -261,6 +261,28 @@ pub fn create_rng_from_seed(seed: [u8; 32]) -> impl Rng {
     ChaCha20Rng::from_seed(seed)
 }
 
+/// Derives a new seed from `seed` and `value` (SplitMix64). Different values
+/// give unrelated seeds, so one world seed can seed many generators.
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce5_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Seed for RNG that is local to a chunk, derived from the world seed and the
+/// chunk's coordinates.
+pub fn chunk_seed(world_seed: u64, chunk: IVec2) -> [u8; 32] {
+    let chunk_value = ((chunk.x as u32 as u64) << 32) | chunk.y as u32 as u64;
+    let chunk_seed = mix_seed(world_seed, chunk_value);
+    let mut seed = [0; 32];
+    for (i, bytes) in seed.chunks_exact_mut(8).enumerate() {
+        bytes.copy_from_slice(&mix_seed(chunk_seed, i as u64).to_le_bytes());
+    }
+    seed
+}
+
 /// Basically a bad hash.
 pub fn coords_to_u64(position: Vec2) -> u64 {
     let x = position.x;